@@ -0,0 +1,15 @@
+create table snapshot
+(
+    id               integer primary key autoincrement not null,
+    tag              text    not null,
+    taken_at         bigint  not null,
+    apps             text    not null,
+    authored         bigint  not null,
+    authored_on_disk bigint  not null,
+    dht              bigint  not null,
+    dht_on_disk      bigint  not null,
+    cache            bigint  not null,
+    cache_on_disk    bigint  not null,
+    dna_hash         text
+);
+create index snapshot_tag_taken_at on snapshot (tag, taken_at);
@@ -2,20 +2,45 @@ pub(crate) mod admin;
 pub(crate) mod agent_tag;
 pub(crate) mod conductor_tag;
 pub(crate) mod explore;
+pub(crate) mod health;
 pub(crate) mod init;
-
-use clap::{Args, Parser, Subcommand};
-use holochain_zome_types::prelude::AgentPubKeyB64;
-use std::net::IpAddr;
+pub(crate) mod key;
+pub(crate) mod metrics;
+pub(crate) mod report;
+#[cfg(unix)]
+pub(crate) mod unlock;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hc_ops::retrieve::{
+    DEFAULT_COVERAGE_BUCKETS, DEFAULT_RETRY_THRESHOLD, DbOpType, ScanFilter, ValidationStatus,
+};
+use holo_hash::{ActionHashB64, AnyDhtHashB64, AnyLinkableHashB64, DnaHashB64, EntryHashB64};
+use holochain_zome_types::prelude::{AgentPubKeyB64, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
+    /// The format to write command output in
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tables and pretty printed JSON, with progress messages
+    Table,
+    /// A single JSON document
+    Json,
+    /// One JSON document per line
+    Ndjson,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Tag a Holochain conductor
@@ -35,6 +60,25 @@ pub enum Commands {
 
     /// Compare data from another Holochain conductor
     Compare(CompareArgs),
+
+    /// Export a cell's databases to a portable archive
+    Export(ExportArgs),
+
+    /// Check the health of a conductor, exiting with 0 for OK, 1 for warning or 2 for critical
+    Health(HealthArgs),
+
+    /// Serve conductor and database metrics in the OpenMetrics text format
+    ServeMetrics(ServeMetricsArgs),
+
+    /// Report on data recorded in the hc-ops state database
+    Report(ReportArgs),
+
+    /// Unlock a data directory once, and hold its database key for other commands until a timeout
+    #[cfg(unix)]
+    Unlock(UnlockArgs),
+
+    /// Inspect and use the key that encrypts a conductor's databases
+    Key(KeyArgs),
 }
 
 #[derive(Debug, Args)]
@@ -151,6 +195,10 @@ pub enum AdminCommands {
     StorageInfo {
         /// Get storage info for a single app
         app_id: Option<String>,
+
+        /// Record the storage info for all apps in the hc-ops state database, for `report storage-trend`
+        #[arg(long)]
+        record: bool,
     },
     /// Get network metrics per DNA.
     NetworkMetrics {
@@ -198,15 +246,267 @@ pub enum InitCommands {
 #[derive(Debug, Args)]
 pub struct ExploreArgs {
     /// The tag to use when connecting to Holochain
-    #[arg(long, short)]
-    pub tag: String,
+    #[arg(long, short, required_unless_present = "offline")]
+    pub tag: Option<String>,
+
+    /// Find cells by scanning the data directory, rather than asking a running conductor
+    #[arg(long, conflicts_with_all = ["tag", "app", "role", "clone_id"])]
+    pub offline: bool,
 
     /// The origin header to use in the request
     #[arg(long, default_value = "hc-ops")]
     pub origin: String,
 
+    /// The app to explore, rather than being prompted to select one
+    #[arg(long)]
+    pub app: Option<String>,
+
+    /// The role to explore, which selects the provisioned cell unless `--clone-id` is also given
+    #[arg(long)]
+    pub role: Option<String>,
+
+    /// The clone cell to explore, e.g. `my_role.0`
+    #[arg(long)]
+    pub clone_id: Option<String>,
+
+    /// The DNA to explore, rather than being prompted to select one
+    #[arg(long)]
+    pub dna: Option<DnaHashB64>,
+
+    /// Only explore cells of this agent, whose authored database is opened
+    #[arg(long)]
+    pub agent: Option<AgentPubKeyB64>,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// Copy a snapshot of each database to the temp directory, and explore the copies
+    ///
+    /// The conductor's databases are only read while copying, so exploring can't hold up the
+    /// conductor. Changes made after the snapshot is taken aren't seen.
+    #[arg(long)]
+    pub snapshot: bool,
+
     /// The path to the Holochain data directory
     pub data_root_path: PathBuf,
+
+    /// Run a single explore operation, rather than starting the interactive explorer
+    #[command(subcommand)]
+    pub command: Option<ExploreCommands>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExploreCommands {
+    /// List agents discovered in the DHT and cache databases
+    WhoIsHere,
+    /// View an agent chain
+    #[command(arg_required_else_help = true)]
+    AgentChain {
+        /// The agent whose chain should be shown
+        agent: AgentPubKeyB64,
+
+        /// Include items from the cache database
+        #[arg(long)]
+        include_cache: bool,
+    },
+    /// View this agent's chain
+    SelfChain,
+    /// Check a source chain for forks, broken links, bad signatures and bad entries
+    VerifyChain {
+        /// The agent whose chain should be checked, defaults to this agent's authored chain
+        agent: Option<AgentPubKeyB64>,
+
+        /// Include items from the cache database when checking another agent's chain
+        #[arg(long)]
+        include_cache: bool,
+    },
+    /// View warrants, with the evidence actions that are held locally
+    Warrants {
+        /// Only show warrants issued against this agent
+        warrantee: Option<AgentPubKeyB64>,
+    },
+    /// View ops pending validation or integration
+    Pending,
+    /// Explain why ops are pending, rejected or abandoned
+    Diagnose {
+        /// Flag ops that have been validated at least this many times
+        #[arg(long, default_value_t = DEFAULT_RETRY_THRESHOLD)]
+        retry_threshold: u32,
+    },
+    /// View ops by action hash
+    #[command(arg_required_else_help = true)]
+    OpsByAction {
+        /// The action hash to look up
+        hash: ActionHashB64,
+    },
+    /// View ops by entry hash
+    #[command(arg_required_else_help = true)]
+    OpsByEntry {
+        /// The entry hash to look up
+        hash: EntryHashB64,
+    },
+    /// View the create, update and delete history of a record
+    #[command(arg_required_else_help = true)]
+    History {
+        /// An action hash or entry hash
+        hash: AnyDhtHashB64,
+    },
+    /// View links from a base, including deleted links
+    #[command(arg_required_else_help = true)]
+    Links {
+        /// The base hash to look up links from
+        base: AnyLinkableHashB64,
+
+        /// Only show links from this zome index
+        #[arg(long)]
+        zome_index: Option<u8>,
+
+        /// Only show links of this link type
+        #[arg(long)]
+        link_type: Option<u8>,
+
+        /// Only show links whose tag starts with this UTF-8 prefix
+        #[arg(long)]
+        tag_prefix: Option<String>,
+    },
+    /// View slice hashes
+    SliceHashes {
+        /// Also write the slice hashes to this file, for use with `hc-ops compare slice-hashes`
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// List the full time slices of an arc, with their op counts and sizes
+    #[command(arg_required_else_help = true)]
+    Slices {
+        /// The start of the DHT arc
+        arc_start: u32,
+
+        /// The end of the DHT arc
+        arc_end: u32,
+    },
+    /// View ops in a slice
+    #[command(arg_required_else_help = true)]
+    OpsInSlice {
+        /// The start of the DHT arc
+        arc_start: u32,
+
+        /// The end of the DHT arc
+        arc_end: u32,
+
+        /// The index of the time slice
+        slice_index: u64,
+    },
+    /// View how ops are spread over the DHT location ring, and which parts of it agents cover
+    Coverage {
+        /// The number of buckets to split the location ring into
+        #[arg(long, default_value_t = DEFAULT_COVERAGE_BUCKETS)]
+        buckets: u32,
+    },
+    /// Dump the authored, DHT and cache databases
+    Dump {
+        #[command(flatten)]
+        filter: ScanFilterArgs,
+    },
+    /// Print new ops, new authored actions and validation and integration progress as they happen.
+    ///
+    /// Runs until interrupted.
+    Watch {
+        /// The number of seconds between polls of the databases
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ScanFilterArgs {
+    /// Only include ops of this type, e.g. `StoreRecord`
+    #[arg(long)]
+    pub op_type: Option<DbOpType>,
+
+    /// Only include ops, actions and entries authored by this agent
+    #[arg(long)]
+    pub author: Option<AgentPubKeyB64>,
+
+    /// Only include ops and actions authored at or after this time, in RFC 3339 format
+    #[arg(long)]
+    pub since: Option<Timestamp>,
+
+    /// Only include ops and actions authored before this time, in RFC 3339 format
+    #[arg(long)]
+    pub until: Option<Timestamp>,
+
+    /// Only include ops with this validation status, e.g. `Rejected`
+    #[arg(long)]
+    pub validation_status: Option<ValidationStatus>,
+}
+
+impl From<ScanFilterArgs> for ScanFilter {
+    fn from(args: ScanFilterArgs) -> Self {
+        ScanFilter {
+            op_type: args.op_type,
+            author: args.author.map(Into::into),
+            since: args.since,
+            until: args.until,
+            validation_status: args.validation_status,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The tag to use when connecting to Holochain
+    #[arg(long, short, required_unless_present = "offline")]
+    pub tag: Option<String>,
+
+    /// Find the cell by scanning the data directory, rather than asking a running conductor
+    #[arg(long, conflicts_with_all = ["tag", "app", "role", "clone_id"])]
+    pub offline: bool,
+
+    /// The origin header to use in the request
+    #[arg(long, default_value = "hc-ops")]
+    pub origin: String,
+
+    /// The app to export from
+    #[arg(long)]
+    pub app: Option<String>,
+
+    /// The role to export, which selects the provisioned cell unless `--clone-id` is also given
+    #[arg(long)]
+    pub role: Option<String>,
+
+    /// The clone cell to export, e.g. `my_role.0`
+    #[arg(long)]
+    pub clone_id: Option<String>,
+
+    /// The DNA to export
+    #[arg(long)]
+    pub dna: Option<DnaHashB64>,
+
+    /// The agent of the cell to export, whose authored database is exported
+    #[arg(long)]
+    pub agent: Option<AgentPubKeyB64>,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// The format to write each section of the archive in
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub format: ExportFormat,
+
+    /// The path to the Holochain data directory
+    pub data_root_path: PathBuf,
+
+    /// The directory to write the archive to, which must not already contain an archive
+    pub out_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON document per line, using the same readable form as `explore`
+    Json,
+    /// Length-prefixed MessagePack, with every column of each op, which can be read back
+    Binary,
 }
 
 #[derive(Debug, Args)]
@@ -220,10 +520,350 @@ pub struct CompareArgs {
 pub enum CompareCommands {
     #[command(arg_required_else_help = true)]
     SliceHashes {
-        /// A file containing the raw printout from `hc-ops explore slice-hashes`.
+        /// A file written by `hc-ops explore slice-hashes --out`, or the table that it prints.
         our_file: PathBuf,
 
-        /// A file containing the raw printout from `hc-ops explore slice-hashes`.
+        /// A file written by `hc-ops explore slice-hashes --out`, or the table that it prints.
         their_file: PathBuf,
     },
+    /// Find the ops responsible for slice hash differences between two nodes
+    #[command(arg_required_else_help = true)]
+    Ops {
+        /// Our Holochain data directory, or an archive written by `hc-ops export --format binary`
+        ours: PathBuf,
+
+        /// Their Holochain data directory, or an archive written by `hc-ops export --format binary`
+        theirs: PathBuf,
+
+        /// The DNA to compare, required when a data directory has more than one cell
+        #[arg(long)]
+        dna: Option<DnaHashB64>,
+
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+    },
+    /// Compare slice hashes read directly from two conductors' databases
+    #[command(arg_required_else_help = true)]
+    Live(CompareLiveArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CompareLiveArgs {
+    /// The tag of our conductor
+    #[arg(long)]
+    pub ours: String,
+
+    /// Our Holochain data directory
+    #[arg(long)]
+    pub our_data_root: PathBuf,
+
+    /// The tag of their conductor, or their Holochain data directory
+    #[arg(long)]
+    pub theirs: String,
+
+    /// Their Holochain data directory, required when `--theirs` is a tag
+    #[arg(long)]
+    pub their_data_root: Option<PathBuf>,
+
+    /// The origin header to use in the request
+    #[arg(long, default_value = "hc-ops")]
+    pub origin: String,
+
+    /// The app to compare, required when our conductor has more than one app installed
+    #[arg(long)]
+    pub app: Option<String>,
+
+    /// The role to compare, which selects the provisioned cell unless `--clone-id` is also given
+    #[arg(long)]
+    pub role: Option<String>,
+
+    /// The clone cell to compare, e.g. `my_role.0`
+    #[arg(long)]
+    pub clone_id: Option<String>,
+
+    /// The DNA to compare, required when the app has more than one DNA
+    #[arg(long)]
+    pub dna: Option<DnaHashB64>,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    #[command(flatten)]
+    pub their_passphrase: TheirPassphraseArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct HealthArgs {
+    /// The tag to use when connecting to Holochain
+    #[arg(long, short)]
+    pub tag: String,
+
+    /// The origin header to use in the request
+    #[arg(long, default_value = "hc-ops")]
+    pub origin: String,
+
+    /// Warn when a DNA has fewer peers than this, not counting agents on this conductor
+    #[arg(long, default_value_t = 1)]
+    pub peers_warning: usize,
+
+    /// Critical when a DNA has fewer peers than this
+    #[arg(long, default_value_t = 0)]
+    pub peers_critical: usize,
+
+    /// Warn when there are fewer open transport connections than this
+    #[arg(long, default_value_t = 1)]
+    pub connections_warning: usize,
+
+    /// Critical when there are fewer open transport connections than this
+    #[arg(long, default_value_t = 0)]
+    pub connections_critical: usize,
+
+    /// Warn when the storage used on disk is more than this many MiB
+    #[arg(long)]
+    pub storage_warning_mib: Option<u64>,
+
+    /// Critical when the storage used on disk is more than this many MiB
+    #[arg(long)]
+    pub storage_critical_mib: Option<u64>,
+
+    /// Warn when the storage used on disk is growing by more than this many MiB per day.
+    ///
+    /// Growth is measured from the snapshots recorded with `admin storage-info --record`.
+    #[arg(long)]
+    pub storage_growth_warning_mib: Option<u64>,
+
+    /// Critical when the storage used on disk is growing by more than this many MiB per day
+    #[arg(long)]
+    pub storage_growth_critical_mib: Option<u64>,
+
+    /// How many days of recorded snapshots to measure storage growth over
+    #[arg(long, default_value_t = 7)]
+    pub storage_growth_days: u64,
+
+    /// The path to the Holochain data directory, to also check the backlog of pending ops
+    #[arg(long)]
+    pub data_root_path: Option<PathBuf>,
+
+    /// Warn when a DHT database has more ops than this waiting to be integrated
+    #[arg(long, default_value_t = 1000)]
+    pub pending_warning: usize,
+
+    /// Critical when a DHT database has more ops than this waiting to be integrated
+    #[arg(long, default_value_t = 10000)]
+    pub pending_critical: usize,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeMetricsArgs {
+    /// The tag to use when connecting to Holochain
+    #[arg(long, short)]
+    pub tag: String,
+
+    /// The origin header to use in the request
+    #[arg(long, default_value = "hc-ops")]
+    pub origin: String,
+
+    /// The address to serve metrics on, at `/metrics`
+    #[arg(long, default_value = "127.0.0.1:9100")]
+    pub listen: SocketAddr,
+
+    /// How often to collect metrics from the conductor, in seconds
+    #[arg(long, default_value_t = 15)]
+    pub interval: u64,
+
+    /// The path to the Holochain data directory, to also report pending ops from the DHT databases
+    #[arg(long)]
+    pub data_root_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    pub command: ReportCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportCommands {
+    /// Show how storage has grown, from snapshots taken with `admin storage-info --record`
+    StorageTrend {
+        /// The conductor tag that snapshots were recorded for
+        #[arg(long, short)]
+        tag: String,
+
+        /// Only use snapshots taken at or after this time, in RFC 3339 format
+        #[arg(long)]
+        since: Option<Timestamp>,
+
+        /// Project when the total storage used on disk will cross this many MiB
+        #[arg(long)]
+        threshold_mib: Option<u64>,
+    },
+
+    /// Attribute a conductor's storage to DNAs and roles
+    Storage {
+        /// The tag to use when connecting to Holochain
+        #[arg(long, short)]
+        tag: String,
+
+        /// The origin header to use in the request
+        #[arg(long, default_value = "hc-ops")]
+        origin: String,
+
+        /// The path to the Holochain data directory, to measure the database files and their pages
+        #[arg(long)]
+        data_root_path: Option<PathBuf>,
+
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+    },
+
+    /// Check the schema of every database in a data directory, and which features work with it
+    ///
+    /// Conductors of different Holochain versions have different database schemas. Features that
+    /// need tables or columns that a database doesn't have are refused when used.
+    Compatibility {
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+
+        /// The path to the Holochain data directory
+        data_root_path: PathBuf,
+    },
+}
+
+/// Where to read the conductor passphrase from, to unlock encrypted databases.
+///
+/// When a running `hc-ops unlock` agent holds the key for the data directory, no passphrase is
+/// read at all.
+#[derive(Debug, Clone, Args)]
+pub struct PassphraseArgs {
+    /// Read the conductor passphrase from stdin instead of prompting for it.
+    ///
+    /// When two data directories are unlocked, ours is read first. This takes precedence over the
+    /// `HC_OPS_PASSPHRASE` environment variable.
+    #[arg(long)]
+    pub piped: bool,
+
+    /// Read the conductor passphrase from the first line of a file
+    #[arg(long, conflicts_with_all = ["piped", "passphrase_fd", "passphrase_keyring"])]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Read the conductor passphrase from a line on an open file descriptor, e.g. `3` with `3<<<"$PASS"`
+    #[arg(long, conflicts_with_all = ["piped", "passphrase_keyring"])]
+    pub passphrase_fd: Option<u32>,
+
+    /// Look the conductor passphrase up in the OS keyring, under the `hc-ops` service and this account.
+    ///
+    /// Uses `secret-tool` on Linux and `security` on macOS. Store the passphrase first with, for
+    /// example, `secret-tool store --label hc-ops service hc-ops account <account>`.
+    #[arg(long, conflicts_with = "piped")]
+    pub passphrase_keyring: Option<String>,
+}
+
+/// Where to read the passphrase for their data directory from, when it isn't the same as ours.
+///
+/// When none of these are given, their passphrase is read from the same source as ours.
+#[derive(Debug, Clone, Args)]
+pub struct TheirPassphraseArgs {
+    /// Read the passphrase for their data directory from the first line of a file
+    #[arg(long, conflicts_with_all = ["their_passphrase_fd", "their_passphrase_keyring"])]
+    pub their_passphrase_file: Option<PathBuf>,
+
+    /// Read the passphrase for their data directory from a line on an open file descriptor
+    #[arg(long, conflicts_with = "their_passphrase_keyring")]
+    pub their_passphrase_fd: Option<u32>,
+
+    /// Look the passphrase for their data directory up in the OS keyring, under this account
+    #[arg(long)]
+    pub their_passphrase_keyring: Option<String>,
+}
+
+impl TheirPassphraseArgs {
+    /// The passphrase source for their data directory, falling back to ours.
+    pub fn or(&self, ours: &PassphraseArgs) -> PassphraseArgs {
+        if self.their_passphrase_file.is_none()
+            && self.their_passphrase_fd.is_none()
+            && self.their_passphrase_keyring.is_none()
+        {
+            return ours.clone();
+        }
+
+        PassphraseArgs {
+            piped: false,
+            passphrase_file: self.their_passphrase_file.clone(),
+            passphrase_fd: self.their_passphrase_fd,
+            passphrase_keyring: self.their_passphrase_keyring.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct UnlockArgs {
+    /// How long to hold the key for, in seconds
+    #[arg(long, default_value_t = 3600)]
+    pub ttl: u64,
+
+    #[command(flatten)]
+    pub passphrase: PassphraseArgs,
+
+    /// The path to the Holochain data directory to unlock
+    pub data_root_path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyArgs {
+    #[command(subcommand)]
+    pub command: KeyCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeyCommands {
+    /// Check the passphrase against `db.key`, and check that the databases can be read with it
+    Verify {
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+
+        /// The path to the Holochain data directory
+        data_root_path: PathBuf,
+    },
+    /// Show the salt and the key derivation and cipher parameters, without needing the passphrase
+    Info {
+        /// The path to the Holochain data directory
+        data_root_path: PathBuf,
+    },
+    /// Write unencrypted copies of databases, to analyse with other SQLite tools.
+    ///
+    /// The copies are laid out like the `databases` directory of a data directory, so they can
+    /// also be explored with `explore --offline`.
+    #[command(arg_required_else_help = true)]
+    DecryptCopy {
+        /// Only copy the databases of this DNA
+        #[arg(long)]
+        dna: Option<DnaHashB64>,
+
+        /// Only copy databases of this kind, can be repeated
+        #[arg(long, value_enum)]
+        database: Vec<DatabaseKind>,
+
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+
+        /// The path to the Holochain data directory
+        data_root_path: PathBuf,
+
+        /// The directory to write the copies to, as `<out_dir>/databases/...`
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DatabaseKind {
+    Authored,
+    Dht,
+    Cache,
 }
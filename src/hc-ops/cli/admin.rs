@@ -1,12 +1,16 @@
-use crate::cli::{AdminArgs, AdminCommands};
+use crate::cli::report::dna_usage;
+use crate::cli::{AdminArgs, AdminCommands, OutputFormat};
 use crate::connect_admin_client;
-use crate::render::Render;
+use crate::data::{NewSnapshot, insert_snapshots};
+use crate::render::{StorageInfoBlob, StorageInfoRecord, write_table};
 use diesel::SqliteConnection;
 use hc_ops::readable::HumanReadableDisplay;
+use hc_ops::retrieve::{ReportedStorage, attribute_storage};
 use holo_hash::DnaHash;
 use holochain_client::InstallAppPayload;
-use holochain_conductor_api::{AppStatusFilter, CellInfo, StorageBlob, StorageInfo};
+use holochain_conductor_api::{AppStatusFilter, CellInfo, DnaStorageInfo, StorageBlob};
 use holochain_types::prelude::AppBundleSource;
+use holochain_zome_types::prelude::Timestamp;
 use kitsune2_api::AgentInfoSigned;
 use kitsune2_core::Ed25519Verifier;
 use std::collections::HashMap;
@@ -15,6 +19,7 @@ use std::io::Write;
 pub(crate) async fn handle_admin_command(
     conn: &mut SqliteConnection,
     args: AdminArgs,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let (client, _) = connect_admin_client(conn, &args.tag, &args.origin).await?;
 
@@ -25,8 +30,10 @@ pub(crate) async fn handle_admin_command(
                 .await
                 .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
 
-            if apps.is_empty() {
+            if apps.is_empty() && !output.is_structured() {
                 eprintln!("No apps installed");
+            } else if output.is_structured() {
+                output.write_human_readable("Apps", &apps, !full, std::io::stdout())?;
             } else {
                 let out = if full {
                     apps.as_human_readable()?
@@ -41,7 +48,7 @@ pub(crate) async fn handle_admin_command(
             network_seed,
             app_id,
         } => {
-            println!("Installing app from path: {:?}", path);
+            output.note(format!("Installing app from path: {:?}", path));
 
             let installed = client
                 .install_app(InstallAppPayload {
@@ -54,47 +61,86 @@ pub(crate) async fn handle_admin_command(
                 })
                 .await?;
 
-            println!("Installed app under agent: {:?}", installed.agent_pub_key);
+            output.note(format!(
+                "Installed app under agent: {:?}",
+                installed.agent_pub_key
+            ));
 
             client
                 .enable_app(installed.installed_app_id.clone())
                 .await?;
 
-            println!("Enabled app: {:?}", installed.installed_app_id);
+            output.note(format!("Enabled app: {:?}", installed.installed_app_id));
 
-            println!("Done");
+            output.note("Done");
         }
         AdminCommands::UninstallApp { app_id } => {
-            println!("Uninstalling app: {:?}", app_id);
+            output.note(format!("Uninstalling app: {:?}", app_id));
 
             client.uninstall_app(app_id, false).await?;
 
-            println!("Done");
+            output.note("Done");
         }
-        AdminCommands::StorageInfo { app_id } => {
-            println!("Getting storage info");
+        AdminCommands::StorageInfo { app_id, record } => {
+            output.note("Getting storage info");
 
             let storage_info = client.storage_info().await?;
 
-            let storage_info = match app_id {
-                Some(app_id) => {
-                    let blobs = storage_info
-                        .blobs
-                        .into_iter()
-                        .filter(|b| match b {
-                            StorageBlob::Dna(dna) => dna.used_by.contains(&app_id),
-                        })
-                        .collect();
-
-                    StorageInfo { blobs }
-                }
-                None => storage_info,
-            };
+            let apps = client
+                .list_apps(None)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
+            let dna_apps = dna_usage(&apps)
+                .into_iter()
+                .map(|(dna, usage)| (dna, usage.apps))
+                .collect();
 
-            if storage_info.blobs.is_empty() {
+            let infos = storage_info
+                .blobs
+                .iter()
+                .map(|b| match b {
+                    StorageBlob::Dna(dna) => dna,
+                })
+                .collect::<Vec<_>>();
+            let attributed = attribute_storage(
+                &infos
+                    .iter()
+                    .map(|i| ReportedStorage::from(*i))
+                    .collect::<Vec<_>>(),
+                &dna_apps,
+                &HashMap::new(),
+            );
+
+            if record {
+                record_snapshot(conn, &args.tag, &infos, &attributed)?;
+                output.note(format!("Recorded storage info for {} DNAs", infos.len()));
+            }
+
+            let blobs = infos
+                .into_iter()
+                .zip(attributed)
+                .filter(|(info, _)| app_id.as_ref().is_none_or(|id| info.used_by.contains(id)))
+                .collect::<Vec<_>>();
+
+            if blobs.is_empty() && !output.is_structured() {
                 eprintln!("No storage info available");
+            } else if output.is_structured() {
+                let records = blobs
+                    .iter()
+                    .map(|(info, dna_hash)| StorageInfoRecord {
+                        dna: dna_hash.as_ref(),
+                        info,
+                    })
+                    .collect::<Vec<_>>();
+                output.write_value(&serde_json::to_value(records)?, std::io::stdout())?;
             } else {
-                storage_info.render(std::io::stdout())?;
+                write_table(
+                    &blobs
+                        .iter()
+                        .map(|(info, dna_hash)| StorageInfoBlob::new(dna_hash.as_ref(), info))
+                        .collect::<Vec<_>>(),
+                    std::io::stdout(),
+                )?;
             }
         }
         AdminCommands::NetworkMetrics { app_id } => {
@@ -135,12 +181,31 @@ pub(crate) async fn handle_admin_command(
                 client.dump_network_metrics(None, true).await?
             };
 
-            std::io::stdout().write_all(network_metrics.as_human_readable()?.as_bytes())?;
+            if output.is_structured() {
+                output.write_human_readable(
+                    "Network metrics",
+                    &network_metrics,
+                    false,
+                    std::io::stdout(),
+                )?;
+            } else {
+                std::io::stdout().write_all(network_metrics.as_human_readable()?.as_bytes())?;
+            }
         }
         AdminCommands::NetworkStats => {
             let stats = client.dump_network_stats().await?;
 
-            std::io::stdout().write_all(stats.transport_stats.as_human_readable()?.as_bytes())?;
+            if output.is_structured() {
+                output.write_human_readable(
+                    "Network stats",
+                    &stats.transport_stats,
+                    false,
+                    std::io::stdout(),
+                )?;
+            } else {
+                std::io::stdout()
+                    .write_all(stats.transport_stats.as_human_readable()?.as_bytes())?;
+            }
         }
         AdminCommands::ListAgents { app_id } => {
             let agents = if let Some(app_id) = app_id {
@@ -193,9 +258,41 @@ pub(crate) async fn handle_admin_command(
                     .collect()
             };
 
-            std::io::stdout().write_all(agents.as_human_readable()?.as_bytes())?;
+            if output.is_structured() {
+                output.write_human_readable("Agents", &agents, false, std::io::stdout())?;
+            } else {
+                std::io::stdout().write_all(agents.as_human_readable()?.as_bytes())?;
+            }
         }
     }
 
     Ok(())
 }
+
+fn record_snapshot(
+    conn: &mut SqliteConnection,
+    tag: &str,
+    infos: &[&DnaStorageInfo],
+    attributed: &[Option<DnaHash>],
+) -> anyhow::Result<()> {
+    let taken_at = Timestamp::now().as_micros();
+
+    let snapshots = infos
+        .iter()
+        .zip(attributed)
+        .map(|(dna, dna_hash)| NewSnapshot {
+            tag,
+            taken_at,
+            apps: dna.used_by.join(", "),
+            authored: dna.authored_data_size as i64,
+            authored_on_disk: dna.authored_data_size_on_disk as i64,
+            dht: dna.dht_data_size as i64,
+            dht_on_disk: dna.dht_data_size_on_disk as i64,
+            cache: dna.cache_data_size as i64,
+            cache_on_disk: dna.cache_data_size_on_disk as i64,
+            dna_hash: dna_hash.as_ref().map(|h| h.to_string()),
+        })
+        .collect::<Vec<_>>();
+
+    insert_snapshots(conn, &snapshots)
+}
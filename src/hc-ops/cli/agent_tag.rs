@@ -1,32 +1,33 @@
-use crate::cli::{AgentTagArgs, AgentTagCommands};
+use crate::cli::{AgentTagArgs, AgentTagCommands, OutputFormat};
 use crate::render::{AgentTagTable, Render};
 use diesel::SqliteConnection;
 
 pub(crate) async fn handle_agent_tag_command(
     conn: &mut SqliteConnection,
     args: AgentTagArgs,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     match args.command {
         AgentTagCommands::Add { agent, tag } => {
             crate::data::insert_agent_tag(conn, &tag, agent.into())?;
-            println!("Added tag: {}", tag);
+            output.note(format!("Added tag: {}", tag));
         }
         AgentTagCommands::List => {
             let tags = crate::data::list_agent_tags(conn)?;
 
-            if tags.is_empty() {
+            if tags.is_empty() && !output.is_structured() {
                 println!("No tags found");
             } else {
                 tags.into_iter()
                     .map(Into::into)
                     .collect::<Vec<AgentTagTable>>()
-                    .render(std::io::stdout())?;
+                    .render(output, std::io::stdout())?;
             }
         }
         AgentTagCommands::Delete { tag } => {
             crate::data::delete_agent_tag(conn, &tag)?;
 
-            println!("Deleted tag: {}", tag);
+            output.note(format!("Deleted tag: {}", tag));
         }
     }
 
@@ -1,4 +1,4 @@
-use crate::cli::{ConductorTagArgs, ConductorTagCommands};
+use crate::cli::{ConductorTagArgs, ConductorTagCommands, OutputFormat};
 use crate::data;
 use crate::render::{ConductorTagTable, Render};
 use diesel::SqliteConnection;
@@ -7,6 +7,7 @@ use std::net::SocketAddr;
 pub(crate) async fn handle_conductor_tag_command(
     conn: &mut SqliteConnection,
     args: ConductorTagArgs,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     match args.command {
         ConductorTagCommands::Add {
@@ -30,7 +31,7 @@ pub(crate) async fn handle_conductor_tag_command(
                 }
             }
 
-            println!("Added tag: {}", tag);
+            output.note(format!("Added tag: {}", tag));
         }
         ConductorTagCommands::List => {
             let tags = data::list_conductor_tags(conn)?;
@@ -38,12 +39,12 @@ pub(crate) async fn handle_conductor_tag_command(
             tags.into_iter()
                 .map(Into::into)
                 .collect::<Vec<ConductorTagTable>>()
-                .render(std::io::stdout())?;
+                .render(output, std::io::stdout())?;
         }
         ConductorTagCommands::Delete { tag } => {
             data::delete_addr_tag(conn, &tag)?;
 
-            println!("Deleted tag: {}", tag);
+            output.note(format!("Deleted tag: {}", tag));
         }
     }
 
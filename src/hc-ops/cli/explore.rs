@@ -1,15 +1,97 @@
-use crate::cli::ExploreArgs;
+use crate::cli::{ExploreArgs, ExploreCommands, OutputFormat};
 use crate::connect_admin_client;
-use crate::explore::start_explorer;
+use crate::explore::{
+    CellSelector, fetch_agent_arcs, resolve_cell, resolve_offline_cell, run_explore_command,
+    start_explorer, start_offline_explorer,
+};
 use diesel::SqliteConnection;
+use hc_ops::retrieve::OpenMode;
+use holochain_zome_types::prelude::{AgentPubKey, DnaHash};
 
 pub(crate) async fn handle_explore_command(
     conn: &mut SqliteConnection,
     args: ExploreArgs,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
-    let (client, _) = connect_admin_client(conn, &args.tag, &args.origin).await?;
+    let dna: Option<DnaHash> = args.dna.map(Into::into);
+    let open_mode = if args.snapshot {
+        OpenMode::Snapshot
+    } else {
+        OpenMode::ReadOnly
+    };
+    let agent: Option<AgentPubKey> = args.agent.map(Into::into);
 
-    start_explorer(conn, client, &args.data_root_path).await?;
+    if args.offline {
+        match args.command {
+            Some(command) => {
+                let (dna, agent) =
+                    resolve_offline_cell(&args.data_root_path, dna.as_ref(), agent.as_ref())?;
+
+                run_explore_command(
+                    &args.data_root_path,
+                    &args.passphrase,
+                    open_mode,
+                    &dna,
+                    &agent,
+                    &[],
+                    command,
+                    output,
+                )?;
+            }
+            None => {
+                start_offline_explorer(&args.data_root_path, &args.passphrase, open_mode, output)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let tag = args
+        .tag
+        .ok_or_else(|| anyhow::anyhow!("A tag is required unless --offline is set"))?;
+    let (client, _) = connect_admin_client(conn, &tag, &args.origin).await?;
+    let selector = CellSelector {
+        app: args.app,
+        role: args.role,
+        clone_id: args.clone_id,
+        dna,
+        agent,
+    };
+
+    match args.command {
+        Some(command) => {
+            let (dna, agent) = resolve_cell(&client, &selector).await?;
+
+            let agent_arcs = if matches!(command, ExploreCommands::Coverage { .. }) {
+                fetch_agent_arcs(&client, &dna).await?
+            } else {
+                Vec::new()
+            };
+
+            run_explore_command(
+                &args.data_root_path,
+                &args.passphrase,
+                open_mode,
+                &dna,
+                &agent,
+                &agent_arcs,
+                command,
+                output,
+            )?;
+        }
+        None => {
+            start_explorer(
+                conn,
+                client,
+                &args.data_root_path,
+                &args.passphrase,
+                open_mode,
+                &selector,
+                output,
+            )
+            .await?;
+        }
+    }
 
     Ok(())
 }
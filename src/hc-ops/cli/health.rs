@@ -0,0 +1,451 @@
+use crate::cli::report::{MICROS_PER_DAY, growth_per_day, total_on_disk};
+use crate::cli::{HealthArgs, OutputFormat};
+use crate::connect_admin_client;
+use crate::data::list_snapshots;
+use crate::explore::unlock_database_key;
+use crate::render::Render;
+use diesel::SqliteConnection;
+use hc_ops::ops::AdminWebsocketExt;
+use hc_ops::retrieve::{DbKind, OpenMode, count_pending_ops, open_holochain_database};
+use holochain_conductor_api::{AppInfo, AppStatusFilter, CellInfo, StorageBlob};
+use holochain_zome_types::prelude::{AgentPubKey, CellId, DnaHash, Timestamp};
+use kitsune2_api::AgentInfoSigned;
+use kitsune2_core::Ed25519Verifier;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use tabled::Tabled;
+
+/// The result of a health check, ordered from best to worst.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl HealthStatus {
+    /// The exit code that monitoring systems, such as Nagios, expect for this status.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            HealthStatus::Ok => 0,
+            HealthStatus::Warning => 1,
+            HealthStatus::Critical => 2,
+        }
+    }
+
+    /// Where lower values are worse.
+    fn at_least(value: usize, warning: usize, critical: usize) -> Self {
+        if value < critical {
+            HealthStatus::Critical
+        } else if value < warning {
+            HealthStatus::Warning
+        } else {
+            HealthStatus::Ok
+        }
+    }
+
+    /// Where higher values are worse.
+    fn at_most(value: u64, warning: Option<u64>, critical: Option<u64>) -> Self {
+        if critical.is_some_and(|critical| value > critical) {
+            HealthStatus::Critical
+        } else if warning.is_some_and(|warning| value > warning) {
+            HealthStatus::Warning
+        } else {
+            HealthStatus::Ok
+        }
+    }
+}
+
+impl Display for HealthStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthStatus::Ok => write!(f, "OK"),
+            HealthStatus::Warning => write!(f, "WARNING"),
+            HealthStatus::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
+struct HealthCheck {
+    check: &'static str,
+    status: HealthStatus,
+    detail: String,
+}
+
+impl HealthCheck {
+    fn new(check: &'static str, status: HealthStatus, detail: impl Into<String>) -> Self {
+        Self {
+            check,
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    /// A check that could not be run is treated as critical.
+    fn failed(check: &'static str, error: impl Display) -> Self {
+        Self::new(
+            check,
+            HealthStatus::Critical,
+            format!("Check failed: {error:#}"),
+        )
+    }
+}
+
+/// Check the health of a conductor, returning the worst status of all checks.
+///
+/// Failures to reach the conductor are reported as a critical check rather than an error, so that
+/// the exit code is meaningful to monitoring.
+pub(crate) async fn handle_health_command(
+    conn: &mut SqliteConnection,
+    args: HealthArgs,
+    output: OutputFormat,
+) -> anyhow::Result<HealthStatus> {
+    let checks = run_checks(conn, &args).await;
+
+    let status = checks
+        .iter()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(HealthStatus::Ok);
+    let failing = checks
+        .iter()
+        .filter(|c| c.status != HealthStatus::Ok)
+        .count();
+
+    match output {
+        OutputFormat::Table => {
+            // Monitoring reads the first line as the summary.
+            println!(
+                "{status}: {failing} of {} checks failing on {}",
+                checks.len(),
+                args.tag
+            );
+            checks.render(output, std::io::stdout())?;
+        }
+        OutputFormat::Json => {
+            output.write_value(
+                &serde_json::json!({ "status": status, "checks": checks }),
+                std::io::stdout(),
+            )?;
+        }
+        OutputFormat::Ndjson => {
+            checks.render(output, std::io::stdout())?;
+        }
+    }
+
+    Ok(status)
+}
+
+async fn run_checks(conn: &mut SqliteConnection, args: &HealthArgs) -> Vec<HealthCheck> {
+    let mut checks = Vec::new();
+
+    let client = match connect_admin_client(conn, &args.tag, &args.origin).await {
+        Ok((client, tag)) => {
+            checks.push(HealthCheck::new(
+                "admin",
+                HealthStatus::Ok,
+                format!("Connected to ws://{}:{}", tag.address, tag.port),
+            ));
+            client
+        }
+        Err(e) => {
+            checks.push(HealthCheck::failed("admin", e));
+            return checks;
+        }
+    };
+
+    let apps = match list_enabled_apps(&client).await {
+        Ok((apps, total)) => {
+            let status = if apps.is_empty() {
+                HealthStatus::Critical
+            } else if apps.len() < total {
+                HealthStatus::Warning
+            } else {
+                HealthStatus::Ok
+            };
+            checks.push(HealthCheck::new(
+                "apps",
+                status,
+                format!("{} of {total} apps enabled", apps.len()),
+            ));
+            apps
+        }
+        Err(e) => {
+            checks.push(HealthCheck::failed("apps", e));
+            return checks;
+        }
+    };
+
+    let cells = provisioned_cells(&apps);
+    let dnas = cells
+        .iter()
+        .map(|cell| cell.dna_hash().clone())
+        .collect::<BTreeSet<_>>();
+
+    checks.push(
+        check_init(&client, &cells)
+            .await
+            .unwrap_or_else(|e| HealthCheck::failed("init", e)),
+    );
+    checks.push(
+        check_peers(&client, &cells, &dnas, args)
+            .await
+            .unwrap_or_else(|e| HealthCheck::failed("peers", e)),
+    );
+    checks.push(
+        check_connections(&client, args)
+            .await
+            .unwrap_or_else(|e| HealthCheck::failed("connections", e)),
+    );
+
+    if args.storage_warning_mib.is_some() || args.storage_critical_mib.is_some() {
+        checks.push(
+            check_storage(&client, args)
+                .await
+                .unwrap_or_else(|e| HealthCheck::failed("storage", e)),
+        );
+    }
+
+    if args.storage_growth_warning_mib.is_some() || args.storage_growth_critical_mib.is_some() {
+        checks.push(
+            check_storage_growth(conn, args)
+                .unwrap_or_else(|e| HealthCheck::failed("storage_growth", e)),
+        );
+    }
+
+    if let Some(data_root_path) = &args.data_root_path {
+        checks.push(
+            check_pending(data_root_path, &dnas, args)
+                .unwrap_or_else(|e| HealthCheck::failed("pending", e)),
+        );
+    }
+
+    checks
+}
+
+/// The enabled apps, and the total number of installed apps.
+async fn list_enabled_apps(
+    client: &holochain_client::AdminWebsocket,
+) -> anyhow::Result<(Vec<AppInfo>, usize)> {
+    let total = client
+        .list_apps(None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?
+        .len();
+    let enabled = client
+        .list_apps(Some(AppStatusFilter::Enabled))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
+
+    Ok((enabled, total))
+}
+
+fn provisioned_cells(apps: &[AppInfo]) -> Vec<CellId> {
+    apps.iter()
+        .flat_map(|app| app.cell_info.values())
+        .flat_map(|cells| {
+            cells.iter().filter_map(|cell| match cell {
+                CellInfo::Provisioned(cell) => Some(cell.cell_id.clone()),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+async fn check_init(
+    client: &holochain_client::AdminWebsocket,
+    cells: &[CellId],
+) -> anyhow::Result<HealthCheck> {
+    let mut uninitialized = 0;
+    for cell in cells {
+        if !client.is_cell_initialized(cell.clone()).await? {
+            uninitialized += 1;
+        }
+    }
+
+    let status = if uninitialized == 0 {
+        HealthStatus::Ok
+    } else {
+        HealthStatus::Warning
+    };
+
+    Ok(HealthCheck::new(
+        "init",
+        status,
+        format!("{uninitialized} of {} cells not initialized", cells.len()),
+    ))
+}
+
+/// Check the DNA with the fewest known peers, not counting agents on this conductor.
+async fn check_peers(
+    client: &holochain_client::AdminWebsocket,
+    cells: &[CellId],
+    dnas: &BTreeSet<DnaHash>,
+    args: &HealthArgs,
+) -> anyhow::Result<HealthCheck> {
+    let local_agents = cells
+        .iter()
+        .map(|cell| cell.agent_pubkey().clone())
+        .collect::<HashSet<_>>();
+
+    let mut fewest: Option<(usize, &DnaHash)> = None;
+    for dna in dnas {
+        let peers = client
+            .agent_info(Some(vec![dna.clone()]))
+            .await?
+            .into_iter()
+            .filter_map(|s| AgentInfoSigned::decode(&Ed25519Verifier, s.as_bytes()).ok())
+            .filter(|info| !info.is_tombstone)
+            .filter(|info| !local_agents.contains(&AgentPubKey::from_k2_agent(&info.agent)))
+            .count();
+
+        if fewest.is_none_or(|(fewest, _)| peers < fewest) {
+            fewest = Some((peers, dna));
+        }
+    }
+
+    Ok(match fewest {
+        Some((peers, dna)) => HealthCheck::new(
+            "peers",
+            HealthStatus::at_least(peers, args.peers_warning, args.peers_critical),
+            format!("Fewest peers is {peers}, for DNA {dna}"),
+        ),
+        None => HealthCheck::new("peers", HealthStatus::Ok, "No DNAs to check"),
+    })
+}
+
+async fn check_connections(
+    client: &holochain_client::AdminWebsocket,
+    args: &HealthArgs,
+) -> anyhow::Result<HealthCheck> {
+    let connections = client
+        .dump_network_stats()
+        .await?
+        .transport_stats
+        .connections
+        .len();
+
+    Ok(HealthCheck::new(
+        "connections",
+        HealthStatus::at_least(
+            connections,
+            args.connections_warning,
+            args.connections_critical,
+        ),
+        format!("{connections} open transport connections"),
+    ))
+}
+
+async fn check_storage(
+    client: &holochain_client::AdminWebsocket,
+    args: &HealthArgs,
+) -> anyhow::Result<HealthCheck> {
+    let on_disk = client
+        .storage_info()
+        .await?
+        .blobs
+        .iter()
+        .map(|blob| match blob {
+            StorageBlob::Dna(dna) => {
+                (dna.authored_data_size_on_disk
+                    + dna.dht_data_size_on_disk
+                    + dna.cache_data_size_on_disk) as u64
+            }
+        })
+        .sum::<u64>();
+
+    let mib = |size: Option<u64>| size.map(|size| size * 1024 * 1024);
+
+    Ok(HealthCheck::new(
+        "storage",
+        HealthStatus::at_most(
+            on_disk,
+            mib(args.storage_warning_mib),
+            mib(args.storage_critical_mib),
+        ),
+        format!("{} on disk", human_bytes::human_bytes(on_disk as f64)),
+    ))
+}
+
+/// Check how fast the storage on disk is growing, from the snapshots recorded for this conductor.
+fn check_storage_growth(
+    conn: &mut SqliteConnection,
+    args: &HealthArgs,
+) -> anyhow::Result<HealthCheck> {
+    let since =
+        Timestamp::now().as_micros() - (args.storage_growth_days as f64 * MICROS_PER_DAY) as i64;
+    let totals = total_on_disk(&list_snapshots(conn, &args.tag, Some(since))?);
+
+    let Some(growth) = growth_per_day(&totals) else {
+        return Ok(HealthCheck::new(
+            "storage_growth",
+            HealthStatus::Warning,
+            format!(
+                "Not enough snapshots in the last {} days to measure growth, record them with `admin storage-info --record`",
+                args.storage_growth_days
+            ),
+        ));
+    };
+
+    let mib = |size: Option<u64>| size.map(|size| size * 1024 * 1024);
+
+    Ok(HealthCheck::new(
+        "storage_growth",
+        HealthStatus::at_most(
+            growth.max(0.0) as u64,
+            mib(args.storage_growth_warning_mib),
+            mib(args.storage_growth_critical_mib),
+        ),
+        format!(
+            "{} per day over {} snapshots",
+            if growth < 0.0 {
+                format!("-{}", human_bytes::human_bytes(-growth))
+            } else {
+                human_bytes::human_bytes(growth)
+            },
+            totals.len()
+        ),
+    ))
+}
+
+/// Check the DNA with the largest backlog of ops waiting to be integrated.
+fn check_pending(
+    data_root_path: &Path,
+    dnas: &BTreeSet<DnaHash>,
+    args: &HealthArgs,
+) -> anyhow::Result<HealthCheck> {
+    let mut key = unlock_database_key(data_root_path, &args.passphrase)?;
+
+    let mut largest: Option<(usize, &DnaHash)> = None;
+    for dna in dnas {
+        let mut dht = open_holochain_database(
+            data_root_path,
+            &DbKind::Dht,
+            dna,
+            key.as_mut(),
+            OpenMode::ReadOnly,
+        )?;
+        let pending = count_pending_ops(&mut dht)?;
+
+        if largest.is_none_or(|(largest, _)| pending > largest) {
+            largest = Some((pending, dna));
+        }
+    }
+
+    Ok(match largest {
+        Some((pending, dna)) => HealthCheck::new(
+            "pending",
+            HealthStatus::at_most(
+                pending as u64,
+                Some(args.pending_warning as u64),
+                Some(args.pending_critical as u64),
+            ),
+            format!("Largest backlog is {pending} ops, for DNA {dna}"),
+        ),
+        None => HealthCheck::new("pending", HealthStatus::Ok, "No DNAs to check"),
+    })
+}
@@ -1,4 +1,4 @@
-use crate::cli::{InitArgs, InitCommands};
+use crate::cli::{InitArgs, InitCommands, OutputFormat};
 use crate::render::Render;
 use crate::{connect_admin_client, render};
 use diesel::SqliteConnection;
@@ -16,6 +16,7 @@ use std::sync::Arc;
 pub(crate) async fn handle_init_command(
     conn: &mut SqliteConnection,
     args: InitArgs,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let (client, tag) = connect_admin_client(conn, &args.tag, &args.origin).await?;
 
@@ -50,10 +51,10 @@ pub(crate) async fn handle_init_command(
                 }
             }
 
-            if out.is_empty() {
+            if out.is_empty() && !output.is_structured() {
                 eprintln!("No cells to check");
             } else {
-                out.render(std::io::stdout())?;
+                out.render(output, std::io::stdout())?;
             }
         }
         InitCommands::Execute { origin, app_id } => {
@@ -77,14 +78,14 @@ pub(crate) async fn handle_init_command(
                 .find(|app| app.installed_app_id == app_id)
                 .ok_or_else(|| anyhow::anyhow!("App not found"))?;
 
-            println!("Using app info: {:#?}", app);
+            output.note(format!("Using app info: {:#?}", app));
 
             for cells in app.cell_info.values() {
                 for cell in cells {
                     match cell {
                         CellInfo::Provisioned(cell) => {
                             if client.is_cell_initialized(cell.cell_id.clone()).await? {
-                                println!("Already initialized: {:?}", cell.cell_id);
+                                output.note(format!("Already initialized: {:?}", cell.cell_id));
                                 continue;
                             }
 
@@ -123,10 +124,10 @@ pub(crate) async fn handle_init_command(
                                     anyhow::anyhow!("Failed to call init on zome: {:?}", e)
                                 })?;
 
-                            println!(
+                            output.note(format!(
                                 "Init result: {:?}",
                                 ExternIO::decode::<InitCallbackResult>(&out)?
-                            );
+                            ));
                         }
                         _ => {
                             // Not relevant
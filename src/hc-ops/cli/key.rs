@@ -0,0 +1,269 @@
+use crate::cli::{DatabaseKind, KeyArgs, KeyCommands, OutputFormat, PassphraseArgs};
+use crate::explore::{AsAnyhowPretty, read_passphrase, unlock_database_key};
+use crate::render::Render;
+use anyhow::Context;
+use hc_ops::retrieve::{
+    CIPHER_COMPATIBILITY, CIPHER_PLAINTEXT_HEADER_SIZE, DbKind, Key, OpenMode,
+    check_database_readable, database_path, decrypt_database_copy, discover_cells,
+    open_holochain_database, read_key_file_info,
+};
+use holochain_zome_types::prelude::DnaHash;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tabled::Tabled;
+
+pub(crate) fn handle_key_command(args: KeyArgs, output: OutputFormat) -> anyhow::Result<()> {
+    match args.command {
+        KeyCommands::Verify {
+            passphrase,
+            data_root_path,
+        } => verify(&data_root_path, &passphrase, output),
+        KeyCommands::Info { data_root_path } => info(&data_root_path, output),
+        KeyCommands::DecryptCopy {
+            dna,
+            database,
+            passphrase,
+            data_root_path,
+            out_dir,
+        } => decrypt_copy(
+            &data_root_path,
+            dna.map(Into::into),
+            &database,
+            &passphrase,
+            &out_dir,
+            output,
+        ),
+    }
+}
+
+#[derive(Tabled, Serialize)]
+struct KeyCheck {
+    check: String,
+    result: String,
+}
+
+#[derive(Tabled, Serialize)]
+struct KeyProperty {
+    property: &'static str,
+    value: String,
+}
+
+#[derive(Tabled, Serialize)]
+struct DecryptedCopy {
+    database: String,
+    copy: String,
+}
+
+fn key_path(data_root_path: &Path) -> anyhow::Result<PathBuf> {
+    let key_path = data_root_path.join("databases").join("db.key");
+    if !key_path.is_file() {
+        anyhow::bail!(
+            "No database key at {}, the databases are not encrypted",
+            key_path.display()
+        );
+    }
+
+    Ok(key_path)
+}
+
+fn verify(
+    data_root_path: &Path,
+    passphrase: &PassphraseArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let key_path = key_path(data_root_path)?;
+
+    let mut key = Key::load(key_path.clone(), read_passphrase(passphrase)?)
+        .into_anyhow()
+        .with_context(|| format!("The passphrase does not unlock {}", key_path.display()))?;
+
+    let mut checks = vec![KeyCheck {
+        check: "db.key".to_string(),
+        result: "Unlocked".to_string(),
+    }];
+
+    let mut unreadable = 0;
+    for (kind, dna) in list_databases(data_root_path, None, &[])? {
+        let result = open_holochain_database(
+            data_root_path,
+            &kind,
+            &dna,
+            Some(&mut key),
+            OpenMode::ReadOnly,
+        )
+        .and_then(|mut conn| check_database_readable(&mut conn));
+
+        checks.push(KeyCheck {
+            check: database_name(&kind, &dna),
+            result: match result {
+                Ok(()) => "Readable".to_string(),
+                Err(e) => {
+                    unreadable += 1;
+                    format!("Not readable: {e}")
+                }
+            },
+        });
+    }
+
+    checks.render(output, std::io::stdout())?;
+
+    if unreadable > 0 {
+        anyhow::bail!("{unreadable} databases could not be read with this key");
+    }
+
+    Ok(())
+}
+
+/// Holochain doesn't store the KDF limits in the key file, so `key info` shows the ones it uses.
+const KDF_LIMITS_SOURCE: &str = "(always used by Holochain, not read from the key file)";
+
+fn info(data_root_path: &Path, output: OutputFormat) -> anyhow::Result<()> {
+    let key_path = key_path(data_root_path)?;
+    let info = read_key_file_info(&key_path).into_anyhow()?;
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    let properties = vec![
+        KeyProperty {
+            property: "key_file",
+            value: key_path.display().to_string(),
+        },
+        KeyProperty {
+            property: "kdf",
+            value: "argon2id".to_string(),
+        },
+        KeyProperty {
+            property: "kdf_salt",
+            value: hex(&info.salt),
+        },
+        KeyProperty {
+            property: "kdf_opslimit",
+            value: format!("{} {KDF_LIMITS_SOURCE}", info.argon2_opslimit),
+        },
+        KeyProperty {
+            property: "kdf_memlimit",
+            value: format!(
+                "{} {KDF_LIMITS_SOURCE}",
+                human_bytes::human_bytes(info.argon2_memlimit as f64)
+            ),
+        },
+        KeyProperty {
+            property: "key_encryption",
+            value: "xsalsa20poly1305".to_string(),
+        },
+        KeyProperty {
+            property: "key_nonce",
+            value: hex(&info.nonce),
+        },
+        KeyProperty {
+            property: "key_length",
+            value: format!("{} bytes", info.key_len),
+        },
+        KeyProperty {
+            property: "database_cipher",
+            value: "SQLCipher, with a raw key".to_string(),
+        },
+        KeyProperty {
+            property: "cipher_compatibility",
+            value: CIPHER_COMPATIBILITY.to_string(),
+        },
+        KeyProperty {
+            property: "cipher_plaintext_header_size",
+            value: format!("{CIPHER_PLAINTEXT_HEADER_SIZE} bytes"),
+        },
+    ];
+
+    properties.render(output, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn decrypt_copy(
+    data_root_path: &Path,
+    dna: Option<DnaHash>,
+    kinds: &[DatabaseKind],
+    passphrase: &PassphraseArgs,
+    out_dir: &Path,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    key_path(data_root_path)?;
+
+    let databases = list_databases(data_root_path, dna.as_ref(), kinds)?;
+    if databases.is_empty() {
+        anyhow::bail!("No matching databases found");
+    }
+
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let mut copies = Vec::new();
+    for (kind, dna) in databases {
+        let name = database_name(&kind, &dna);
+        output.note(format!("Decrypting {name}"));
+
+        let copy = database_path(out_dir, &kind, &dna);
+        if let Some(parent) = copy.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        decrypt_database_copy(data_root_path, &kind, &dna, key.as_mut(), &copy)
+            .into_anyhow()
+            .with_context(|| format!("Failed to decrypt {name}"))?;
+
+        copies.push(DecryptedCopy {
+            database: name,
+            copy: copy.display().to_string(),
+        });
+    }
+
+    copies.render(output, std::io::stdout())?;
+
+    Ok(())
+}
+
+/// The databases in a data directory, optionally only for one DNA and of some kinds.
+pub(crate) fn list_databases(
+    data_root_path: &Path,
+    dna: Option<&DnaHash>,
+    kinds: &[DatabaseKind],
+) -> anyhow::Result<Vec<(DbKind, DnaHash)>> {
+    let include = |kind: DatabaseKind| kinds.is_empty() || kinds.contains(&kind);
+
+    let cells = discover_cells(data_root_path)
+        .into_anyhow()?
+        .into_iter()
+        .filter(|c| dna.is_none_or(|dna| &c.dna_hash == dna))
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        if include(DatabaseKind::Authored) {
+            out.push((
+                DbKind::Authored(cell.agent_pub_key.clone()),
+                cell.dna_hash.clone(),
+            ));
+        }
+
+        // The DHT and cache databases are shared by the cells of a DNA, and cells are sorted by
+        // DNA, so only include them for the first cell of each DNA.
+        if i > 0 && cells[i - 1].dna_hash == cell.dna_hash {
+            continue;
+        }
+
+        if include(DatabaseKind::Dht) && cell.has_dht {
+            out.push((DbKind::Dht, cell.dna_hash.clone()));
+        }
+        if include(DatabaseKind::Cache) && cell.has_cache {
+            out.push((DbKind::Cache, cell.dna_hash.clone()));
+        }
+    }
+
+    Ok(out)
+}
+
+pub(crate) fn database_name(kind: &DbKind, dna: &DnaHash) -> String {
+    match kind {
+        DbKind::Authored(agent) => format!("authored/{dna}-{agent}"),
+        DbKind::Dht => format!("dht/{dna}"),
+        DbKind::Cache => format!("cache/{dna}"),
+    }
+}
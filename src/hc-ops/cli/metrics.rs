@@ -0,0 +1,648 @@
+use crate::cli::report::dna_usage;
+use crate::cli::{OutputFormat, ServeMetricsArgs};
+use crate::connect_admin_client;
+use crate::explore::unlock_database_key;
+use diesel::SqliteConnection;
+use hc_ops::retrieve::{
+    DbKind, Key, OpenMode, ReportedStorage, attribute_storage, count_pending_ops,
+    count_pending_ops_by_stage, measure_database_sizes, open_holochain_database,
+};
+use holochain_conductor_api::{AppStatusFilter, CellInfo, StorageBlob};
+use holochain_zome_types::prelude::DnaHash;
+use kitsune2_api::AgentInfoSigned;
+use kitsune2_core::Ed25519Verifier;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Write as _};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Collect metrics from a conductor on an interval, and serve the latest collection over HTTP.
+///
+/// Runs until interrupted. If the conductor can't be reached, `hc_ops_up` is reported as 0 and the
+/// connection is retried on the next collection.
+pub(crate) async fn handle_serve_metrics_command(
+    conn: &mut SqliteConnection,
+    args: ServeMetricsArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut key = match &args.data_root_path {
+        Some(data_root_path) => unlock_database_key(data_root_path, &args.passphrase)?,
+        None => None,
+    };
+
+    let latest = Arc::new(RwLock::new(MetricsText::default().finish()));
+
+    let listener = TcpListener::bind(args.listen).await?;
+    output.note(format!("Serving metrics at http://{}/metrics", args.listen));
+    tokio::spawn(serve(listener, latest.clone()));
+
+    let mut client = None;
+    loop {
+        if client.is_none() {
+            client = connect_admin_client(conn, &args.tag, &args.origin)
+                .await
+                .inspect_err(|e| eprintln!("Failed to connect to the conductor: {e:#}"))
+                .ok()
+                .map(|(client, _)| client);
+        }
+
+        let mut metrics = MetricsText::default();
+        let up = match &client {
+            Some(client) => {
+                match collect(
+                    client,
+                    args.data_root_path.as_deref(),
+                    &mut key,
+                    &mut metrics,
+                )
+                .await
+                {
+                    Ok(()) => true,
+                    Err(e) => {
+                        eprintln!("Failed to collect metrics: {e:#}");
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+
+        if !up {
+            client = None;
+            metrics = MetricsText::default();
+        }
+
+        metrics.family(
+            "hc_ops_up",
+            "gauge",
+            "Whether the last collection from the conductor succeeded",
+        );
+        metrics.sample("hc_ops_up", &[], if up { 1 } else { 0 });
+
+        *latest
+            .write()
+            .map_err(|_| anyhow::anyhow!("Metrics lock poisoned"))? = metrics.finish();
+
+        tokio::time::sleep(Duration::from_secs(args.interval.max(1))).await;
+    }
+}
+
+async fn collect(
+    client: &holochain_client::AdminWebsocket,
+    data_root_path: Option<&Path>,
+    key: &mut Option<Key>,
+    metrics: &mut MetricsText,
+) -> anyhow::Result<()> {
+    let apps = client
+        .list_apps(Some(AppStatusFilter::Enabled))
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
+    let dnas = apps
+        .iter()
+        .flat_map(|app| app.cell_info.values())
+        .flat_map(|cells| {
+            cells.iter().filter_map(|cell| match cell {
+                CellInfo::Provisioned(cell) => Some(cell.cell_id.dna_hash().clone()),
+                _ => None,
+            })
+        })
+        .collect::<BTreeSet<_>>();
+
+    metrics.family("holochain_enabled_apps", "gauge", "Enabled apps");
+    metrics.sample("holochain_enabled_apps", &[], apps.len());
+
+    collect_storage(client, data_root_path, metrics).await?;
+    collect_network(client, &dnas, metrics).await?;
+
+    if let Some(data_root_path) = data_root_path {
+        collect_pending(data_root_path, key, &dnas, metrics)?;
+    }
+
+    Ok(())
+}
+
+async fn collect_storage(
+    client: &holochain_client::AdminWebsocket,
+    data_root_path: Option<&Path>,
+    metrics: &mut MetricsText,
+) -> anyhow::Result<()> {
+    // Storage info is reported for every installed app, not just the enabled ones.
+    let apps = client
+        .list_apps(None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
+    let dna_apps = dna_usage(&apps)
+        .into_iter()
+        .map(|(dna, usage)| (dna, usage.apps))
+        .collect::<BTreeMap<_, _>>();
+
+    let measured = match data_root_path {
+        Some(data_root_path) => dna_apps
+            .keys()
+            .map(|dna| Ok((dna.clone(), measure_database_sizes(data_root_path, dna)?)))
+            .collect::<anyhow::Result<HashMap<_, _>>>()?,
+        None => HashMap::new(),
+    };
+
+    let storage_info = client.storage_info().await?;
+    let infos = storage_info
+        .blobs
+        .iter()
+        .map(|b| match b {
+            StorageBlob::Dna(dna) => dna,
+        })
+        .collect::<Vec<_>>();
+    let attributed = attribute_storage(
+        &infos
+            .iter()
+            .map(|i| ReportedStorage::from(*i))
+            .collect::<Vec<_>>(),
+        &dna_apps,
+        &measured,
+    );
+
+    let mut rows = Vec::new();
+    for (i, (dna, dna_hash)) in infos.iter().zip(attributed).enumerate() {
+        // Several DNAs can be used by the same apps, so each series needs its own DNA label. Where
+        // the DNA can't be worked out, the position in the storage info keeps the series apart.
+        let dna_hash = dna_hash
+            .map(|h| h.to_string())
+            .unwrap_or_else(|| format!("unknown-{i}"));
+        let apps = dna.used_by.join(",");
+
+        for (database, data, on_disk) in [
+            (
+                "authored",
+                dna.authored_data_size,
+                dna.authored_data_size_on_disk,
+            ),
+            ("dht", dna.dht_data_size, dna.dht_data_size_on_disk),
+            ("cache", dna.cache_data_size, dna.cache_data_size_on_disk),
+        ] {
+            rows.push(StorageRow {
+                dna: dna_hash.clone(),
+                apps: apps.clone(),
+                database,
+                data: data as u64,
+                on_disk: on_disk as u64,
+            });
+        }
+    }
+
+    write_storage(metrics, &rows);
+
+    Ok(())
+}
+
+/// The storage used by one database of a DNA.
+struct StorageRow {
+    dna: String,
+    apps: String,
+    database: &'static str,
+    data: u64,
+    on_disk: u64,
+}
+
+impl StorageRow {
+    fn labels(&self) -> [(&str, &str); 3] {
+        [
+            ("dna", self.dna.as_str()),
+            ("apps", self.apps.as_str()),
+            ("database", self.database),
+        ]
+    }
+}
+
+fn write_storage(metrics: &mut MetricsText, rows: &[StorageRow]) {
+    metrics.family(
+        "holochain_storage_data_bytes",
+        "gauge",
+        "Size of the data in each database, by DNA and the apps that use it",
+    );
+    for row in rows {
+        metrics.sample("holochain_storage_data_bytes", &row.labels(), row.data);
+    }
+
+    metrics.family(
+        "holochain_storage_on_disk_bytes",
+        "gauge",
+        "Size on disk of each database, by DNA and the apps that use it",
+    );
+    for row in rows {
+        metrics.sample(
+            "holochain_storage_on_disk_bytes",
+            &row.labels(),
+            row.on_disk,
+        );
+    }
+}
+
+async fn collect_network(
+    client: &holochain_client::AdminWebsocket,
+    dnas: &BTreeSet<DnaHash>,
+    metrics: &mut MetricsText,
+) -> anyhow::Result<()> {
+    let mut agents = Vec::new();
+    for dna in dnas {
+        let count = client
+            .agent_info(Some(vec![dna.clone()]))
+            .await?
+            .into_iter()
+            .filter_map(|s| AgentInfoSigned::decode(&Ed25519Verifier, s.as_bytes()).ok())
+            .filter(|info| !info.is_tombstone)
+            .count();
+
+        agents.push((dna.to_string(), count));
+    }
+
+    write_per_dna(
+        metrics,
+        "holochain_agents",
+        "Agents known to the conductor for each DNA, including local agents",
+        &agents,
+        |count| *count,
+    );
+
+    let networks = client
+        .dump_network_metrics(None, true)
+        .await?
+        .into_iter()
+        .map(|(dna, network)| (dna.to_string(), network))
+        .collect::<Vec<_>>();
+
+    write_per_dna(
+        metrics,
+        "holochain_local_agents",
+        "Local agents joined to each DNA",
+        &networks,
+        |network| network.local_agents.len(),
+    );
+    write_per_dna(
+        metrics,
+        "holochain_fetch_pending_requests",
+        "Ops waiting to be fetched for each DNA",
+        &networks,
+        |network| network.fetch_state_summary.pending_requests.len(),
+    );
+    write_per_dna(
+        metrics,
+        "holochain_fetch_peers_on_backoff",
+        "Peers that fetching is backing off from for each DNA",
+        &networks,
+        |network| network.fetch_state_summary.peers_on_backoff.len(),
+    );
+    write_per_dna(
+        metrics,
+        "holochain_gossip_peers",
+        "Peers with gossip state for each DNA",
+        &networks,
+        |network| network.gossip_state_summary.peer_meta.len(),
+    );
+    write_per_dna(
+        metrics,
+        "holochain_gossip_accepted_rounds",
+        "Gossip rounds accepted from other peers that are in progress for each DNA",
+        &networks,
+        |network| network.gossip_state_summary.accepted_rounds.len(),
+    );
+    write_per_dna(
+        metrics,
+        "holochain_gossip_initiated_round",
+        "Whether a gossip round initiated by this conductor is in progress for each DNA",
+        &networks,
+        |network| {
+            if network.gossip_state_summary.initiated_round.is_some() {
+                1
+            } else {
+                0
+            }
+        },
+    );
+
+    let transport = client.dump_network_stats().await?.transport_stats;
+
+    metrics.family(
+        "holochain_transport_connections",
+        "gauge",
+        "Open transport connections",
+    );
+    metrics.sample(
+        "holochain_transport_connections",
+        &[],
+        transport.connections.len(),
+    );
+
+    metrics.family(
+        "holochain_transport_sent_bytes",
+        "gauge",
+        "Bytes sent over the open transport connections",
+    );
+    metrics.sample(
+        "holochain_transport_sent_bytes",
+        &[],
+        transport
+            .connections
+            .iter()
+            .map(|c| c.send_bytes)
+            .sum::<u64>(),
+    );
+
+    metrics.family(
+        "holochain_transport_received_bytes",
+        "gauge",
+        "Bytes received over the open transport connections",
+    );
+    metrics.sample(
+        "holochain_transport_received_bytes",
+        &[],
+        transport
+            .connections
+            .iter()
+            .map(|c| c.recv_bytes)
+            .sum::<u64>(),
+    );
+
+    Ok(())
+}
+
+fn collect_pending(
+    data_root_path: &Path,
+    key: &mut Option<Key>,
+    dnas: &BTreeSet<DnaHash>,
+    metrics: &mut MetricsText,
+) -> anyhow::Result<()> {
+    let mut pending = Vec::new();
+    let mut by_stage = Vec::new();
+    for dna in dnas {
+        let mut dht = open_holochain_database(
+            data_root_path,
+            &DbKind::Dht,
+            dna,
+            key.as_mut(),
+            OpenMode::ReadOnly,
+        )?;
+
+        let dna = dna.to_string();
+        pending.push((dna.clone(), count_pending_ops(&mut dht)?));
+
+        for (stage, count) in count_pending_ops_by_stage(&mut dht)? {
+            let stage = stage
+                .map(|stage| format!("{stage:?}"))
+                .unwrap_or_else(|| "None".to_string());
+
+            by_stage.push((dna.clone(), stage, count));
+        }
+    }
+
+    write_per_dna(
+        metrics,
+        "holochain_pending_ops",
+        "Ops in the DHT database that have not been integrated",
+        &pending,
+        |count| *count,
+    );
+
+    metrics.family(
+        "holochain_pending_ops_by_stage",
+        "gauge",
+        "Ops in the DHT database that have not been integrated, by validation stage",
+    );
+    for (dna, stage, count) in &by_stage {
+        metrics.sample(
+            "holochain_pending_ops_by_stage",
+            &[("dna", dna.as_str()), ("stage", stage.as_str())],
+            count,
+        );
+    }
+
+    Ok(())
+}
+
+/// Write a gauge family with one sample for each DNA.
+fn write_per_dna<T>(
+    metrics: &mut MetricsText,
+    name: &str,
+    help: &str,
+    rows: &[(String, T)],
+    value: impl Fn(&T) -> usize,
+) {
+    metrics.family(name, "gauge", help);
+    for (dna, row) in rows {
+        metrics.sample(name, &[("dna", dna.as_str())], value(row));
+    }
+}
+
+/// Builds an OpenMetrics text exposition.
+///
+/// Samples must be added straight after the family they belong to, and each family only once, so
+/// collect everything for a family before writing it.
+#[derive(Default)]
+struct MetricsText {
+    out: String,
+}
+
+impl MetricsText {
+    fn family(&mut self, name: &str, typ: &str, help: &str) {
+        let _ = writeln!(self.out, "# TYPE {name} {typ}");
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.out, "{name}");
+
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(self.out, "{{{labels}}}");
+        }
+
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+async fn serve(listener: TcpListener, latest: Arc<RwLock<String>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Failed to accept a metrics connection: {e}");
+                continue;
+            }
+        };
+
+        let latest = latest.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &latest).await {
+                eprintln!("Failed to serve metrics to {peer}: {e:#}");
+            }
+        });
+    }
+}
+
+/// Answer a single HTTP request, then close the connection.
+async fn respond(mut stream: TcpStream, latest: &RwLock<String>) -> anyhow::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() > 8 * 1024 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = latest
+                .read()
+                .map_err(|_| anyhow::anyhow!("Metrics lock poisoned"))?
+                .clone();
+
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that every sample follows its own family, and that no family is declared twice.
+    fn assert_grouped(text: &str) {
+        let mut declared = BTreeSet::new();
+        let mut current = None;
+
+        let mut lines = text.lines().peekable();
+        while let Some(line) = lines.next() {
+            if line == "# EOF" {
+                assert!(lines.peek().is_none(), "Content after # EOF");
+                return;
+            }
+
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let name = rest.split(' ').next().unwrap();
+                assert!(declared.insert(name), "{name} declared twice");
+                current = Some(name);
+            } else if let Some(rest) = line.strip_prefix("# HELP ") {
+                assert_eq!(current, rest.split(' ').next());
+            } else {
+                let name = line.split(['{', ' ']).next().unwrap();
+                assert_eq!(current, Some(name), "{name} sample outside its family");
+            }
+        }
+
+        panic!("Missing # EOF");
+    }
+
+    fn storage_row(dna: &str, database: &'static str) -> StorageRow {
+        StorageRow {
+            dna: dna.to_string(),
+            apps: "app".to_string(),
+            database,
+            data: 10,
+            on_disk: 20,
+        }
+    }
+
+    #[test]
+    fn storage_families_are_grouped() {
+        let mut metrics = MetricsText::default();
+        write_storage(
+            &mut metrics,
+            &[
+                storage_row("dna-1", "authored"),
+                storage_row("dna-1", "dht"),
+                storage_row("dna-2", "authored"),
+            ],
+        );
+        let text = metrics.finish();
+
+        assert_grouped(&text);
+        assert_eq!(
+            "# TYPE holochain_storage_data_bytes gauge
+# HELP holochain_storage_data_bytes Size of the data in each database, by DNA and the apps that use it
+holochain_storage_data_bytes{dna=\"dna-1\",apps=\"app\",database=\"authored\"} 10
+holochain_storage_data_bytes{dna=\"dna-1\",apps=\"app\",database=\"dht\"} 10
+holochain_storage_data_bytes{dna=\"dna-2\",apps=\"app\",database=\"authored\"} 10
+# TYPE holochain_storage_on_disk_bytes gauge
+# HELP holochain_storage_on_disk_bytes Size on disk of each database, by DNA and the apps that use it
+holochain_storage_on_disk_bytes{dna=\"dna-1\",apps=\"app\",database=\"authored\"} 20
+holochain_storage_on_disk_bytes{dna=\"dna-1\",apps=\"app\",database=\"dht\"} 20
+holochain_storage_on_disk_bytes{dna=\"dna-2\",apps=\"app\",database=\"authored\"} 20
+# EOF
+",
+            text
+        );
+    }
+
+    #[test]
+    fn per_dna_families_are_grouped() {
+        let rows = [("dna-1".to_string(), 3), ("dna-2".to_string(), 4)];
+
+        let mut metrics = MetricsText::default();
+        write_per_dna(&mut metrics, "first", "First", &rows, |n| *n);
+        write_per_dna(&mut metrics, "second", "Second", &rows, |n| n * 2);
+        metrics.family("hc_ops_up", "gauge", "Up");
+        metrics.sample("hc_ops_up", &[], 1);
+        let text = metrics.finish();
+
+        assert_grouped(&text);
+        assert!(text.contains("second{dna=\"dna-2\"} 8\n"));
+        assert!(text.ends_with("hc_ops_up 1\n# EOF\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "sample outside its family")]
+    fn interleaved_samples_are_not_grouped() {
+        let mut metrics = MetricsText::default();
+        metrics.family("first", "gauge", "First");
+        metrics.family("second", "gauge", "Second");
+        metrics.sample("first", &[], 1);
+
+        assert_grouped(&metrics.finish());
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut metrics = MetricsText::default();
+        metrics.family("apps", "gauge", "Apps");
+        metrics.sample("apps", &[("apps", "a\"b\\c\nd")], 1);
+
+        assert_eq!(
+            "# TYPE apps gauge\n# HELP apps Apps\napps{apps=\"a\\\"b\\\\c\\nd\"} 1\n# EOF\n",
+            metrics.finish()
+        );
+    }
+}
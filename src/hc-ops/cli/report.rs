@@ -0,0 +1,633 @@
+use crate::cli::key::{database_name, list_databases};
+use crate::cli::{OutputFormat, PassphraseArgs, ReportArgs, ReportCommands};
+use crate::connect_admin_client;
+use crate::data::{Snapshot, list_snapshots};
+use crate::explore::{AsAnyhowPretty, list_app_cells, unlock_database_key};
+use crate::render::{
+    DatabasePages, DatabasePagesTable, Render, StorageReport, StorageReportTable, write_table,
+};
+use anyhow::Context;
+use diesel::SqliteConnection;
+use hc_ops::retrieve::{
+    DbKind, Feature, OpenMode, ReportedStorage, attribute_storage, database_path, get_page_stats,
+    measure_database_sizes, open_holochain_database, read_database_schema,
+};
+use holochain_conductor_api::{AppInfo, StorageBlob};
+use holochain_zome_types::prelude::{AgentPubKey, DnaHash, Timestamp};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use tabled::Tabled;
+
+pub(crate) const MICROS_PER_DAY: f64 = 86_400_000_000.0;
+
+pub(crate) async fn handle_report_command(
+    conn: &mut SqliteConnection,
+    args: ReportArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    match args.command {
+        ReportCommands::StorageTrend {
+            tag,
+            since,
+            threshold_mib,
+        } => {
+            let snapshots = list_snapshots(conn, &tag, since.map(|since| since.as_micros()))?;
+            if snapshots.is_empty() {
+                anyhow::bail!(
+                    "No storage snapshots recorded for {tag}, record some with `admin storage-info --record`"
+                );
+            }
+
+            let threshold = threshold_mib.map(|mib| (mib * 1024 * 1024) as i64);
+            let rows = storage_trend(&snapshots, threshold);
+            // The last row is for all DNAs, so has a point for each time snapshots were recorded.
+            let recordings = rows.last().map(|r| r.snapshots).unwrap_or_default();
+
+            rows.render(output, std::io::stdout())?;
+
+            if recordings < 2 {
+                output.note("Only one snapshot has been recorded, so growth can't be measured yet");
+            }
+        }
+        ReportCommands::Storage {
+            tag,
+            origin,
+            data_root_path,
+            passphrase,
+        } => {
+            storage_report(
+                conn,
+                &tag,
+                &origin,
+                data_root_path.as_deref(),
+                &passphrase,
+                output,
+            )
+            .await?;
+        }
+        ReportCommands::Compatibility {
+            passphrase,
+            data_root_path,
+        } => {
+            compatibility_report(&data_root_path, &passphrase, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Summarise the growth of each DNA, and of all DNAs together.
+///
+/// Snapshots of a DNA that couldn't be worked out are grouped by the apps that used the DNA
+/// instead. An app can have several such DNAs, so their sizes are summed for each recording.
+fn storage_trend(snapshots: &[Snapshot], threshold: Option<i64>) -> Vec<StorageTrendTable> {
+    let mut series = BTreeMap::<(Option<&str>, &str), (&str, BTreeMap<i64, i64>)>::new();
+    for snapshot in snapshots {
+        let key = match &snapshot.dna_hash {
+            Some(dna_hash) => (Some(dna_hash.as_str()), ""),
+            None => (None, snapshot.apps.as_str()),
+        };
+
+        // Label each DNA with the apps that used it most recently.
+        let (apps, points) = series.entry(key).or_default();
+        *apps = &snapshot.apps;
+        *points.entry(snapshot.taken_at).or_default() += snapshot.on_disk();
+    }
+
+    let mut rows = series
+        .into_iter()
+        .map(|((dna_hash, _), (apps, points))| {
+            StorageTrendTable::new(
+                dna_hash,
+                apps,
+                &points.into_iter().collect::<Vec<_>>(),
+                None,
+            )
+        })
+        .collect::<Vec<_>>();
+    rows.push(StorageTrendTable::new(
+        None,
+        "(all)",
+        &total_on_disk(snapshots),
+        threshold,
+    ));
+
+    rows
+}
+
+/// The total size on disk of all DNAs, as `(taken_at, on_disk)` for each time snapshots were
+/// recorded.
+pub(crate) fn total_on_disk(snapshots: &[Snapshot]) -> Vec<(i64, i64)> {
+    let mut totals = BTreeMap::<i64, i64>::new();
+    for snapshot in snapshots {
+        *totals.entry(snapshot.taken_at).or_default() += snapshot.on_disk();
+    }
+
+    totals.into_iter().collect()
+}
+
+#[derive(Tabled, Serialize)]
+struct StorageTrendTable {
+    dna_hash: String,
+    apps: String,
+    snapshots: usize,
+    first_taken_at: String,
+    last_taken_at: String,
+    on_disk: String,
+    growth_per_day: String,
+    crosses_threshold_at: String,
+}
+
+impl StorageTrendTable {
+    /// Summarise a series of `(taken_at, on_disk)` points, which must be in time order and
+    /// non-empty.
+    fn new(
+        dna_hash: Option<&str>,
+        apps: &str,
+        points: &[(i64, i64)],
+        threshold: Option<i64>,
+    ) -> Self {
+        let (first_taken_at, _) = points[0];
+        let (last_taken_at, on_disk) = points[points.len() - 1];
+        let growth = growth_per_day(points);
+
+        let crosses_threshold_at = match (threshold, growth) {
+            (None, _) => "-".to_string(),
+            (Some(threshold), _) if on_disk >= threshold => "Already over".to_string(),
+            (Some(threshold), Some(growth)) if growth > 0.0 => {
+                let days = (threshold - on_disk) as f64 / growth;
+                Timestamp(last_taken_at + (days * MICROS_PER_DAY) as i64).to_string()
+            }
+            (Some(_), _) => "Not growing".to_string(),
+        };
+
+        Self {
+            dna_hash: dna_hash.unwrap_or("-").to_string(),
+            apps: apps.to_string(),
+            snapshots: points.len(),
+            first_taken_at: Timestamp(first_taken_at).to_string(),
+            last_taken_at: Timestamp(last_taken_at).to_string(),
+            on_disk: human_bytes::human_bytes(on_disk as f64),
+            growth_per_day: match growth {
+                Some(growth) if growth < 0.0 => {
+                    format!("-{}", human_bytes::human_bytes(-growth))
+                }
+                Some(growth) => human_bytes::human_bytes(growth),
+                None => "-".to_string(),
+            },
+            crosses_threshold_at,
+        }
+    }
+}
+
+/// The least squares growth rate, in bytes per day.
+///
+/// Returns `None` unless the points span some time.
+pub(crate) fn growth_per_day(points: &[(i64, i64)]) -> Option<f64> {
+    let (first_taken_at, _) = points.first()?;
+
+    // Relative to the first point, in days, to keep the sums small.
+    let xs = points
+        .iter()
+        .map(|(taken_at, _)| (taken_at - first_taken_at) as f64 / MICROS_PER_DAY)
+        .collect::<Vec<_>>();
+    let ys = points
+        .iter()
+        .map(|(_, on_disk)| *on_disk as f64)
+        .collect::<Vec<_>>();
+
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let covariance = xs
+        .iter()
+        .zip(&ys)
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let variance = xs.iter().map(|x| (x - mean_x).powi(2)).sum::<f64>();
+
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// How a DNA is used by the installed apps.
+pub(crate) struct DnaUsage {
+    pub apps: BTreeSet<String>,
+    /// As `<app>/<role>`, or `<app>/<clone id>` for clone cells.
+    pub roles: Vec<String>,
+    /// The agents with a cell for the DNA, each of which has its own authored database.
+    pub agents: Vec<AgentPubKey>,
+}
+
+pub(crate) fn dna_usage(apps: &[AppInfo]) -> BTreeMap<DnaHash, DnaUsage> {
+    let mut out = BTreeMap::<DnaHash, DnaUsage>::new();
+
+    for cell in list_app_cells(apps) {
+        let usage = out
+            .entry(cell.cell_id.dna_hash().clone())
+            .or_insert_with(|| DnaUsage {
+                apps: BTreeSet::new(),
+                roles: Vec::new(),
+                agents: Vec::new(),
+            });
+
+        usage.apps.insert(cell.app_id.to_string());
+        usage.roles.push(format!(
+            "{}/{}",
+            cell.app_id,
+            cell.clone_id.as_deref().unwrap_or(cell.role_name)
+        ));
+        if !usage.agents.contains(cell.cell_id.agent_pubkey()) {
+            usage.agents.push(cell.cell_id.agent_pubkey().clone());
+        }
+    }
+
+    out
+}
+
+async fn storage_report(
+    conn: &mut SqliteConnection,
+    tag: &str,
+    origin: &str,
+    data_root_path: Option<&Path>,
+    passphrase: &PassphraseArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let (client, _) = connect_admin_client(conn, tag, origin).await?;
+
+    let apps = client
+        .list_apps(None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to list apps: {e:?}"))?;
+    let usage = dna_usage(&apps);
+
+    let storage_info = client.storage_info().await?;
+    let infos = storage_info
+        .blobs
+        .iter()
+        .map(|b| match b {
+            StorageBlob::Dna(dna) => dna,
+        })
+        .collect::<Vec<_>>();
+
+    let measured = match data_root_path {
+        Some(data_root_path) => usage
+            .keys()
+            .map(|dna| Ok((dna.clone(), measure_database_sizes(data_root_path, dna)?)))
+            .collect::<anyhow::Result<HashMap<_, _>>>()?,
+        None => HashMap::new(),
+    };
+
+    let attributed = attribute_storage(
+        &infos
+            .iter()
+            .map(|i| ReportedStorage::from(*i))
+            .collect::<Vec<_>>(),
+        &usage
+            .iter()
+            .map(|(dna, usage)| (dna.clone(), usage.apps.clone()))
+            .collect(),
+        &measured,
+    );
+
+    let dnas = infos
+        .iter()
+        .zip(&attributed)
+        .map(|(info, dna)| StorageReport {
+            dna: dna.clone(),
+            roles: dna
+                .as_ref()
+                .and_then(|d| usage.get(d))
+                .map(|u| u.roles.clone())
+                .unwrap_or_else(|| info.used_by.clone()),
+            authored_on_disk: info.authored_data_size_on_disk as u64,
+            dht_on_disk: info.dht_data_size_on_disk as u64,
+            cache_on_disk: info.cache_data_size_on_disk as u64,
+            used: (info.authored_data_size + info.dht_data_size + info.cache_data_size) as u64,
+            measured_on_disk: dna
+                .as_ref()
+                .and_then(|d| measured.get(d))
+                .map(|m| m.total()),
+        })
+        .collect::<Vec<_>>();
+
+    let databases = match data_root_path {
+        Some(data_root_path) => database_pages(data_root_path, &usage, passphrase)?,
+        None => Vec::new(),
+    };
+
+    match output {
+        OutputFormat::Table => {
+            write_table(
+                &dnas
+                    .iter()
+                    .map(Into::into)
+                    .collect::<Vec<StorageReportTable>>(),
+                std::io::stdout(),
+            )?;
+            if !databases.is_empty() {
+                write_table(
+                    &databases
+                        .iter()
+                        .map(Into::into)
+                        .collect::<Vec<DatabasePagesTable>>(),
+                    std::io::stdout(),
+                )?;
+            }
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            output.write_value(
+                &serde_json::json!({ "dnas": dnas, "databases": databases }),
+                std::io::stdout(),
+            )?;
+        }
+    }
+
+    if attributed.iter().any(Option::is_none) && data_root_path.is_none() {
+        output.note(
+            "Some storage could not be attributed to a DNA, pass --data-root-path to match by size",
+        );
+    }
+
+    Ok(())
+}
+
+/// Page and freelist stats for every database of the DNAs in use.
+fn database_pages(
+    data_root_path: &Path,
+    usage: &BTreeMap<DnaHash, DnaUsage>,
+    passphrase: &PassphraseArgs,
+) -> anyhow::Result<Vec<DatabasePages>> {
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let mut out = Vec::new();
+    for (dna, usage) in usage {
+        let kinds = usage
+            .agents
+            .iter()
+            .map(|agent| DbKind::Authored(agent.clone()))
+            .chain([DbKind::Dht, DbKind::Cache]);
+
+        for kind in kinds {
+            if !database_path(data_root_path, &kind, dna).is_file() {
+                continue;
+            }
+
+            let mut db = open_holochain_database(
+                data_root_path,
+                &kind,
+                dna,
+                key.as_mut(),
+                OpenMode::ReadOnly,
+            )?;
+            let database = match &kind {
+                DbKind::Authored(agent) => format!("authored {agent}"),
+                DbKind::Dht => "dht".to_string(),
+                DbKind::Cache => "cache".to_string(),
+            };
+
+            out.push(DatabasePages::new(dna, database, get_page_stats(&mut db)?));
+        }
+    }
+
+    Ok(out)
+}
+
+#[derive(Tabled, Serialize)]
+struct DatabaseSchemaTable {
+    database: String,
+    schema_version: i64,
+    missing: String,
+    unknown: String,
+}
+
+#[derive(Tabled, Serialize)]
+struct FeatureSupportTable {
+    feature: String,
+    supported: bool,
+    detail: String,
+}
+
+/// Read the schema of every database in a data directory, and check each feature against the
+/// databases that it reads.
+fn compatibility_report(
+    data_root_path: &Path,
+    passphrase: &PassphraseArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let databases = list_databases(data_root_path, None, &[])?;
+    if databases.is_empty() {
+        anyhow::bail!("No databases found in {}", data_root_path.display());
+    }
+
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let mut schemas = Vec::new();
+    for (kind, dna) in databases {
+        let name = database_name(&kind, &dna);
+        let mut db = open_holochain_database(
+            data_root_path,
+            &kind,
+            &dna,
+            key.as_mut(),
+            OpenMode::ReadOnly,
+        )
+        .into_anyhow()
+        .with_context(|| format!("Failed to open {name}"))?;
+        let schema = read_database_schema(&mut db)
+            .into_anyhow()
+            .with_context(|| format!("Failed to read the schema of {name}"))?;
+
+        schemas.push((name, kind, schema));
+    }
+
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            "-".to_string()
+        } else {
+            items.join(", ")
+        }
+    };
+
+    let features = Feature::ALL
+        .into_iter()
+        .map(|feature| {
+            let mut checked = 0;
+            let mut failures = Vec::new();
+            for (name, kind, schema) in &schemas {
+                if !feature.reads(kind) {
+                    continue;
+                }
+
+                checked += 1;
+                if let Err(missing) = schema.check(feature) {
+                    failures.push(format!("{name} is missing {}", missing.join(", ")));
+                }
+            }
+
+            FeatureSupportTable {
+                feature: feature.to_string(),
+                supported: failures.is_empty(),
+                detail: if failures.is_empty() {
+                    format!("{checked} databases checked")
+                } else {
+                    failures.join("; ")
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let databases = schemas
+        .into_iter()
+        .map(|(database, kind, schema)| {
+            // Only the tables that are read from this kind of database are expected in it.
+            let mut tables = Feature::ALL
+                .iter()
+                .filter(|f| f.reads(&kind))
+                .flat_map(|f| f.tables())
+                .copied()
+                .collect::<Vec<_>>();
+            tables.sort();
+            tables.dedup();
+
+            DatabaseSchemaTable {
+                database,
+                schema_version: schema.user_version,
+                missing: list(schema.missing(&tables)),
+                unknown: list(schema.unknown()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match output {
+        OutputFormat::Table => {
+            databases.render(output, std::io::stdout())?;
+            features.render(output, std::io::stdout())?;
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            output.write_value(
+                &serde_json::json!({ "databases": databases, "features": features }),
+                std::io::stdout(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: i64 = 1024 * 1024;
+    const DAY: i64 = MICROS_PER_DAY as i64;
+
+    fn snapshot(taken_at: i64, apps: &str, dna_hash: Option<&str>, on_disk: i64) -> Snapshot {
+        Snapshot {
+            id: 0,
+            tag: "test".to_string(),
+            taken_at,
+            apps: apps.to_string(),
+            authored: 0,
+            authored_on_disk: 0,
+            dht: on_disk,
+            dht_on_disk: on_disk,
+            cache: 0,
+            cache_on_disk: 0,
+            dna_hash: dna_hash.map(|h| h.to_string()),
+        }
+    }
+
+    #[test]
+    fn growth_of_a_straight_line() {
+        let points = [(0, 0), (DAY, MIB), (2 * DAY, 2 * MIB)];
+
+        assert_eq!(Some(MIB as f64), growth_per_day(&points));
+    }
+
+    #[test]
+    fn growth_is_a_best_fit() {
+        let points = [(0, 0), (DAY, 3 * MIB), (2 * DAY, 2 * MIB)];
+
+        let growth = growth_per_day(&points).unwrap();
+        assert!((growth - MIB as f64).abs() < 1.0, "{growth}");
+    }
+
+    #[test]
+    fn no_growth_without_time() {
+        assert_eq!(None, growth_per_day(&[]));
+        assert_eq!(None, growth_per_day(&[(DAY, MIB)]));
+        assert_eq!(None, growth_per_day(&[(DAY, MIB), (DAY, 2 * MIB)]));
+    }
+
+    #[test]
+    fn project_when_threshold_is_crossed() {
+        let points = [(0, 0), (DAY, MIB)];
+
+        let growing = StorageTrendTable::new(None, "app", &points, Some(3 * MIB));
+        assert_eq!(Timestamp(3 * DAY).to_string(), growing.crosses_threshold_at);
+
+        let over = StorageTrendTable::new(None, "app", &points, Some(MIB));
+        assert_eq!("Already over", over.crosses_threshold_at);
+
+        let shrinking = StorageTrendTable::new(None, "app", &[(0, MIB), (DAY, 0)], Some(MIB));
+        assert_eq!("Not growing", shrinking.crosses_threshold_at);
+
+        let no_threshold = StorageTrendTable::new(None, "app", &points, None);
+        assert_eq!("-", no_threshold.crosses_threshold_at);
+    }
+
+    #[test]
+    fn trend_per_dna() {
+        let snapshots = [
+            snapshot(0, "app", Some("dna-1"), MIB),
+            snapshot(0, "app", Some("dna-2"), MIB),
+            snapshot(0, "old", None, MIB),
+            snapshot(DAY, "app, other", Some("dna-1"), 2 * MIB),
+            snapshot(DAY, "app", Some("dna-2"), MIB),
+        ];
+
+        let rows = storage_trend(&snapshots, None);
+
+        assert_eq!(
+            vec![
+                ("-", "old", 1),
+                ("dna-1", "app, other", 2),
+                ("dna-2", "app", 2),
+                ("-", "(all)", 2),
+            ],
+            rows.iter()
+                .map(|r| (r.dna_hash.as_str(), r.apps.as_str(), r.snapshots))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(human_bytes::human_bytes(MIB as f64), rows[1].growth_per_day);
+        assert_eq!(human_bytes::human_bytes(0.0), rows[2].growth_per_day);
+    }
+
+    #[test]
+    fn unattributed_dnas_of_an_app_are_summed() {
+        let snapshots = [
+            snapshot(0, "app", None, MIB),
+            snapshot(0, "app", None, 10 * MIB),
+            snapshot(DAY, "app", None, 2 * MIB),
+            snapshot(DAY, "app", None, 10 * MIB),
+        ];
+
+        let rows = storage_trend(&snapshots, None);
+
+        assert_eq!(2, rows.len());
+        assert_eq!(
+            ("-", "app", 2),
+            (
+                rows[0].dna_hash.as_str(),
+                rows[0].apps.as_str(),
+                rows[0].snapshots
+            )
+        );
+        assert_eq!(human_bytes::human_bytes((12 * MIB) as f64), rows[0].on_disk);
+        assert_eq!(human_bytes::human_bytes(MIB as f64), rows[0].growth_per_day);
+        assert_eq!(rows[0].on_disk, rows[1].on_disk);
+        assert_eq!(rows[0].growth_per_day, rows[1].growth_per_day);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::cli::{OutputFormat, UnlockArgs};
+use crate::explore::{AsAnyhowPretty, read_passphrase};
+use anyhow::Context;
+use hc_ops::retrieve::{Key, load_database_key};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The socket path, relative to the XDG runtime directory.
+const AGENT_SOCKET: &str = "hc-ops/agent.sock";
+
+/// Sent before the key when the agent holds the key for the requested data directory.
+const KEY_FOLLOWS: u8 = 1;
+const NO_KEY: u8 = 0;
+
+/// How long either side waits for the other, so that a stalled connection can't block the agent.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest request line, including the newline, which is enough for any data directory path.
+const MAX_REQUEST_LEN: u64 = 4096;
+
+/// Derive the database key once, then hand it to other `hc-ops` commands that open databases in
+/// the same data directory, until the timeout.
+///
+/// The key is only served over a Unix socket in the user's runtime directory, which only the user
+/// can access.
+pub(crate) fn handle_unlock_command(args: UnlockArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let data_root_path = args
+        .data_root_path
+        .canonicalize()
+        .with_context(|| format!("No such data directory: {}", args.data_root_path.display()))?;
+
+    let passphrase = read_passphrase(&args.passphrase)?;
+    let Some(mut key) = load_database_key(&data_root_path, passphrase).into_anyhow()? else {
+        anyhow::bail!(
+            "No database key found in {}, the databases are not encrypted",
+            data_root_path.display()
+        );
+    };
+
+    let socket_path = xdg::BaseDirectories::new()
+        .place_runtime_file(AGENT_SOCKET)
+        .context("Failed to create the agent socket directory")?;
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            anyhow::bail!("An agent is already running at {}", socket_path.display());
+        }
+
+        std::fs::remove_file(&socket_path)?;
+    }
+
+    let listener = UnixListener::bind(&socket_path)
+        .with_context(|| format!("Failed to listen at {}", socket_path.display()))?;
+    std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))?;
+
+    output.note(format!(
+        "Holding the key for {} for {} seconds, at {}",
+        data_root_path.display(),
+        args.ttl,
+        socket_path.display()
+    ));
+
+    {
+        let socket_path = socket_path.clone();
+        let ttl = Duration::from_secs(args.ttl);
+        std::thread::spawn(move || {
+            std::thread::sleep(ttl);
+            let _ = std::fs::remove_file(&socket_path);
+            eprintln!("Key expired, exiting");
+            std::process::exit(0);
+        });
+    }
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| serve_key(stream, &data_root_path, &mut key));
+        if let Err(e) = result {
+            eprintln!("Failed to serve a key request: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Each request is the data directory path on one line, answered with the key if it matches.
+///
+/// Requests are answered one at a time, so a client that sends nothing or never ends its line is
+/// dropped after [REQUEST_TIMEOUT] or [MAX_REQUEST_LEN] bytes rather than holding up the others.
+fn serve_key(mut stream: UnixStream, data_root_path: &Path, key: &mut Key) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut requested = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_LEN)).read_line(&mut requested)?;
+    let Some(requested) = requested.strip_suffix('\n') else {
+        anyhow::bail!("Request was not a line of at most {MAX_REQUEST_LEN} bytes");
+    };
+
+    if PathBuf::from(requested) == data_root_path {
+        stream.write_all(&[KEY_FOLLOWS])?;
+        key.write_to(&mut stream)?;
+    } else {
+        stream.write_all(&[NO_KEY])?;
+    }
+
+    Ok(())
+}
+
+/// Ask a running agent for the key to a data directory.
+///
+/// Returns `None` if there is no agent, or it holds the key for a different data directory.
+pub(crate) fn request_key(data_root_path: &Path) -> Option<Key> {
+    let socket_path = xdg::BaseDirectories::new().find_runtime_file(AGENT_SOCKET)?;
+    let data_root_path = data_root_path.canonicalize().ok()?;
+
+    let request = || -> anyhow::Result<Option<Key>> {
+        let mut stream = UnixStream::connect(&socket_path)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        writeln!(stream, "{}", data_root_path.display())?;
+
+        let mut status = [NO_KEY];
+        stream.read_exact(&mut status)?;
+        if status[0] != KEY_FOLLOWS {
+            return Ok(None);
+        }
+
+        Ok(Some(Key::read_from(&mut stream)?))
+    };
+
+    match request() {
+        Ok(key) => {
+            if key.is_some() {
+                eprintln!("Using the database key held by the hc-ops agent");
+            }
+            key
+        }
+        Err(e) => {
+            eprintln!("Failed to get the database key from the hc-ops agent: {e:#}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> Key {
+        Key::read_from(
+            &[7; sodoken::secretbox::XSALSA_KEYBYTES + sodoken::argon2::ARGON2_ID_SALTBYTES][..],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn serve_key_for_the_held_data_directory() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        writeln!(client, "/data/root").unwrap();
+
+        serve_key(server, Path::new("/data/root"), &mut test_key()).unwrap();
+
+        let mut status = [NO_KEY];
+        client.read_exact(&mut status).unwrap();
+        assert_eq!(KEY_FOLLOWS, status[0]);
+        Key::read_from(&mut client).unwrap();
+    }
+
+    #[test]
+    fn no_key_for_another_data_directory() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        writeln!(client, "/other/root").unwrap();
+
+        serve_key(server, Path::new("/data/root"), &mut test_key()).unwrap();
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        assert_eq!(vec![NO_KEY], response);
+    }
+
+    #[test]
+    fn overlong_request_is_dropped() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client
+            .write_all(&vec![b'a'; MAX_REQUEST_LEN as usize + 1])
+            .unwrap();
+
+        assert!(serve_key(server, Path::new("/data/root"), &mut test_key()).is_err());
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        let (_client, server) = UnixStream::pair().unwrap();
+
+        let started = std::time::Instant::now();
+        assert!(serve_key(server, Path::new("/data/root"), &mut test_key()).is_err());
+        assert!(started.elapsed() < REQUEST_TIMEOUT * 2);
+    }
+}
@@ -1,24 +1,55 @@
-use crate::cli::{CompareArgs, CompareCommands};
+use crate::cli::{CompareArgs, CompareCommands, CompareLiveArgs, OutputFormat, PassphraseArgs};
+use crate::connect_admin_client;
+use crate::explore::{
+    AsAnyhowPretty, CellSelector, require_feature, resolve_dna, resolve_offline_dna,
+    unlock_database_key,
+};
+use crate::export::{is_archive, read_binary_section, read_manifest};
 use crate::render::Render;
+use crate::slice_file::parse_slice_hash_file;
 use anyhow::Context;
 use base64::Engine;
-use hc_ops::retrieve::SliceHash;
+use diesel::SqliteConnection;
+use hc_ops::HcOpsResult;
+use hc_ops::retrieve::{
+    ChainOp, DbDhtOp, DbKind, DbOpType, DhtMeta, Feature, OpenMode, SliceHash, ValidationStatus,
+    discover_cells, get_ops_in_slice, get_ops_with_authors, get_slice_hashes,
+    open_holochain_database, read_database_schema, time_bounds_for_slice_index,
+};
+use holo_hash::{AnyLinkableHash, DhtOpHash};
+use holochain_zome_types::prelude::{AgentPubKey, DnaHash, SignedAction};
+use kitsune2_api::DhtArc;
 use nom::Parser;
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_until};
 use nom::character::complete::{char, digit1, space1};
 use nom::combinator::map_res;
 use nom::multi::many1;
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 use tabled::Tabled;
 
-pub fn handle_compare_command(args: CompareArgs) -> anyhow::Result<()> {
+pub async fn handle_compare_command(
+    conn: &mut SqliteConnection,
+    args: CompareArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     match args.command {
         CompareCommands::SliceHashes {
             our_file,
             their_file,
-        } => compare_slice_hash_files(our_file, their_file)
+        } => compare_slice_hash_files(our_file, their_file, output)
+            .map_err(|e| anyhow::anyhow!("Failed to compare slice hashes: {}", e))?,
+        CompareCommands::Ops {
+            ours,
+            theirs,
+            dna,
+            passphrase,
+        } => compare_ops(ours, theirs, dna.map(Into::into), &passphrase, output)
+            .map_err(|e| anyhow::anyhow!("Failed to compare ops: {}", e))?,
+        CompareCommands::Live(args) => compare_live(conn, args, output)
+            .await
             .map_err(|e| anyhow::anyhow!("Failed to compare slice hashes: {}", e))?,
     }
 
@@ -28,106 +59,293 @@ pub fn handle_compare_command(args: CompareArgs) -> anyhow::Result<()> {
 fn compare_slice_hash_files(
     our_file: impl AsRef<Path>,
     their_file: impl AsRef<Path>,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
-    #[derive(Tabled)]
-    struct SliceHashDiffTable {
-        pub dht_arc: String,
-        pub slice_index: u64,
-        pub diff: String,
+    let (our_dna, our_hashes) = load_hash_file(our_file)?;
+    let (their_dna, their_hashes) = load_hash_file(their_file)?;
+
+    if let (Some(our_dna), Some(their_dna)) = (&our_dna, &their_dna)
+        && our_dna != their_dna
+    {
+        anyhow::bail!("Cannot compare different DNAs: {our_dna} and {their_dna}");
     }
 
-    let our_hashes = load_hash_file(our_file)?;
-    let their_hashes = load_hash_file(their_file)?;
+    let diff_table = diff_slice_hashes(&our_hashes, &their_hashes, "file");
+
+    if diff_table.is_empty() && !output.is_structured() {
+        println!("No differences found between the two files.");
+    } else {
+        diff_table.render(output, std::io::stdout())?
+    }
+
+    Ok(())
+}
+
+async fn compare_live(
+    conn: &mut SqliteConnection,
+    args: CompareLiveArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let dna: Option<DnaHash> = args.dna.map(Into::into);
 
-    let our_diffable = our_hashes
+    let (our_client, _) = connect_admin_client(conn, &args.ours, &args.origin).await?;
+    let dna = resolve_dna(
+        &our_client,
+        &CellSelector {
+            app: args.app.clone(),
+            role: args.role.clone(),
+            clone_id: args.clone_id.clone(),
+            dna,
+            agent: None,
+        },
+    )
+    .await?;
+
+    let their_data_root = if Path::new(&args.theirs).is_dir() {
+        let data_root = PathBuf::from(&args.theirs);
+        if !discover_cells(&data_root)
+            .into_anyhow()?
+            .iter()
+            .any(|c| c.dna_hash == dna)
+        {
+            anyhow::bail!("DNA not found in their data directory: {dna}");
+        }
+
+        data_root
+    } else {
+        let data_root = args.their_data_root.ok_or_else(|| {
+            anyhow::anyhow!("--their-data-root is required when --theirs is a tag")
+        })?;
+
+        let (their_client, _) = connect_admin_client(conn, &args.theirs, &args.origin).await?;
+        resolve_dna(
+            &their_client,
+            &CellSelector {
+                app: args.app.clone(),
+                dna: Some(dna.clone()),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        data_root
+    };
+
+    let our_hashes =
+        load_live_slice_hashes(&args.our_data_root, &dna, &args.passphrase, "our", output)?;
+    let their_hashes = load_live_slice_hashes(
+        &their_data_root,
+        &dna,
+        &args.their_passphrase.or(&args.passphrase),
+        "their",
+        output,
+    )?;
+
+    let diff_table = diff_slice_hashes(&our_hashes, &their_hashes, "node");
+
+    if diff_table.is_empty() && !output.is_structured() {
+        println!("No differences found between the two nodes.");
+    } else {
+        diff_table.render(output, std::io::stdout())?
+    }
+
+    let summary = sync_summary(&our_hashes, &their_hashes);
+    if summary.total_slices == 0 {
+        output.note("Neither node has any slice hashes yet.");
+    } else {
+        output.note(format!(
+            "{} of {} slices in sync, covering {:.1}% of the arc.",
+            summary.matching_slices,
+            summary.total_slices,
+            summary.arc_in_sync * 100.0
+        ));
+    }
+
+    Ok(())
+}
+
+/// Read the slice hashes from the DHT database in a data directory.
+fn load_live_slice_hashes(
+    data_root_path: &Path,
+    dna: &DnaHash,
+    passphrase: &PassphraseArgs,
+    label: &str,
+    output: OutputFormat,
+) -> anyhow::Result<Vec<SliceHash>> {
+    output.note(format!(
+        "Unlocking {label} databases at: {}",
+        data_root_path.display()
+    ));
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let mut dht = open_holochain_database(
+        data_root_path,
+        &DbKind::Dht,
+        dna,
+        key.as_mut(),
+        OpenMode::ReadOnly,
+    )
+    .context("Failed to open the DHT database")?;
+    require_feature(
+        &read_database_schema(&mut dht).into_anyhow()?,
+        &DbKind::Dht,
+        Feature::Slices,
+    )?;
+
+    get_slice_hashes(&mut dht).into_anyhow()
+}
+
+#[derive(Tabled, Serialize)]
+struct SliceHashDiffTable {
+    dht_arc: String,
+    slice_index: u64,
+    diff: String,
+}
+
+/// Describe every slice that is missing from one side or has a different hash.
+///
+/// The `source` names what is being compared, such as a file or a node.
+fn diff_slice_hashes(
+    ours: &[SliceHash],
+    theirs: &[SliceHash],
+    source: &str,
+) -> Vec<SliceHashDiffTable> {
+    let our_hashes = ours
         .iter()
-        .map(|oh| {
-            (
-                format!(
-                    "{}-{}-{}",
-                    oh.arc_start as u32, oh.arc_end as u32, oh.slice_index as u64
-                ),
-                oh,
-            )
-        })
+        .map(|h| (slice_key(h), &h.hash))
+        .collect::<HashMap<_, _>>();
+    let their_hashes = theirs
+        .iter()
+        .map(|h| (slice_key(h), &h.hash))
         .collect::<HashMap<_, _>>();
 
-    let our_keys = our_diffable.keys().collect::<HashSet<_>>();
+    differing_slices(ours, theirs)
+        .into_iter()
+        .map(|key| {
+            let (arc_start, arc_end, slice_index) = key;
 
-    let their_diffable = their_hashes
-        .iter()
-        .map(|oh| {
-            (
-                format!(
-                    "{}-{}-{}",
-                    oh.arc_start as u32, oh.arc_end as u32, oh.slice_index as u64
+            let diff = match (our_hashes.get(&key), their_hashes.get(&key)) {
+                (Some(our_hash), Some(their_hash)) => format!(
+                    "Different hashes: our hash = {}, their hash = {}",
+                    base64::prelude::BASE64_STANDARD.encode(our_hash),
+                    base64::prelude::BASE64_STANDARD.encode(their_hash)
                 ),
-                oh,
-            )
+                (Some(_), None) => format!("Only in our {source}"),
+                _ => format!("Only in their {source}"),
+            };
+
+            SliceHashDiffTable {
+                dht_arc: format!("{:?}", arc_start..arc_end),
+                slice_index,
+                diff,
+            }
         })
-        .collect::<HashMap<_, _>>();
+        .collect()
+}
 
-    let their_keys = &their_diffable.keys().collect::<HashSet<_>>();
+/// How much of the compared arc two nodes agree on.
+struct SyncSummary {
+    matching_slices: usize,
+    total_slices: usize,
+    /// The fraction of the arc, covered by either node, where every slice matches.
+    arc_in_sync: f64,
+}
 
-    let our_extra = our_keys.difference(their_keys).collect::<Vec<_>>();
+fn sync_summary(ours: &[SliceHash], theirs: &[SliceHash]) -> SyncSummary {
+    let all_slices = ours
+        .iter()
+        .chain(theirs)
+        .map(slice_key)
+        .collect::<BTreeSet<_>>();
+    let differing = differing_slices(ours, theirs);
 
-    let mut diff_table = Vec::new();
+    let all_arcs = all_slices
+        .iter()
+        .map(|(start, end, _)| (*start, *end))
+        .collect::<BTreeSet<_>>();
+    let differing_arcs = differing
+        .iter()
+        .map(|(start, end, _)| (*start, *end))
+        .collect::<BTreeSet<_>>();
 
-    for key in our_extra {
-        if let Some(oh) = our_diffable.get(*key) {
-            diff_table.push(SliceHashDiffTable {
-                dht_arc: format!("{:?}", (oh.arc_start as u32)..(oh.arc_end as u32)),
-                slice_index: oh.slice_index as u64,
-                diff: "Only in our file".to_string(),
-            });
-        }
+    // A location is only in sync if no arc that covers it has a differing slice.
+    let total_len = covered_len(&all_arcs);
+    let in_sync_len = total_len - covered_len(&differing_arcs);
+
+    SyncSummary {
+        matching_slices: all_slices.len() - differing.len(),
+        total_slices: all_slices.len(),
+        arc_in_sync: if total_len == 0 {
+            0.0
+        } else {
+            in_sync_len as f64 / total_len as f64
+        },
     }
+}
 
-    let their_extra = their_keys.difference(&our_keys).collect::<Vec<_>>();
+/// The number of locations covered by any of the arcs, counting overlapping locations once.
+///
+/// An arc that ends before it starts wraps around the end of the location space.
+fn covered_len(arcs: &BTreeSet<(u32, u32)>) -> u64 {
+    let mut ranges = arcs
+        .iter()
+        .flat_map(|&(start, end)| {
+            if start <= end {
+                vec![(start as u64, end as u64 + 1)]
+            } else {
+                vec![(start as u64, 1 << 32), (0, end as u64 + 1)]
+            }
+        })
+        .collect::<Vec<_>>();
+    ranges.sort();
 
-    for key in their_extra {
-        if let Some(oh) = their_diffable.get(*key) {
-            diff_table.push(SliceHashDiffTable {
-                dht_arc: format!("{:?}", (oh.arc_start as u32)..(oh.arc_end as u32)),
-                slice_index: oh.slice_index as u64,
-                diff: "Only in their file".to_string(),
-            });
+    let mut len = 0;
+    let mut covered_to = 0;
+    for (start, end) in ranges {
+        let start = start.max(covered_to);
+        if end > start {
+            len += end - start;
+            covered_to = end;
         }
     }
 
-    let common_keys = our_keys.intersection(their_keys).collect::<Vec<_>>();
+    len
+}
+
+/// Load a slice hash file, falling back to the table printed by `explore slice-hashes`.
+fn load_hash_file(path: impl AsRef<Path>) -> anyhow::Result<(Option<DnaHash>, Vec<SliceHash>)> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).context("Failed to load input file")?;
 
-    for key in common_keys {
-        if let (Some(oh1), Some(oh2)) = (our_diffable.get(*key), their_diffable.get(*key))
-            && oh1.hash != oh2.hash
-        {
-            diff_table.push(SliceHashDiffTable {
-                dht_arc: format!("{:?}", (oh1.arc_start as u32)..(oh1.arc_end as u32)),
-                slice_index: oh1.slice_index as u64,
-                diff: format!(
-                    "Different hashes: our hash = {}, their hash = {}",
-                    base64::prelude::BASE64_STANDARD.encode(&oh1.hash),
-                    base64::prelude::BASE64_STANDARD.encode(&oh2.hash)
-                ),
-            });
-        }
+    if let Some(file) = parse_slice_hash_file(&content)? {
+        let slice_hashes = file
+            .slice_hashes
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        return Ok((Some(file.dna_hash.into()), slice_hashes));
     }
 
-    if diff_table.is_empty() {
-        println!("No differences found between the two files.");
-    } else {
-        diff_table.render(std::io::stdout())?
+    let (slice_hashes, skipped) = parse_hash_table(&content);
+    for (line_number, line) in skipped {
+        eprintln!(
+            "Skipping line {line_number} of {} that could not be parsed: {line}",
+            path.display()
+        );
     }
 
-    Ok(())
+    Ok((None, slice_hashes))
 }
 
-fn load_hash_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<SliceHash>> {
+/// Parse the table printed by `explore slice-hashes`.
+///
+/// Also returns the line numbers and contents of any rows that couldn't be parsed, which are not
+/// borders or headers, so that they can be reported.
+fn parse_hash_table(content: &str) -> (Vec<SliceHash>, Vec<(usize, &str)>) {
     let mut out = Vec::new();
-    for line in std::fs::read_to_string(path)
-        .context("Failed to load input file")?
-        .lines()
-    {
+    let mut skipped = Vec::new();
+    for (line_index, line) in content.lines().enumerate() {
         let Ok((_, (_, _, start, _, end, _, _, index, _, hash))) = (
             many1(alt((space1, tag("├"), tag("│"), tag("┤")))),
             tag::<_, _, nom::error::Error<_>>("Arc("),
@@ -144,6 +362,10 @@ fn load_hash_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<SliceHash>> {
         )
             .parse(line)
         else {
+            if !is_table_decoration(line) {
+                skipped.push((line_index + 1, line));
+            }
+
             continue;
         };
 
@@ -155,5 +377,533 @@ fn load_hash_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<SliceHash>> {
         });
     }
 
-    Ok(out)
+    (out, skipped)
+}
+
+/// Borders, the header row and blank lines are expected in a printed table.
+fn is_table_decoration(line: &str) -> bool {
+    let line = line.trim();
+
+    line.is_empty() || line.contains("dht_arc") || !line.chars().any(char::is_alphanumeric)
+}
+
+/// The details of an op that are shown when it is held by one node but not the other.
+#[derive(Debug, Clone)]
+struct OpSummary {
+    typ: DbOpType,
+    basis: AnyLinkableHash,
+    author: AgentPubKey,
+    validation_status: Option<ValidationStatus>,
+}
+
+impl OpSummary {
+    fn new(op: &ChainOp<DhtMeta>, author: AgentPubKey) -> Self {
+        Self {
+            typ: op.typ,
+            basis: op.basis_hash.clone(),
+            author,
+            validation_status: op.validation_status,
+        }
+    }
+}
+
+/// Either the DHT database of a live data directory, or an archive written by `hc-ops export`.
+enum OpSource {
+    Database(Box<SqliteConnection>),
+    Archive {
+        slice_hashes: Vec<SliceHash>,
+        ops: HashMap<DhtOpHash, (ChainOp<DhtMeta>, AgentPubKey)>,
+    },
+}
+
+impl OpSource {
+    fn open(
+        path: &Path,
+        dna: Option<&DnaHash>,
+        passphrase: &PassphraseArgs,
+        label: &str,
+        output: OutputFormat,
+    ) -> anyhow::Result<(Self, DnaHash)> {
+        if is_archive(path) {
+            let manifest = read_manifest(path)?;
+
+            let dna_hash: DnaHash = manifest.dna_hash.clone().into();
+            let slice_hashes = read_binary_section::<SliceHash>(path, &manifest, "slice_hashes")?;
+
+            let authors = read_binary_section::<SignedAction>(path, &manifest, "dht_actions")?
+                .into_iter()
+                .map(|a| {
+                    let hash = holo_hash::ActionHash::with_data_sync(a.action());
+                    (hash.get_raw_39().to_vec(), a.action().author().clone())
+                })
+                .collect::<HashMap<_, _>>();
+
+            let ops = read_binary_section::<DbDhtOp>(path, &manifest, "dht_ops")?
+                .into_iter()
+                .map(ChainOp::<DhtMeta>::try_from)
+                .collect::<HcOpsResult<Vec<_>>>()
+                .into_anyhow()?
+                .into_iter()
+                .filter_map(|op| {
+                    let author = authors.get(&op.action_hash)?.clone();
+                    Some((op.hash.clone(), (op, author)))
+                })
+                .collect();
+
+            Ok((OpSource::Archive { slice_hashes, ops }, dna_hash))
+        } else {
+            // Only the DHT database is read, which all agents of a DNA share.
+            let dna = resolve_offline_dna(path, dna)?;
+
+            output.note(format!(
+                "Unlocking {label} databases at: {}",
+                path.display()
+            ));
+            let mut key = unlock_database_key(path, passphrase)?;
+
+            let mut dht =
+                open_holochain_database(path, &DbKind::Dht, &dna, key.as_mut(), OpenMode::ReadOnly)
+                    .context("Failed to open the DHT database")?;
+            let schema = read_database_schema(&mut dht).into_anyhow()?;
+            require_feature(&schema, &DbKind::Dht, Feature::Ops)?;
+            require_feature(&schema, &DbKind::Dht, Feature::Slices)?;
+
+            Ok((OpSource::Database(Box::new(dht)), dna))
+        }
+    }
+
+    fn slice_hashes(&mut self) -> anyhow::Result<Vec<SliceHash>> {
+        match self {
+            OpSource::Database(dht) => get_slice_hashes(dht).into_anyhow(),
+            OpSource::Archive { slice_hashes, .. } => Ok(std::mem::take(slice_hashes)),
+        }
+    }
+
+    /// The ops that make up a slice hash.
+    fn ops_in_slice(
+        &mut self,
+        arc_start: u32,
+        arc_end: u32,
+        slice_index: u64,
+    ) -> anyhow::Result<BTreeSet<DhtOpHash>> {
+        match self {
+            OpSource::Database(dht) => Ok(get_ops_in_slice(dht, arc_start, arc_end, slice_index)
+                .into_anyhow()?
+                .into_iter()
+                .map(|op| op.hash)
+                .collect()),
+            OpSource::Archive { ops, .. } => Ok(archived_ops_in_slice(
+                ops.values().map(|(op, _)| op),
+                arc_start,
+                arc_end,
+                slice_index,
+            )),
+        }
+    }
+
+    /// Look up ops by hash, whether or not they are integrated. Ops that aren't held are left out.
+    fn find_ops(&mut self, hashes: &[DhtOpHash]) -> anyhow::Result<HashMap<DhtOpHash, OpSummary>> {
+        match self {
+            OpSource::Database(dht) => Ok(get_ops_with_authors(dht, hashes)
+                .into_anyhow()?
+                .into_iter()
+                .map(|(op, author)| (op.hash.clone(), OpSummary::new(&op, author)))
+                .collect()),
+            OpSource::Archive { ops, .. } => Ok(hashes
+                .iter()
+                .filter_map(|hash| {
+                    let (op, author) = ops.get(hash)?;
+                    Some((hash.clone(), OpSummary::new(op, author.clone())))
+                })
+                .collect()),
+        }
+    }
+}
+
+fn compare_ops(
+    ours: PathBuf,
+    theirs: PathBuf,
+    dna: Option<DnaHash>,
+    passphrase: &PassphraseArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    #[derive(Tabled, Serialize)]
+    struct OpDiffTable {
+        dht_arc: String,
+        slice_index: u64,
+        op_hash: String,
+        op_type: String,
+        basis: String,
+        author: String,
+        ours: String,
+        theirs: String,
+    }
+
+    let (mut ours, our_dna) = OpSource::open(&ours, dna.as_ref(), passphrase, "our", output)?;
+    let (mut theirs, their_dna) =
+        OpSource::open(&theirs, dna.as_ref(), passphrase, "their", output)?;
+
+    if our_dna != their_dna {
+        anyhow::bail!("Cannot compare different DNAs: {our_dna} and {their_dna}");
+    }
+
+    let slices = differing_slices(&ours.slice_hashes()?, &theirs.slice_hashes()?);
+
+    let mut diff_table = Vec::new();
+    for (arc_start, arc_end, slice_index) in slices {
+        let our_ops = ours.ops_in_slice(arc_start, arc_end, slice_index)?;
+        let their_ops = theirs.ops_in_slice(arc_start, arc_end, slice_index)?;
+
+        let differing = our_ops
+            .symmetric_difference(&their_ops)
+            .cloned()
+            .collect::<Vec<_>>();
+        let our_held = ours.find_ops(&differing)?;
+        let their_held = theirs.find_ops(&differing)?;
+
+        for hash in &differing {
+            let our_op = our_held.get(hash);
+            let their_op = their_held.get(hash);
+
+            let Some(summary) = our_op.or(their_op) else {
+                continue;
+            };
+
+            diff_table.push(OpDiffTable {
+                dht_arc: format!("{:?}", arc_start..arc_end),
+                slice_index,
+                op_hash: hash.to_string(),
+                op_type: format!("{:?}", summary.typ),
+                basis: summary.basis.to_string(),
+                author: summary.author.to_string(),
+                ours: held_status(our_op, our_ops.contains(hash)),
+                theirs: held_status(their_op, their_ops.contains(hash)),
+            });
+        }
+    }
+
+    if diff_table.is_empty() && !output.is_structured() {
+        println!("No differing ops found.");
+    } else {
+        diff_table.render(output, std::io::stdout())?
+    }
+
+    Ok(())
+}
+
+/// The archived ops that make up a slice hash, chosen the same way as [get_ops_in_slice] chooses
+/// them from a database.
+fn archived_ops_in_slice<'a>(
+    ops: impl Iterator<Item = &'a ChainOp<DhtMeta>>,
+    arc_start: u32,
+    arc_end: u32,
+    slice_index: u64,
+) -> BTreeSet<DhtOpHash> {
+    let arc = DhtArc::Arc(arc_start, arc_end);
+    let (start, end) = time_bounds_for_slice_index(slice_index);
+
+    ops.filter(|op| {
+        op.meta.when_integrated.is_some()
+            && arc.contains(op.storage_center_loc)
+            && op.authored_timestamp.as_micros() >= start.as_micros()
+            && op.authored_timestamp.as_micros() < end.as_micros()
+    })
+    .map(|op| op.hash.clone())
+    .collect()
+}
+
+/// Describe how one side holds an op, for ops that are only in one side's slice.
+fn held_status(op: Option<&OpSummary>, in_slice: bool) -> String {
+    match (op, in_slice) {
+        (None, _) => "Not held".to_string(),
+        (Some(op), true) => format!("{:?}", op.validation_status),
+        (Some(op), false) => format!("Held outside the slice, {:?}", op.validation_status),
+    }
+}
+
+/// The `(arc_start, arc_end, slice_index)` of every slice that is missing from one side or has a
+/// different hash.
+fn differing_slices(ours: &[SliceHash], theirs: &[SliceHash]) -> BTreeSet<(u32, u32, u64)> {
+    let our_hashes = ours
+        .iter()
+        .map(|h| (slice_key(h), &h.hash))
+        .collect::<HashMap<_, _>>();
+    let their_hashes = theirs
+        .iter()
+        .map(|h| (slice_key(h), &h.hash))
+        .collect::<HashMap<_, _>>();
+
+    our_hashes
+        .keys()
+        .chain(their_hashes.keys())
+        .filter(|k| our_hashes.get(*k) != their_hashes.get(*k))
+        .copied()
+        .collect()
+}
+
+fn slice_key(h: &SliceHash) -> (u32, u32, u64) {
+    (h.arc_start as u32, h.arc_end as u32, h.slice_index as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holochain_zome_types::prelude::Timestamp;
+
+    fn slice_hash(arc_start: u32, arc_end: u32, slice_index: u64, hash: u8) -> SliceHash {
+        SliceHash {
+            arc_start: arc_start as i32,
+            arc_end: arc_end as i32,
+            slice_index: slice_index as i64,
+            hash: vec![hash; 32],
+        }
+    }
+
+    fn archived_op(i: u8, storage_center_loc: u32, authored_timestamp: i64) -> ChainOp<DhtMeta> {
+        ChainOp {
+            hash: DhtOpHash::from_raw_36(vec![i; 36]),
+            typ: DbOpType::StoreRecord,
+            basis_hash: holo_hash::ActionHash::from_raw_36(vec![i; 36]).into(),
+            action_hash: holo_hash::ActionHash::from_raw_36(vec![i; 36])
+                .get_raw_39()
+                .to_vec(),
+            storage_center_loc,
+            authored_timestamp: Timestamp(authored_timestamp),
+            validation_status: Some(ValidationStatus::Valid),
+            meta: DhtMeta {
+                require_receipt: false,
+                when_integrated: Some(Timestamp(authored_timestamp)),
+                validation_stage: None,
+                num_validation_attempts: None,
+                last_validation_attempt: None,
+            },
+        }
+    }
+
+    fn op_summary(validation_status: Option<ValidationStatus>) -> OpSummary {
+        OpSummary {
+            typ: DbOpType::StoreRecord,
+            basis: holo_hash::ActionHash::from_raw_36(vec![1; 36]).into(),
+            author: AgentPubKey::from_raw_36(vec![2; 36]),
+            validation_status,
+        }
+    }
+
+    #[test]
+    fn differing_slices_include_one_sided_and_changed_slices() {
+        let ours = [
+            slice_hash(0, 100, 0, 1),
+            slice_hash(0, 100, 1, 2),
+            slice_hash(0, 100, 2, 3),
+        ];
+        let theirs = [
+            slice_hash(0, 100, 0, 1),
+            slice_hash(0, 100, 1, 9),
+            slice_hash(0, 100, 3, 4),
+        ];
+
+        assert_eq!(
+            BTreeSet::from([(0, 100, 1), (0, 100, 2), (0, 100, 3)]),
+            differing_slices(&ours, &theirs)
+        );
+        assert!(differing_slices(&ours, &ours).is_empty());
+    }
+
+    #[test]
+    fn parse_printed_table_and_report_unparsed_rows() {
+        let hash = |byte: u8| base64::prelude::BASE64_STANDARD.encode(vec![byte; 32]);
+        let content = format!(
+            "╭──────────────────────┬─────────────┬──────╮
+│ dht_arc              │ slice_index │ hash │
+├──────────────────────┼─────────────┼──────┤
+│ Arc(0, 100)          │ 0           │ {} │
+│ Arc(0, 100)          │ x           │ {} │
+│ Arc(0, 100)          │ 2           │ not-base64! │
+│ Arc(4000000000, 100) │ 3           │ {} │
+╰──────────────────────┴─────────────┴──────╯
+
+",
+            hash(1),
+            hash(2),
+            hash(3)
+        );
+
+        let (slice_hashes, skipped) = parse_hash_table(&content);
+
+        assert_eq!(
+            vec![
+                slice_hash(0, 100, 0, 1),
+                slice_hash(4_000_000_000, 100, 3, 3)
+            ],
+            slice_hashes
+        );
+        assert_eq!(
+            vec![5, 6],
+            skipped
+                .iter()
+                .map(|(line_number, _)| *line_number)
+                .collect::<Vec<_>>()
+        );
+        assert!(skipped[1].1.contains("not-base64!"));
+    }
+
+    #[test]
+    fn table_borders_and_headers_are_decoration() {
+        assert!(is_table_decoration(""));
+        assert!(is_table_decoration("   "));
+        assert!(is_table_decoration("╭────┬────╮"));
+        assert!(is_table_decoration("├────┼────┤"));
+        assert!(is_table_decoration("│ dht_arc │ slice_index │ hash │"));
+
+        assert!(!is_table_decoration("│ Arc(0, 100) │ x │ AAAA │"));
+        assert!(!is_table_decoration("not a table"));
+    }
+
+    #[test]
+    fn diff_describes_changed_and_one_sided_slices() {
+        let ours = [
+            slice_hash(0, 100, 0, 1),
+            slice_hash(0, 100, 1, 2),
+            slice_hash(0, 100, 2, 3),
+        ];
+        let theirs = [
+            slice_hash(0, 100, 0, 1),
+            slice_hash(0, 100, 1, 9),
+            slice_hash(0, 100, 3, 4),
+        ];
+
+        let diff = diff_slice_hashes(&ours, &theirs, "file")
+            .into_iter()
+            .map(|row| (row.dht_arc, row.slice_index, row.diff))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (
+                    "0..100".to_string(),
+                    1,
+                    format!(
+                        "Different hashes: our hash = {}, their hash = {}",
+                        base64::prelude::BASE64_STANDARD.encode(vec![2; 32]),
+                        base64::prelude::BASE64_STANDARD.encode(vec![9; 32])
+                    )
+                ),
+                ("0..100".to_string(), 2, "Only in our file".to_string()),
+                ("0..100".to_string(), 3, "Only in their file".to_string()),
+            ],
+            diff
+        );
+        assert!(diff_slice_hashes(&ours, &ours, "file").is_empty());
+    }
+
+    #[test]
+    fn mismatched_slice_takes_its_arc_out_of_sync() {
+        let ours = [
+            slice_hash(0, 99, 0, 1),
+            slice_hash(0, 99, 1, 2),
+            slice_hash(100, 199, 0, 3),
+        ];
+        let theirs = [
+            slice_hash(0, 99, 0, 1),
+            slice_hash(0, 99, 1, 9),
+            slice_hash(100, 199, 0, 3),
+        ];
+
+        let summary = sync_summary(&ours, &theirs);
+
+        assert_eq!(2, summary.matching_slices);
+        assert_eq!(3, summary.total_slices);
+        assert_eq!(0.5, summary.arc_in_sync);
+    }
+
+    #[test]
+    fn arc_held_by_one_side_is_out_of_sync() {
+        let ours = [slice_hash(0, 99, 0, 1), slice_hash(100, 399, 0, 2)];
+        let theirs = [slice_hash(0, 99, 0, 1)];
+
+        let summary = sync_summary(&ours, &theirs);
+
+        assert_eq!(1, summary.matching_slices);
+        assert_eq!(2, summary.total_slices);
+        assert_eq!(0.25, summary.arc_in_sync);
+
+        let summary = sync_summary(&ours, &[]);
+        assert_eq!(0, summary.matching_slices);
+        assert_eq!(0.0, summary.arc_in_sync);
+        assert_eq!(0.0, sync_summary(&[], &[]).arc_in_sync);
+    }
+
+    #[test]
+    fn overlapping_arcs_are_counted_once() {
+        // 0..=99 matches, but half of it is also covered by 50..=149 which doesn't.
+        let ours = [slice_hash(0, 99, 0, 1), slice_hash(50, 149, 0, 2)];
+        let theirs = [slice_hash(0, 99, 0, 1), slice_hash(50, 149, 0, 9)];
+
+        let summary = sync_summary(&ours, &theirs);
+
+        assert_eq!(1, summary.matching_slices);
+        assert_eq!(2, summary.total_slices);
+        assert_eq!(50.0 / 150.0, summary.arc_in_sync);
+
+        // A matching arc that wraps past the end of the location space, overlapping a full arc
+        // that matches too.
+        let wrapping = [
+            slice_hash(u32::MAX - 99, 99, 0, 1),
+            slice_hash(0, u32::MAX, 0, 2),
+        ];
+        assert_eq!(1.0, sync_summary(&wrapping, &wrapping).arc_in_sync);
+    }
+
+    #[test]
+    fn covered_len_merges_overlapping_and_wrapping_arcs() {
+        assert_eq!(0, covered_len(&BTreeSet::new()));
+        assert_eq!(100, covered_len(&BTreeSet::from([(0, 99)])));
+        assert_eq!(150, covered_len(&BTreeSet::from([(0, 99), (50, 149)])));
+        assert_eq!(
+            200,
+            covered_len(&BTreeSet::from([(0, 99), (u32::MAX - 99, 20)]))
+        );
+        assert_eq!(
+            1 << 32,
+            covered_len(&BTreeSet::from([(0, u32::MAX), (10, 5)]))
+        );
+    }
+
+    #[test]
+    fn describe_held_status() {
+        assert_eq!("Not held", held_status(None, false));
+        assert_eq!(
+            "Some(Valid)",
+            held_status(Some(&op_summary(Some(ValidationStatus::Valid))), true)
+        );
+        assert_eq!(
+            "Held outside the slice, None",
+            held_status(Some(&op_summary(None)), false)
+        );
+    }
+
+    #[test]
+    fn archived_ops_in_slice_must_be_integrated() {
+        let (start, end) = time_bounds_for_slice_index(1);
+
+        let in_slice = archived_op(1, 3_000_000_000, start.as_micros());
+
+        // Validated but not yet integrated, so not counted by the conductor either.
+        let mut not_integrated = archived_op(2, 3_000_000_000, start.as_micros());
+        not_integrated.meta.when_integrated = None;
+
+        // Integrated without a validation status is still counted.
+        let mut no_status = archived_op(3, 3_000_000_000, start.as_micros());
+        no_status.validation_status = None;
+
+        let outside_arc = archived_op(4, 100, start.as_micros());
+        let next_slice = archived_op(5, 3_000_000_000, end.as_micros());
+
+        let ops = [in_slice, not_integrated, no_status, outside_arc, next_slice];
+
+        assert_eq!(
+            BTreeSet::from([ops[0].hash.clone(), ops[2].hash.clone()]),
+            archived_ops_in_slice(ops.iter(), 1 << 31, u32::MAX, 1)
+        );
+    }
 }
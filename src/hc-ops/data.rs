@@ -123,3 +123,77 @@ pub fn delete_agent_tag(conn: &mut SqliteConnection, tag: &str) -> anyhow::Resul
 
     Ok(())
 }
+
+/// The storage used by one DNA at a point in time.
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::snapshot)]
+#[diesel(primary_key(id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Snapshot {
+    #[allow(dead_code)]
+    pub id: i32,
+    #[allow(dead_code)]
+    pub tag: String,
+    /// Microseconds since the Unix epoch.
+    pub taken_at: i64,
+    pub apps: String,
+    pub authored: i64,
+    pub authored_on_disk: i64,
+    pub dht: i64,
+    pub dht_on_disk: i64,
+    pub cache: i64,
+    pub cache_on_disk: i64,
+    /// The DNA that the storage is for, if it could be worked out from the apps that use it.
+    pub dna_hash: Option<String>,
+}
+
+impl Snapshot {
+    pub fn on_disk(&self) -> i64 {
+        self.authored_on_disk + self.dht_on_disk + self.cache_on_disk
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::snapshot)]
+pub struct NewSnapshot<'a> {
+    pub tag: &'a str,
+    pub taken_at: i64,
+    pub apps: String,
+    pub authored: i64,
+    pub authored_on_disk: i64,
+    pub dht: i64,
+    pub dht_on_disk: i64,
+    pub cache: i64,
+    pub cache_on_disk: i64,
+    pub dna_hash: Option<String>,
+}
+
+pub fn insert_snapshots(
+    conn: &mut SqliteConnection,
+    snapshots: &[NewSnapshot],
+) -> anyhow::Result<()> {
+    diesel::insert_into(schema::snapshot::table)
+        .values(snapshots)
+        .execute(conn)
+        .context("Failed to record snapshot")?;
+
+    Ok(())
+}
+
+/// List the snapshots for a conductor tag, oldest first, optionally only those taken since a time.
+pub fn list_snapshots(
+    conn: &mut SqliteConnection,
+    tag: &str,
+    since: Option<i64>,
+) -> anyhow::Result<Vec<Snapshot>> {
+    let mut query = schema::snapshot::table
+        .filter(schema::snapshot::tag.eq(tag))
+        .order_by((schema::snapshot::taken_at, schema::snapshot::id))
+        .into_boxed();
+
+    if let Some(since) = since {
+        query = query.filter(schema::snapshot::taken_at.ge(since));
+    }
+
+    query.load(conn).context("Failed to load snapshots")
+}
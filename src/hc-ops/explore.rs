@@ -1,19 +1,39 @@
-use crate::render::{Render, SliceHashTable};
+use crate::cli::{ExploreCommands, OutputFormat, PassphraseArgs};
+use crate::render::{
+    AgentArcTable, ChainViolationTable, CoverageBucketTable, DiagnosedOpTable, DiagnosisGroupTable,
+    Render, SliceHashTable, SliceOpTable, SliceSummaryTable, WatchEventTable, write_table,
+};
+use crate::slice_file::write_slice_hash_file;
 use anyhow::Context;
 use diesel::SqliteConnection;
-use hc_ops::readable::{HumanReadable, HumanReadableDisplay};
+use hc_ops::readable::HumanReadable;
 use hc_ops::retrieve::{
-    AuthoredMeta, CacheMeta, ChainOp, DbKind, DhtMeta, get_agent_chain, get_all_actions,
-    get_all_dht_ops, get_all_entries, get_ops_by_action_hash, get_ops_by_entry_hash,
-    get_ops_in_slice, get_pending_ops, get_self_agent_chain, get_slice_hashes,
-    list_discovered_agents, load_database_key, open_holochain_database,
+    AgentArc, AuthoredMeta, CacheMeta, ChainOp, DEFAULT_COVERAGE_BUCKETS, DEFAULT_PAGE_SIZE,
+    DEFAULT_RETRY_THRESHOLD, DatabaseSchema, DbKind, DhtMeta, DiscoveredCell, Feature, HistoryNode,
+    Key, LinkState, OpenMode, ScanFilter, SliceSummary, Watcher, coverage_map, diagnose_validation,
+    discover_cells, get_agent_chain, get_entry_history, get_links_by_base, get_op_locations,
+    get_ops_by_action_hash, get_ops_by_entry_hash, get_ops_in_slice, get_pending_ops,
+    get_self_agent_chain, get_slice_hashes, get_warrants, iter_actions, iter_dht_ops, iter_entries,
+    list_discovered_agents, list_slices, load_database_key, open_holochain_database,
+    read_database_schema, resolve_warrant,
 };
+use hc_ops::verify::verify_chain;
 use hc_ops::{HcOpsError, HcOpsResult};
-use holo_hash::{ActionHash, ActionHashB64};
+use holo_hash::{
+    ActionHash, ActionHashB64, AnyDhtHash, AnyDhtHashB64, AnyLinkableHash, AnyLinkableHashB64,
+    EntryHash, EntryHashB64,
+};
 use holochain_conductor_api::{AppInfo, CellInfo};
-use holochain_zome_types::prelude::{AgentPubKey, AgentPubKeyB64, DnaHash, Entry, SignedAction};
+use holochain_zome_types::prelude::{
+    AgentPubKey, AgentPubKeyB64, CellId, DnaHash, Entry, SignedAction,
+};
+use kitsune2_api::{AgentInfoSigned, DhtArc};
+use kitsune2_core::Ed25519Verifier;
+use std::collections::BTreeSet;
 use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
 use std::path::Path;
+use std::time::Duration;
 
 pub trait AsAnyhowPretty<T> {
     fn into_anyhow(self) -> anyhow::Result<T>;
@@ -31,49 +51,332 @@ impl<T> AsAnyhowPretty<T> for HcOpsResult<T> {
     }
 }
 
+/// The service name that passphrases are stored under in the OS keyring.
+const KEYRING_SERVICE: &str = "hc-ops";
+
+/// The longest passphrase that will be read, far longer than any passphrase in practice.
+const MAX_PASSPHRASE_LEN: usize = 1024;
+
+/// Read the conductor passphrase into locked memory.
+///
+/// Uses a file, file descriptor or the OS keyring if one is given, then reads a line from stdin
+/// when `piped` is set, then the `HC_OPS_PASSPHRASE` environment variable if it is set, and
+/// otherwise prompts for it.
+pub fn read_passphrase(args: &PassphraseArgs) -> anyhow::Result<sodoken::LockedArray> {
+    if let Some(path) = &args.passphrase_file {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open passphrase file: {}", path.display()))?;
+        return read_passphrase_line(file);
+    }
+
+    if let Some(fd) = args.passphrase_fd {
+        // Opening the descriptor through `/dev/fd` avoids taking ownership of it. Reading a byte at
+        // a time doesn't consume anything after the passphrase line when it is a pipe.
+        let file = std::fs::File::open(format!("/dev/fd/{fd}"))
+            .with_context(|| format!("Failed to open passphrase file descriptor {fd}"))?;
+        return read_passphrase_line(file);
+    }
+
+    if let Some(account) = &args.passphrase_keyring {
+        return read_keyring_passphrase(account);
+    }
+
+    if args.piped {
+        return read_passphrase_line(std::io::stdin().lock())
+            .context("Failed to read passphrase from stdin");
+    }
+
+    if let Ok(pass) = std::env::var("HC_OPS_PASSPHRASE") {
+        return lock_passphrase(pass.into_bytes());
+    }
+
+    lock_passphrase(
+        rpassword::prompt_password("Enter conductor passphrase to unlock databases: ")?
+            .into_bytes(),
+    )
+}
+
+/// Load the key for the databases in a data directory, if they are encrypted.
+///
+/// The key is taken from a running `hc-ops unlock` agent if it holds the key for this data
+/// directory, otherwise the passphrase is read and the key derived from it.
+pub fn unlock_database_key(
+    data_root_path: &Path,
+    passphrase: &PassphraseArgs,
+) -> anyhow::Result<Option<Key>> {
+    #[cfg(unix)]
+    if let Some(key) = crate::cli::unlock::request_key(data_root_path) {
+        return Ok(Some(key));
+    }
+
+    load_database_key(data_root_path, read_passphrase(passphrase)?).into_anyhow()
+}
+
+/// Read a line into locked memory, without its line ending.
+fn read_passphrase_line(mut read: impl Read) -> anyhow::Result<sodoken::LockedArray> {
+    let mut buf = sodoken::LockedArray::new(MAX_PASSPHRASE_LEN)?;
+    let mut len = 0;
+
+    {
+        let mut lock = buf.lock();
+        let mut byte = [0u8];
+        while read.read(&mut byte)? == 1 && byte[0] != b'\n' {
+            if len == MAX_PASSPHRASE_LEN {
+                anyhow::bail!("Passphrase is longer than {MAX_PASSPHRASE_LEN} bytes");
+            }
+
+            lock[len] = byte[0];
+            len += 1;
+        }
+        byte[0] = 0;
+
+        if len > 0 && lock[len - 1] == b'\r' {
+            len -= 1;
+        }
+    }
+
+    let mut pass = sodoken::LockedArray::new(len)?;
+    pass.lock().copy_from_slice(&buf.lock()[..len]);
+
+    Ok(pass)
+}
+
+/// Move a passphrase into locked memory, overwriting the original.
+fn lock_passphrase(mut bytes: Vec<u8>) -> anyhow::Result<sodoken::LockedArray> {
+    let len = bytes
+        .iter()
+        .rposition(|b| *b != b'\n' && *b != b'\r')
+        .map_or(0, |i| i + 1);
+
+    let mut pass = sodoken::LockedArray::new(len)?;
+    pass.lock().copy_from_slice(&bytes[..len]);
+    bytes.fill(0);
+
+    Ok(pass)
+}
+
+fn read_keyring_passphrase(account: &str) -> anyhow::Result<sodoken::LockedArray> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("security");
+        command.args([
+            "find-generic-password",
+            "-s",
+            KEYRING_SERVICE,
+            "-a",
+            account,
+            "-w",
+        ]);
+        command
+    } else {
+        let mut command = std::process::Command::new("secret-tool");
+        command.args(["lookup", "service", KEYRING_SERVICE, "account", account]);
+        command
+    };
+
+    let output = command
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .context("Failed to run the keyring tool")?;
+    if !output.status.success() {
+        anyhow::bail!("No passphrase found in the keyring for account: {account}");
+    }
+
+    lock_passphrase(output.stdout)
+}
+
+/// The databases for a single cell.
+pub struct CellDatabases {
+    pub authored: SqliteConnection,
+    pub dht: SqliteConnection,
+    pub cache: SqliteConnection,
+    schemas: Vec<(DbKind, DatabaseSchema)>,
+}
+
+impl CellDatabases {
+    pub fn open(
+        data_root_path: &Path,
+        agent: &AgentPubKey,
+        dna: &DnaHash,
+        key: &mut Option<Key>,
+        mode: OpenMode,
+    ) -> anyhow::Result<Self> {
+        let mut authored = open_holochain_database(
+            data_root_path,
+            &DbKind::Authored(agent.clone()),
+            dna,
+            key.as_mut(),
+            mode,
+        )
+        .context("Failed to open the authored database")?;
+        let mut dht =
+            open_holochain_database(data_root_path, &DbKind::Dht, dna, key.as_mut(), mode)
+                .context("Failed to open the DHT database")?;
+        let mut cache =
+            open_holochain_database(data_root_path, &DbKind::Cache, dna, key.as_mut(), mode)
+                .context("Failed to open the cache database")?;
+
+        let schemas = vec![
+            (
+                DbKind::Authored(agent.clone()),
+                read_database_schema(&mut authored).into_anyhow()?,
+            ),
+            (DbKind::Dht, read_database_schema(&mut dht).into_anyhow()?),
+            (
+                DbKind::Cache,
+                read_database_schema(&mut cache).into_anyhow()?,
+            ),
+        ];
+
+        Ok(CellDatabases {
+            authored,
+            dht,
+            cache,
+            schemas,
+        })
+    }
+
+    /// Check that the databases have the tables and columns that a feature reads.
+    pub fn require(&self, feature: Feature) -> anyhow::Result<()> {
+        for (kind, schema) in &self.schemas {
+            require_feature(schema, kind, feature)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that one of the databases has the tables and columns that a feature reads.
+    pub fn require_in(&self, kind: &DbKind, feature: Feature) -> anyhow::Result<()> {
+        for (_, schema) in self.schemas.iter().filter(|(k, _)| k == kind) {
+            require_feature(schema, kind, feature)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that a database has the tables and columns that a feature reads, if it reads that kind
+/// of database.
+///
+/// Holochain versions differ in their schemas, so a feature that would fail part way through with
+/// a database error is refused up front instead.
+pub fn require_feature(
+    schema: &DatabaseSchema,
+    kind: &DbKind,
+    feature: Feature,
+) -> anyhow::Result<()> {
+    if !feature.reads(kind) {
+        return Ok(());
+    }
+
+    if let Err(missing) = schema.check(feature) {
+        let database = match kind {
+            DbKind::Authored(_) => "authored",
+            DbKind::Dht => "DHT",
+            DbKind::Cache => "cache",
+        };
+
+        anyhow::bail!(
+            "The {feature} feature doesn't support this Holochain version, the {database} database (schema version {}) is missing: {}",
+            schema.user_version,
+            missing.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The features that an explore command reads.
+fn command_features(command: &ExploreCommands) -> &'static [Feature] {
+    match command {
+        ExploreCommands::WhoIsHere
+        | ExploreCommands::AgentChain { .. }
+        | ExploreCommands::SelfChain
+        | ExploreCommands::VerifyChain { .. }
+        | ExploreCommands::History { .. } => &[Feature::Chains],
+        ExploreCommands::Warrants { .. } => &[Feature::Warrants],
+        ExploreCommands::Pending
+        | ExploreCommands::Diagnose { .. }
+        | ExploreCommands::OpsByAction { .. }
+        | ExploreCommands::OpsByEntry { .. }
+        | ExploreCommands::Links { .. }
+        | ExploreCommands::Coverage { .. }
+        | ExploreCommands::Watch { .. } => &[Feature::Ops],
+        ExploreCommands::SliceHashes { .. }
+        | ExploreCommands::Slices { .. }
+        | ExploreCommands::OpsInSlice { .. } => &[Feature::Slices],
+        ExploreCommands::Dump { .. } => &[Feature::Chains, Feature::Ops],
+    }
+}
+
 pub async fn start_explorer(
     _conn: &mut SqliteConnection,
     client: holochain_client::AdminWebsocket,
     data_root_path: impl AsRef<Path>,
+    passphrase: &PassphraseArgs,
+    open_mode: OpenMode,
+    selector: &CellSelector,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let data_root_path = data_root_path.as_ref();
 
-    let pass = rpassword::prompt_password("Enter conductor passphrase to unlock databases: ")?;
-    let pass = sodoken::LockedArray::from(pass.into_bytes());
-    let mut key = load_database_key(data_root_path, pass)?;
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
 
     let apps = client.list_apps(None).await?;
+    let apps = apps
+        .iter()
+        .filter(|a| {
+            selector
+                .app
+                .as_ref()
+                .is_none_or(|app_id| &a.installed_app_id == app_id)
+        })
+        .collect::<Vec<_>>();
 
+    // Only prompt when the selectors leave more than one choice.
     'outer: loop {
-        let use_app = select_app(&apps)?;
-        if use_app.is_none() {
-            break 'outer;
-        }
-        let use_app = use_app.unwrap();
+        let use_app = match apps.as_slice() {
+            [app] => *app,
+            _ => match select_app(&apps)? {
+                Some(app) => app,
+                None => break 'outer,
+            },
+        };
+
+        let cells = list_app_cells(std::slice::from_ref(use_app))
+            .into_iter()
+            .filter(|c| selector.matches(c))
+            .collect::<Vec<_>>();
 
         loop {
-            let use_dna = select_dna(use_app)?;
-            if use_dna.is_none() {
-                break;
-            }
-            let use_dna = use_dna.unwrap();
+            let use_cell = match cells.as_slice() {
+                [cell] => cell,
+                _ => match select_cell(&cells)? {
+                    Some(cell) => cell,
+                    None => break,
+                },
+            };
+            let use_dna = use_cell.cell_id.dna_hash();
+
+            let agent_arcs = fetch_agent_arcs(&client, use_dna).await?;
 
             loop {
-                let mut authored = open_holochain_database(
+                // One of the cell's databases may be missing or unreadable, so go back to cell
+                // selection rather than stopping the explorer.
+                let mut dbs = match CellDatabases::open(
                     data_root_path,
-                    &DbKind::Authored(use_app.agent_pub_key.clone()),
+                    use_cell.cell_id.agent_pubkey(),
                     use_dna,
-                    key.as_mut(),
-                )
-                .context("Failed to open the authored database")?;
-                let mut dht =
-                    open_holochain_database(data_root_path, &DbKind::Dht, use_dna, key.as_mut())
-                        .context("Failed to open the DHT database")?;
-                let mut cache =
-                    open_holochain_database(data_root_path, &DbKind::Cache, use_dna, key.as_mut())
-                        .context("Failed to open the cache database")?;
-
-                match run_explorer(&mut authored, &mut dht, &mut cache) {
+                    &mut key,
+                    open_mode,
+                ) {
+                    Ok(dbs) => dbs,
+                    Err(e) => {
+                        eprintln!("\nCannot explore this cell: {e:#}\n");
+                        break;
+                    }
+                };
+
+                match run_explorer(&mut dbs, output, &agent_arcs) {
                     Ok(true) => break 'outer,
                     Ok(false) => {
                         break;
@@ -83,28 +386,399 @@ pub async fn start_explorer(
                     }
                 }
             }
+
+            if cells.len() == 1 {
+                break;
+            }
+        }
+
+        if apps.len() == 1 {
+            break;
+        }
+    }
+
+    output.note("Thank you for exploring!");
+
+    Ok(())
+}
+
+/// Explore the cells found in a data directory, without a running conductor.
+pub fn start_offline_explorer(
+    data_root_path: impl AsRef<Path>,
+    passphrase: &PassphraseArgs,
+    open_mode: OpenMode,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let data_root_path = data_root_path.as_ref();
+
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let cells = discover_cells(data_root_path).into_anyhow()?;
+
+    'outer: loop {
+        let use_cell = select_offline_cell(&cells)?;
+        if use_cell.is_none() {
+            break 'outer;
+        }
+        let use_cell = use_cell.unwrap();
+
+        loop {
+            // One of the cell's databases may be missing or unreadable, which shouldn't stop
+            // another cell from being explored.
+            let mut dbs = match CellDatabases::open(
+                data_root_path,
+                &use_cell.agent_pub_key,
+                &use_cell.dna_hash,
+                &mut key,
+                open_mode,
+            ) {
+                Ok(dbs) => dbs,
+                Err(e) => {
+                    eprintln!("\nCannot explore this cell: {e:#}\n");
+                    continue 'outer;
+                }
+            };
+
+            match run_explorer(&mut dbs, output, &[]) {
+                Ok(true) => break 'outer,
+                Ok(false) => {
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("\nProblem running action: {:?}\n", e);
+                }
+            }
         }
     }
 
-    println!("Thank you for exploring!");
+    output.note("Thank you for exploring!");
 
     Ok(())
 }
 
+/// Which cells of the installed apps to use. Fields that are not set match any cell.
+#[derive(Debug, Default)]
+pub struct CellSelector {
+    pub app: Option<String>,
+    /// Matches the role name, which clone cells share with the cell they were cloned from.
+    pub role: Option<String>,
+    pub clone_id: Option<String>,
+    pub dna: Option<DnaHash>,
+    pub agent: Option<AgentPubKey>,
+}
+
+impl CellSelector {
+    fn matches(&self, cell: &AppCell) -> bool {
+        let clone_matches = match (&self.clone_id, &cell.clone_id) {
+            (Some(selected), Some(clone_id)) => selected == clone_id,
+            (Some(_), None) => false,
+            // Selecting a role without a clone id means the provisioned cell, not its clones.
+            (None, Some(_)) => self.role.is_none(),
+            (None, None) => true,
+        };
+
+        clone_matches
+            && self.app.as_ref().is_none_or(|app| app == cell.app_id)
+            && self.role.as_ref().is_none_or(|role| role == cell.role_name)
+            && self
+                .dna
+                .as_ref()
+                .is_none_or(|dna| dna == cell.cell_id.dna_hash())
+            && self
+                .agent
+                .as_ref()
+                .is_none_or(|agent| agent == cell.cell_id.agent_pubkey())
+    }
+}
+
+/// A provisioned or clone cell of an installed app.
+pub struct AppCell<'a> {
+    pub app_id: &'a str,
+    pub role_name: &'a str,
+    pub name: &'a str,
+    pub clone_id: Option<String>,
+    pub cell_id: &'a CellId,
+}
+
+impl Display for AppCell<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.clone_id {
+            Some(clone_id) => write!(f, "{}/{clone_id}", self.name)?,
+            None => write!(f, "{}", self.name)?,
+        }
+
+        write!(
+            f,
+            " ({:?}): {:?}",
+            self.cell_id.agent_pubkey(),
+            self.cell_id.dna_hash()
+        )
+    }
+}
+
+pub fn list_app_cells(apps: &[AppInfo]) -> Vec<AppCell<'_>> {
+    apps.iter()
+        .flat_map(|app| {
+            app.cell_info.iter().flat_map(move |(role_name, cells)| {
+                cells.iter().filter_map(move |c| match c {
+                    CellInfo::Provisioned(cell) => Some(AppCell {
+                        app_id: &app.installed_app_id,
+                        role_name,
+                        name: &cell.name,
+                        clone_id: None,
+                        cell_id: &cell.cell_id,
+                    }),
+                    CellInfo::Cloned(cell) => Some(AppCell {
+                        app_id: &app.installed_app_id,
+                        role_name,
+                        name: &cell.name,
+                        clone_id: Some(cell.clone_id.to_string()),
+                        cell_id: &cell.cell_id,
+                    }),
+                    _ => None,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Resolve the cell to explore from the apps installed on a running conductor.
+///
+/// The selector must match exactly one cell. The agent is the cell's own agent, which is the one
+/// whose authored database belongs to the cell.
+pub async fn resolve_cell(
+    client: &holochain_client::AdminWebsocket,
+    selector: &CellSelector,
+) -> anyhow::Result<(DnaHash, AgentPubKey)> {
+    let apps = client.list_apps(None).await?;
+    let cells = list_app_cells(&apps)
+        .into_iter()
+        .filter(|c| selector.matches(c))
+        .collect::<Vec<_>>();
+
+    match cells.as_slice() {
+        [] => anyhow::bail!("No cells match the selection"),
+        [cell] => Ok((
+            cell.cell_id.dna_hash().clone(),
+            cell.cell_id.agent_pubkey().clone(),
+        )),
+        _ => anyhow::bail!(
+            "Multiple cells match, select one with --app, --role, --clone-id, --dna or --agent:\n{}",
+            describe_cells(&cells)
+        ),
+    }
+}
+
+/// Resolve the DNA of the cells matched by a selector, which may belong to several agents.
+pub async fn resolve_dna(
+    client: &holochain_client::AdminWebsocket,
+    selector: &CellSelector,
+) -> anyhow::Result<DnaHash> {
+    let apps = client.list_apps(None).await?;
+    let cells = list_app_cells(&apps)
+        .into_iter()
+        .filter(|c| selector.matches(c))
+        .collect::<Vec<_>>();
+    let dnas = cells
+        .iter()
+        .map(|c| c.cell_id.dna_hash())
+        .collect::<BTreeSet<_>>();
+
+    match dnas.into_iter().collect::<Vec<_>>().as_slice() {
+        [] => anyhow::bail!("No cells match the selection"),
+        [dna] => Ok((*dna).clone()),
+        _ => anyhow::bail!(
+            "Cells with multiple DNAs match, select one with --app, --role, --clone-id or --dna:\n{}",
+            describe_cells(&cells)
+        ),
+    }
+}
+
+fn describe_cells(cells: &[AppCell]) -> String {
+    cells
+        .iter()
+        .map(|c| format!("  {}: {c}", c.app_id))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Resolve the cell to explore from the cells found in a data directory.
+///
+/// The DNA and agent must either be provided or be the only possible choice.
+pub fn resolve_offline_cell(
+    data_root_path: impl AsRef<Path>,
+    dna: Option<&DnaHash>,
+    agent: Option<&AgentPubKey>,
+) -> anyhow::Result<(DnaHash, AgentPubKey)> {
+    let cells = discover_cells(data_root_path)
+        .into_anyhow()?
+        .into_iter()
+        .filter(|c| dna.is_none_or(|dna| &c.dna_hash == dna))
+        .filter(|c| agent.is_none_or(|agent| &c.agent_pub_key == agent))
+        .collect::<Vec<_>>();
+
+    match cells.as_slice() {
+        [] => anyhow::bail!("No matching cells found in the data directory"),
+        [cell] => Ok((cell.dna_hash.clone(), cell.agent_pub_key.clone())),
+        _ => anyhow::bail!("Multiple cells found, select one with --dna and --agent"),
+    }
+}
+
+/// Find the DNA to use from a data directory, for commands that only read its DHT database.
+///
+/// The cells of every agent with the same DNA share its DHT database, so there is no need to pick
+/// an agent.
+pub fn resolve_offline_dna(
+    data_root_path: impl AsRef<Path>,
+    dna: Option<&DnaHash>,
+) -> anyhow::Result<DnaHash> {
+    select_dna(&discover_cells(data_root_path).into_anyhow()?, dna)
+}
+
+fn select_dna(cells: &[DiscoveredCell], dna: Option<&DnaHash>) -> anyhow::Result<DnaHash> {
+    let dnas = cells
+        .iter()
+        .map(|c| &c.dna_hash)
+        .filter(|h| dna.is_none_or(|dna| *h == dna))
+        .collect::<BTreeSet<_>>();
+
+    match dnas.into_iter().collect::<Vec<_>>().as_slice() {
+        [] => anyhow::bail!("No matching DNA found in the data directory"),
+        [dna] => Ok((*dna).clone()),
+        _ => anyhow::bail!("Multiple DNAs found, select one with --dna"),
+    }
+}
+
+/// The storage arcs claimed by the agents that a conductor knows about for a DNA.
+pub async fn fetch_agent_arcs(
+    client: &holochain_client::AdminWebsocket,
+    dna: &DnaHash,
+) -> anyhow::Result<Vec<AgentArc>> {
+    Ok(client
+        .agent_info(Some(vec![dna.clone()]))
+        .await?
+        .into_iter()
+        .filter_map(|s| AgentInfoSigned::decode(&Ed25519Verifier, s.as_bytes()).ok())
+        .filter(|info| !info.is_tombstone)
+        .map(|info| AgentArc {
+            agent: AgentPubKey::from_k2_agent(&info.agent),
+            arc: info.storage_arc,
+        })
+        .collect())
+}
+
+/// Run a single explore operation without prompting.
+///
+/// The agent arcs are only used for the coverage map, and are empty when exploring offline.
+pub fn run_explore_command(
+    data_root_path: impl AsRef<Path>,
+    passphrase: &PassphraseArgs,
+    open_mode: OpenMode,
+    dna: &DnaHash,
+    agent: &AgentPubKey,
+    agent_arcs: &[AgentArc],
+    command: ExploreCommands,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let data_root_path = data_root_path.as_ref();
+
+    if open_mode == OpenMode::Snapshot && matches!(command, ExploreCommands::Watch { .. }) {
+        anyhow::bail!("Watching needs the live databases, a snapshot would never change");
+    }
+
+    let mut key = unlock_database_key(data_root_path, passphrase)?;
+
+    let mut dbs = CellDatabases::open(data_root_path, agent, dna, &mut key, open_mode)?;
+    for feature in command_features(&command) {
+        dbs.require(*feature)?;
+    }
+
+    match command {
+        ExploreCommands::WhoIsHere => who_is_here(&mut dbs, output),
+        ExploreCommands::AgentChain {
+            agent,
+            include_cache,
+        } => agent_chain(&mut dbs, output, &agent.into(), include_cache),
+        ExploreCommands::SelfChain => self_agent_chain(&mut dbs, output),
+        ExploreCommands::VerifyChain {
+            agent,
+            include_cache,
+        } => verify_agent_chain(
+            &mut dbs,
+            output,
+            agent.map(Into::into).as_ref(),
+            include_cache,
+        ),
+        ExploreCommands::Warrants { warrantee } => {
+            warrants(&mut dbs, output, warrantee.map(Into::into).as_ref())
+        }
+        ExploreCommands::Pending => pending_ops(&mut dbs, output),
+        ExploreCommands::Diagnose { retry_threshold } => {
+            diagnose_ops(&mut dbs, output, retry_threshold)
+        }
+        ExploreCommands::OpsByAction { hash } => ops_by_action_hash(&mut dbs, output, &hash.into()),
+        ExploreCommands::OpsByEntry { hash } => ops_by_entry_hash(&mut dbs, output, &hash.into()),
+        ExploreCommands::History { hash } => entry_history(&mut dbs, output, &hash.into()),
+        ExploreCommands::Links {
+            base,
+            zome_index,
+            link_type,
+            tag_prefix,
+        } => links_by_base(
+            &mut dbs,
+            output,
+            &base.into(),
+            zome_index,
+            link_type,
+            tag_prefix.as_deref().map(str::as_bytes),
+        ),
+        ExploreCommands::SliceHashes { out } => {
+            slice_hashes(&mut dbs, output, out.as_deref().map(|out| (out, dna)))
+        }
+        ExploreCommands::Slices { arc_start, arc_end } => {
+            slices(&mut dbs, output, arc_start, arc_end)
+        }
+        ExploreCommands::OpsInSlice {
+            arc_start,
+            arc_end,
+            slice_index,
+        } => ops_in_slice(&mut dbs, output, arc_start, arc_end, slice_index),
+        ExploreCommands::Coverage { buckets } => coverage(&mut dbs, output, agent_arcs, buckets),
+        ExploreCommands::Dump { filter } => dump(&mut dbs, output, filter.into()),
+        ExploreCommands::Watch { interval } => {
+            watch(&mut dbs, output, Duration::from_secs(interval.max(1)))
+        }
+    }
+}
+
 fn run_explorer(
-    authored: &mut SqliteConnection,
-    dht: &mut SqliteConnection,
-    cache: &mut SqliteConnection,
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    agent_arcs: &[AgentArc],
 ) -> anyhow::Result<bool> {
+    // Still explore with what is supported, but say up front what isn't.
+    for feature in Feature::ALL {
+        if let Err(e) = dbs.require(feature) {
+            output.note(format!("{e}"));
+        }
+    }
+
     enum Operation {
         WhoIsHere,
         AgentChain,
         SelfAgentChain,
+        VerifyChain,
+        Warrants,
         Pending,
+        Diagnose,
         FindOpsByActionHash,
         FindOpsByEntryHash,
+        History,
+        Links,
         SliceHashes,
+        BrowseSlices,
         OpsInSlice,
+        Coverage,
         Dump,
         Back,
         Exit,
@@ -116,11 +790,18 @@ fn run_explorer(
                 Operation::WhoIsHere => write!(f, "Who is here?"),
                 Operation::AgentChain => write!(f, "View an agent chain"),
                 Operation::SelfAgentChain => write!(f, "View this agent's chain"),
+                Operation::VerifyChain => write!(f, "Verify a source chain"),
+                Operation::Warrants => write!(f, "View warrants"),
                 Operation::Pending => write!(f, "View ops pending validation or integration"),
+                Operation::Diagnose => write!(f, "Diagnose pending and failed ops"),
                 Operation::FindOpsByActionHash => write!(f, "View ops by action hash"),
                 Operation::FindOpsByEntryHash => write!(f, "View ops by entry hash"),
+                Operation::History => write!(f, "View the history of a record"),
+                Operation::Links => write!(f, "View links by base"),
                 Operation::SliceHashes => write!(f, "View slice hashes"),
+                Operation::BrowseSlices => write!(f, "Browse time slices"),
                 Operation::OpsInSlice => write!(f, "View ops in a slice"),
+                Operation::Coverage => write!(f, "View DHT arc coverage"),
                 Operation::Dump => write!(f, "Dump"),
                 Operation::Back => write!(f, ":back"),
                 Operation::Exit => write!(f, ":exit"),
@@ -132,11 +813,18 @@ fn run_explorer(
         Operation::WhoIsHere,
         Operation::AgentChain,
         Operation::SelfAgentChain,
+        Operation::VerifyChain,
+        Operation::Warrants,
         Operation::Pending,
+        Operation::Diagnose,
         Operation::FindOpsByActionHash,
         Operation::FindOpsByEntryHash,
+        Operation::History,
+        Operation::Links,
         Operation::SliceHashes,
+        Operation::BrowseSlices,
         Operation::OpsInSlice,
+        Operation::Coverage,
         Operation::Dump,
         Operation::Back,
         Operation::Exit,
@@ -149,14 +837,7 @@ fn run_explorer(
             .interact()?;
 
         match operations[selected] {
-            Operation::WhoIsHere => {
-                let discovered = list_discovered_agents(dht, cache)?;
-
-                println!(
-                    "Discovered agents: {}",
-                    discovered.as_human_readable_pretty()?
-                );
-            }
+            Operation::WhoIsHere => who_is_here(dbs, output)?,
             Operation::AgentChain => {
                 let key: String = dialoguer::Input::new()
                     .with_prompt("Enter the agent pubkey")
@@ -167,39 +848,53 @@ fn run_explorer(
                     .into();
 
                 // Prompt the user to check whether to include items from the cache.
-                let cache = dialoguer::Confirm::new()
+                let include_cache = dialoguer::Confirm::new()
                     .with_prompt("Include items from cache?")
-                    .interact()?
-                    .then_some(&mut *cache);
+                    .interact()?;
 
-                let chain = get_agent_chain(dht, cache, &key).into_anyhow()?;
+                agent_chain(dbs, output, &key, include_cache)?;
+            }
+            Operation::SelfAgentChain => self_agent_chain(dbs, output)?,
+            Operation::VerifyChain => {
+                let key: String = dialoguer::Input::new()
+                    .with_prompt("Enter the agent pubkey (leave empty for this agent)")
+                    .allow_empty(true)
+                    .interact()?;
 
-                println!(
-                    "Agent chain: {}",
-                    chain.as_human_readable_pretty().into_anyhow()?
-                );
+                if key.trim().is_empty() {
+                    verify_agent_chain(dbs, output, None, false)?;
+                } else {
+                    let key: AgentPubKey = AgentPubKeyB64::from_b64_str(key.trim())
+                        .context("Invalid agent key")?
+                        .into();
+
+                    let include_cache = dialoguer::Confirm::new()
+                        .with_prompt("Include items from cache?")
+                        .interact()?;
+
+                    verify_agent_chain(dbs, output, Some(&key), include_cache)?;
+                }
             }
-            Operation::SelfAgentChain => {
-                let chain = get_self_agent_chain(authored).into_anyhow()?;
+            Operation::Warrants => {
+                let key: String = dialoguer::Input::new()
+                    .with_prompt("Enter the warranted agent pubkey (leave empty for all agents)")
+                    .allow_empty(true)
+                    .interact()?;
+
+                let warrantee: Option<AgentPubKey> = parse_optional::<AgentPubKeyB64>(&key)
+                    .context("Invalid agent key")?
+                    .map(Into::into);
 
-                println!(
-                    "This agent's chain: {}",
-                    chain.as_human_readable_pretty().into_anyhow()?
-                );
+                warrants(dbs, output, warrantee.as_ref())?;
             }
-            Operation::Pending => {
-                let pending = get_pending_ops(dht)?;
+            Operation::Pending => pending_ops(dbs, output)?,
+            Operation::Diagnose => {
+                let retry_threshold: u32 = dialoguer::Input::new()
+                    .with_prompt("Flag ops that have been validated at least this many times")
+                    .default(DEFAULT_RETRY_THRESHOLD)
+                    .interact()?;
 
-                if pending.is_empty() {
-                    println!("No pending ops");
-                } else {
-                    println!(
-                        "Pending ops: {}",
-                        pending
-                            .as_human_readable_pretty()
-                            .context("Could not convert pending ops")?
-                    );
-                }
+                diagnose_ops(dbs, output, retry_threshold)?;
             }
             Operation::FindOpsByActionHash => {
                 let hash: String = dialoguer::Input::new()
@@ -210,56 +905,67 @@ fn run_explorer(
                     .context("Invalid action hash, must be a 39 character base64 string")?
                     .into();
 
-                let ops = get_ops_by_action_hash(dht, &hash)?
-                    .into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<HcOpsResult<Vec<ChainOp<DhtMeta>>>>()?;
-
-                if ops.is_empty() {
-                    println!("No ops found for action hash: {}", hash);
-                } else {
-                    println!(
-                        "Ops for action hash {}: {}",
-                        hash,
-                        ops.as_human_readable_pretty()?
-                    );
-                }
+                ops_by_action_hash(dbs, output, &hash)?;
             }
             Operation::FindOpsByEntryHash => {
                 let hash: String = dialoguer::Input::new()
                     .with_prompt("Enter the entry hash")
                     .interact()?;
 
-                let hash: holo_hash::EntryHash = holo_hash::EntryHashB64::from_b64_str(&hash)
+                let hash: EntryHash = EntryHashB64::from_b64_str(&hash)
                     .context("Invalid entry hash, must be a 39 character base64 string")?
                     .into();
 
-                let ops = get_ops_by_entry_hash(dht, &hash)?
-                    .into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<HcOpsResult<Vec<ChainOp<DhtMeta>>>>()?;
+                ops_by_entry_hash(dbs, output, &hash)?;
+            }
+            Operation::History => {
+                let hash: String = dialoguer::Input::new()
+                    .with_prompt("Enter an action hash or entry hash")
+                    .interact()?;
 
-                if ops.is_empty() {
-                    println!("No ops found for entry hash: {}", hash);
-                } else {
-                    println!(
-                        "Ops for entry hash {}: {}",
-                        hash,
-                        ops.as_human_readable_pretty()?
-                    );
-                }
+                let hash: AnyDhtHash = AnyDhtHashB64::from_b64_str(&hash)
+                    .context("Invalid hash, must be a 39 character base64 string")?
+                    .into();
+
+                entry_history(dbs, output, &hash)?;
             }
-            Operation::SliceHashes => {
-                let mut slice_hashes = get_slice_hashes(dht)?;
+            Operation::Links => {
+                let base: String = dialoguer::Input::new()
+                    .with_prompt("Enter the base hash")
+                    .interact()?;
 
-                slice_hashes.sort();
+                let base: AnyLinkableHash = AnyLinkableHashB64::from_b64_str(&base)
+                    .context("Invalid base hash, must be a 39 character base64 string")?
+                    .into();
 
-                slice_hashes
-                    .into_iter()
-                    .map(Into::into)
-                    .collect::<Vec<SliceHashTable>>()
-                    .render(std::io::stdout())?
+                let zome_index: String = dialoguer::Input::new()
+                    .with_prompt("Enter the zome index (leave empty for any)")
+                    .allow_empty(true)
+                    .interact()?;
+                let zome_index = parse_optional::<u8>(&zome_index).context("Invalid zome index")?;
+
+                let link_type: String = dialoguer::Input::new()
+                    .with_prompt("Enter the link type (leave empty for any)")
+                    .allow_empty(true)
+                    .interact()?;
+                let link_type = parse_optional::<u8>(&link_type).context("Invalid link type")?;
+
+                let tag_prefix: String = dialoguer::Input::new()
+                    .with_prompt("Enter a tag prefix (leave empty for any)")
+                    .allow_empty(true)
+                    .interact()?;
+
+                links_by_base(
+                    dbs,
+                    output,
+                    &base,
+                    zome_index,
+                    link_type,
+                    (!tag_prefix.is_empty()).then_some(tag_prefix.as_bytes()),
+                )?;
             }
+            Operation::SliceHashes => slice_hashes(dbs, output, None)?,
+            Operation::BrowseSlices => browse_slices(dbs, output)?,
             Operation::OpsInSlice => {
                 let arc_start: u32 = dialoguer::Input::new()
                     .with_prompt("Enter the arc start")
@@ -273,85 +979,17 @@ fn run_explorer(
                     .with_prompt("Enter the slice index")
                     .interact()?;
 
-                let ops = get_ops_in_slice(dht, arc_start, arc_end, slice_index)?;
-
-                if ops.is_empty() {
-                    println!("No ops in slice");
-                } else {
-                    for op in ops {
-                        println!("{op:?} @ {}", op.get_loc());
-                    }
-                }
+                ops_in_slice(dbs, output, arc_start, arc_end, slice_index)?;
             }
-            Operation::Dump => {
-                let out = get_all_dht_ops(authored);
-                println!(
-                    "Authored ops: {}\n\n",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<ChainOp<AuthoredMeta>>>>()?
-                        .as_human_readable_pretty()
-                        .context("Could not convert authored ops")?
-                );
-
-                let out = get_all_actions(authored);
-                println!(
-                    "Authored actions: {}",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<SignedAction>>>()?
-                        .as_human_readable_summary_pretty()
-                        .context("Could not convert authored actions")?
-                );
-
-                let out = get_all_entries(authored);
-                println!(
-                    "Authored entries: {}",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<Entry>>>()?
-                        .as_human_readable_summary_pretty()
-                        .context("Could not convert authored entries")?
-                );
-
-                let out = get_all_dht_ops(dht);
-                println!(
-                    "DHT ops: {}\n\n",
-                    serde_json::to_string_pretty(
-                        &out.into_iter()
-                            .map(TryInto::try_into)
-                            .collect::<HcOpsResult<Vec<ChainOp<DhtMeta>>>>()?
-                            .as_human_readable_raw()?
-                    )?
-                );
-
-                let out = get_all_actions(dht);
-                println!(
-                    "DHT actions: {}",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<SignedAction>>>()?
-                        .as_human_readable_summary_pretty()?
-                );
-
-                let out = get_all_dht_ops(cache);
-                println!(
-                    "Cache ops: {}\n\n",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<ChainOp<CacheMeta>>>>()?
-                        .as_human_readable_pretty()?
-                );
-
-                let out = get_all_actions(cache);
-                println!(
-                    "Cache actions: {}",
-                    out.into_iter()
-                        .map(TryInto::try_into)
-                        .collect::<HcOpsResult<Vec<SignedAction>>>()?
-                        .as_human_readable_summary_pretty()?
-                );
+            Operation::Coverage => {
+                let buckets: u32 = dialoguer::Input::new()
+                    .with_prompt("Enter the number of buckets to split the ring into")
+                    .default(DEFAULT_COVERAGE_BUCKETS)
+                    .interact()?;
+
+                coverage(dbs, output, agent_arcs, buckets)?;
             }
+            Operation::Dump => dump(dbs, output, ScanFilter::default())?,
             Operation::Back => {
                 return Ok(false);
             }
@@ -362,7 +1000,799 @@ fn run_explorer(
     }
 }
 
-fn select_app(apps: &[AppInfo]) -> anyhow::Result<Option<&AppInfo>> {
+fn who_is_here(dbs: &mut CellDatabases, output: OutputFormat) -> anyhow::Result<()> {
+    let discovered = list_discovered_agents(&mut dbs.dht, &mut dbs.cache)?;
+
+    output.write_human_readable("Discovered agents", &discovered, false, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn agent_chain(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    agent: &AgentPubKey,
+    include_cache: bool,
+) -> anyhow::Result<()> {
+    let cache = include_cache.then_some(&mut dbs.cache);
+
+    let chain = get_agent_chain(&mut dbs.dht, cache, agent).into_anyhow()?;
+
+    output.write_human_readable("Agent chain", &chain, false, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn self_agent_chain(dbs: &mut CellDatabases, output: OutputFormat) -> anyhow::Result<()> {
+    let chain = get_self_agent_chain(&mut dbs.authored).into_anyhow()?;
+
+    output.write_human_readable("This agent's chain", &chain, false, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn verify_agent_chain(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    agent: Option<&AgentPubKey>,
+    include_cache: bool,
+) -> anyhow::Result<()> {
+    let chain = match agent {
+        Some(agent) => {
+            let cache = include_cache.then_some(&mut dbs.cache);
+            get_agent_chain(&mut dbs.dht, cache, agent).into_anyhow()?
+        }
+        None => get_self_agent_chain(&mut dbs.authored).into_anyhow()?,
+    };
+
+    let violations = verify_chain(&chain);
+
+    if violations.is_empty() && !output.is_structured() {
+        println!("Checked {} actions, no problems found", chain.len());
+    } else {
+        output.note(format!(
+            "Checked {} actions, found {} problems",
+            chain.len(),
+            violations.len()
+        ));
+
+        violations
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<ChainViolationTable>>()
+            .render(output, std::io::stdout())?;
+    }
+
+    Ok(())
+}
+
+fn warrants(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    warrantee: Option<&AgentPubKey>,
+) -> anyhow::Result<()> {
+    let mut warrants = get_warrants(&mut dbs.dht, warrantee)?;
+    for warrant in get_warrants(&mut dbs.cache, warrantee)? {
+        if !warrants
+            .iter()
+            .any(|w| w.signature() == warrant.signature())
+        {
+            warrants.push(warrant);
+        }
+    }
+    warrants.sort_by_key(|w| w.timestamp);
+
+    let warrants = warrants
+        .into_iter()
+        .map(|w| resolve_warrant(&mut dbs.dht, &mut dbs.cache, w))
+        .collect::<HcOpsResult<Vec<_>>>()?;
+
+    if warrants.is_empty() && !output.is_structured() {
+        println!("No warrants found");
+    } else {
+        output
+            .write_human_readable("Warrants", &warrants, true, std::io::stdout())
+            .context("Could not convert warrants")?;
+    }
+
+    Ok(())
+}
+
+fn pending_ops(dbs: &mut CellDatabases, output: OutputFormat) -> anyhow::Result<()> {
+    let pending = get_pending_ops(&mut dbs.dht)?;
+
+    if pending.is_empty() && !output.is_structured() {
+        println!("No pending ops");
+    } else {
+        output
+            .write_human_readable("Pending ops", &pending, false, std::io::stdout())
+            .context("Could not convert pending ops")?;
+    }
+
+    Ok(())
+}
+
+fn diagnose_ops(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    retry_threshold: u32,
+) -> anyhow::Result<()> {
+    let diagnosis = diagnose_validation(&mut dbs.dht, &mut dbs.cache, retry_threshold)?;
+
+    if diagnosis.ops.is_empty() && !output.is_structured() {
+        println!("No pending, rejected or abandoned ops");
+        return Ok(());
+    }
+
+    let groups = diagnosis
+        .groups
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<DiagnosisGroupTable>>();
+
+    match output {
+        OutputFormat::Table => {
+            println!("Ops by validation stage and status:");
+            groups.render(output, std::io::stdout())?;
+
+            let missing = diagnosis
+                .ops
+                .iter()
+                .filter(|op| !op.missing.is_empty())
+                .count();
+            let retried = diagnosis.ops.iter().filter(|op| op.retried_often).count();
+
+            println!("Pending and failed ops:");
+            write_table(
+                &diagnosis
+                    .ops
+                    .into_iter()
+                    .map(Into::into)
+                    .collect::<Vec<DiagnosedOpTable>>(),
+                std::io::stdout(),
+            )?;
+
+            println!(
+                "{missing} ops are missing dependencies, {retried} ops have been validated at least {retry_threshold} times"
+            );
+        }
+        OutputFormat::Json => {
+            output.write_value(
+                &serde_json::json!({
+                    "groups": groups,
+                    "ops": diagnosis.ops.as_human_readable_summary_raw()?,
+                }),
+                std::io::stdout(),
+            )?;
+        }
+        OutputFormat::Ndjson => {
+            // The groups can be recomputed from the ops, so only stream the ops.
+            output.write_value(
+                &diagnosis.ops.as_human_readable_summary_raw()?,
+                std::io::stdout(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn ops_by_action_hash(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    hash: &ActionHash,
+) -> anyhow::Result<()> {
+    let ops = get_ops_by_action_hash(&mut dbs.dht, hash)?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<HcOpsResult<Vec<ChainOp<DhtMeta>>>>()?;
+
+    if ops.is_empty() && !output.is_structured() {
+        println!("No ops found for action hash: {}", hash);
+    } else {
+        output.write_human_readable(
+            &format!("Ops for action hash {}", hash),
+            &ops,
+            false,
+            std::io::stdout(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn ops_by_entry_hash(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    hash: &EntryHash,
+) -> anyhow::Result<()> {
+    let ops = get_ops_by_entry_hash(&mut dbs.dht, hash)?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<HcOpsResult<Vec<ChainOp<DhtMeta>>>>()?;
+
+    if ops.is_empty() && !output.is_structured() {
+        println!("No ops found for entry hash: {}", hash);
+    } else {
+        output.write_human_readable(
+            &format!("Ops for entry hash {}", hash),
+            &ops,
+            false,
+            std::io::stdout(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn entry_history(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    hash: &AnyDhtHash,
+) -> anyhow::Result<()> {
+    let history = get_entry_history(&mut dbs.dht, hash).into_anyhow()?;
+
+    if output.is_structured() {
+        output.write_human_readable("History", &history, false, std::io::stdout())?;
+        return Ok(());
+    }
+
+    if history.roots.is_empty() {
+        println!("No history found for: {}", hash);
+        return Ok(());
+    }
+
+    for root in &history.roots {
+        print_history_node(root, history.likely_resolves_to.as_ref(), "", "");
+    }
+
+    Ok(())
+}
+
+fn print_history_node(
+    node: &HistoryNode,
+    latest: Option<&ActionHash>,
+    first_prefix: &str,
+    prefix: &str,
+) {
+    let action = node.action.action();
+
+    let mut notes = vec![if node.is_valid() {
+        "valid"
+    } else {
+        "not valid"
+    }];
+    if node.is_deleted() {
+        notes.push("deleted");
+    }
+    if latest == Some(node.action.as_hash()) {
+        notes.push("latest");
+    }
+
+    println!(
+        "{first_prefix}{:?} {} at {} by {} [{}]",
+        action.action_type(),
+        node.action.as_hash(),
+        action.timestamp(),
+        action.author(),
+        notes.join(", ")
+    );
+    for op in &node.ops {
+        println!(
+            "{prefix}    op {:?}: {:?}, integrated {}",
+            op.typ,
+            op.validation_status,
+            op.when_integrated
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| "never".to_string())
+        );
+    }
+
+    let count = node.deletes.len() + node.updates.len();
+    let mut i = 0;
+    for delete in &node.deletes {
+        i += 1;
+        let (branch, _) = tree_branch(i == count);
+        println!(
+            "{prefix}{branch}Delete {} at {} [{}]",
+            delete.action.as_hash(),
+            delete.action.action().timestamp(),
+            delete
+                .ops
+                .iter()
+                .map(|op| format!("{:?}", op.validation_status))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    for update in &node.updates {
+        i += 1;
+        let (branch, next) = tree_branch(i == count);
+        print_history_node(
+            update,
+            latest,
+            &format!("{prefix}{branch}"),
+            &format!("{prefix}{next}"),
+        );
+    }
+}
+
+fn tree_branch(last: bool) -> (&'static str, &'static str) {
+    if last {
+        ("└── ", "    ")
+    } else {
+        ("├── ", "│   ")
+    }
+}
+
+fn links_by_base(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    base: &AnyLinkableHash,
+    zome_index: Option<u8>,
+    link_type: Option<u8>,
+    tag_prefix: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mut links = get_links_by_base(&mut dbs.dht, base, zome_index, link_type, tag_prefix)?;
+
+    // Links that are only in the cache are added after the ones from the DHT
+    let cache_links = get_links_by_base(&mut dbs.cache, base, zome_index, link_type, tag_prefix)?;
+    for link in cache_links {
+        if !links
+            .iter()
+            .any(|l| l.create_link.as_hash() == link.create_link.as_hash())
+        {
+            links.push(link);
+        }
+    }
+
+    if links.is_empty() && !output.is_structured() {
+        println!("No links found for base: {}", base);
+        return Ok(());
+    }
+
+    output.write_human_readable(
+        &format!("Links from base {}", base),
+        &links,
+        false,
+        std::io::stdout(),
+    )?;
+
+    if !output.is_structured() {
+        let count = |state: LinkState| links.iter().filter(|l| l.state() == state).count();
+        println!(
+            "Live: {}, tombstoned: {}, pending: {}, invalid: {}",
+            count(LinkState::Live),
+            count(LinkState::Tombstoned),
+            count(LinkState::Pending),
+            count(LinkState::Invalid),
+        );
+    }
+
+    Ok(())
+}
+
+/// Show the slice hashes, and write them to a slice hash file if `out` is set.
+fn slice_hashes(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    out: Option<(&Path, &DnaHash)>,
+) -> anyhow::Result<()> {
+    let mut slice_hashes = get_slice_hashes(&mut dbs.dht)?;
+
+    slice_hashes.sort();
+
+    if let Some((path, dna)) = out {
+        write_slice_hash_file(path, dna, &slice_hashes)?;
+        output.note(format!("Wrote slice hashes to: {}", path.display()));
+    }
+
+    slice_hashes
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<SliceHashTable>>()
+        .render(output, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn ops_in_slice(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    arc_start: u32,
+    arc_end: u32,
+    slice_index: u64,
+) -> anyhow::Result<()> {
+    let ops = get_ops_in_slice(&mut dbs.dht, arc_start, arc_end, slice_index)?;
+
+    if ops.is_empty() && !output.is_structured() {
+        println!("No ops in slice");
+    } else if output.is_structured() {
+        output.write_value(&ops.as_human_readable_raw()?, std::io::stdout())?;
+    } else {
+        write_table(
+            &ops.into_iter()
+                .map(Into::into)
+                .collect::<Vec<SliceOpTable>>(),
+            std::io::stdout(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn slices(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    arc_start: u32,
+    arc_end: u32,
+) -> anyhow::Result<()> {
+    let slices = list_slices(&mut dbs.dht, arc_start, arc_end)?;
+
+    if slices.is_empty() && !output.is_structured() {
+        println!("No full time slices with ops in this arc");
+    } else {
+        render_slices(&slices, output)?;
+    }
+
+    Ok(())
+}
+
+fn render_slices(slices: &Vec<SliceSummary>, output: OutputFormat) -> anyhow::Result<()> {
+    if output.is_structured() {
+        output.write_value(&slices.as_human_readable_raw()?, std::io::stdout())?;
+    } else {
+        write_table(
+            &slices
+                .iter()
+                .map(Into::into)
+                .collect::<Vec<SliceSummaryTable>>(),
+            std::io::stdout(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// List the slices of an arc and let the user drill into the ops of one slice at a time.
+fn browse_slices(dbs: &mut CellDatabases, output: OutputFormat) -> anyhow::Result<()> {
+    let arcs = get_slice_hashes(&mut dbs.dht)?
+        .into_iter()
+        .map(|h| (h.arc_start as u32, h.arc_end as u32))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let (arc_start, arc_end) = if arcs.is_empty() {
+        println!("No slice hashes found, enter an arc instead");
+
+        let arc_start: u32 = dialoguer::Input::new()
+            .with_prompt("Enter the arc start")
+            .interact()?;
+        let arc_end: u32 = dialoguer::Input::new()
+            .with_prompt("Enter the arc end")
+            .interact()?;
+
+        (arc_start, arc_end)
+    } else {
+        let selected = dialoguer::Select::new()
+            .with_prompt("Select an arc")
+            .default(0)
+            .items(
+                &arcs
+                    .iter()
+                    .map(|(start, end)| format!("{:?}", DhtArc::Arc(*start, *end)))
+                    .collect::<Vec<_>>(),
+            )
+            .interact()?;
+
+        arcs[selected]
+    };
+
+    let slices = list_slices(&mut dbs.dht, arc_start, arc_end)?;
+    if slices.is_empty() {
+        println!("No full time slices with ops in this arc");
+        return Ok(());
+    }
+
+    render_slices(&slices, output)?;
+
+    let mut items = slices
+        .iter()
+        .map(|s| {
+            format!(
+                "{}: {} to {} ({} ops)",
+                s.slice_index, s.start, s.end, s.op_count
+            )
+        })
+        .collect::<Vec<_>>();
+    items.push(":back".to_string());
+
+    loop {
+        let selected = dialoguer::Select::new()
+            .with_prompt("Select a slice to view its ops")
+            .default(0)
+            .items(&items)
+            .interact()?;
+
+        let Some(slice) = slices.get(selected) else {
+            return Ok(());
+        };
+
+        ops_in_slice(dbs, output, arc_start, arc_end, slice.slice_index)?;
+    }
+}
+
+fn coverage(
+    dbs: &mut CellDatabases,
+    output: OutputFormat,
+    agent_arcs: &[AgentArc],
+    buckets: u32,
+) -> anyhow::Result<()> {
+    let locations = get_op_locations(&mut dbs.dht)?;
+    let slice_hashes = get_slice_hashes(&mut dbs.dht)?;
+
+    let map = coverage_map(&locations, &slice_hashes, agent_arcs.to_vec(), buckets);
+
+    let agents = map
+        .agent_arcs
+        .iter()
+        .map(Into::into)
+        .collect::<Vec<AgentArcTable>>();
+
+    match output {
+        OutputFormat::Table => {
+            let max_ops = map.buckets.iter().map(|b| b.op_count).max().unwrap_or(0);
+
+            println!("Ops by storage location:");
+            map.buckets
+                .iter()
+                .map(|b| CoverageBucketTable::new(b, max_ops))
+                .collect::<Vec<_>>()
+                .render(output, std::io::stdout())?;
+
+            if agents.is_empty() {
+                println!("No agent arcs known, connect to a running conductor to include them");
+            } else {
+                println!("Agent storage arcs:");
+                agents.render(output, std::io::stdout())?;
+            }
+
+            if map.uncovered.is_empty() {
+                println!("Every location is covered by at least one known agent");
+            } else {
+                println!("Locations not covered by any known agent:");
+                for (start, end) in &map.uncovered {
+                    println!("  {start}..={end}");
+                }
+            }
+        }
+        OutputFormat::Json => {
+            output.write_value(
+                &serde_json::json!({
+                    "buckets": map.buckets,
+                    "slice_hash_arcs": map.slice_hash_arcs,
+                    "agent_arcs": agents,
+                    "uncovered": map.uncovered,
+                }),
+                std::io::stdout(),
+            )?;
+        }
+        OutputFormat::Ndjson => {
+            // The arcs are summarised per bucket, so only stream the buckets.
+            output.write_value(&serde_json::to_value(&map.buckets)?, std::io::stdout())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Poll the authored and DHT databases, printing a line for each change until interrupted.
+fn watch(dbs: &mut CellDatabases, output: OutputFormat, interval: Duration) -> anyhow::Result<()> {
+    let mut watcher = Watcher::new(&mut dbs.authored, &mut dbs.dht)?;
+
+    output.note(format!(
+        "Watching for changes every {}s, press Ctrl+C to stop",
+        interval.as_secs()
+    ));
+
+    let mut stdout = std::io::stdout();
+    loop {
+        std::thread::sleep(interval);
+
+        for event in watcher.poll(&mut dbs.authored, &mut dbs.dht)? {
+            let row = WatchEventTable::from(event);
+
+            if output.is_structured() {
+                serde_json::to_writer(&mut stdout, &row)?;
+                writeln!(stdout)?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{} {:<8} {:<18} {} {}",
+                    row.seen_at, row.database, row.event, row.hash, row.detail
+                )?;
+            }
+        }
+
+        stdout.flush()?;
+    }
+}
+
+fn dump(dbs: &mut CellDatabases, output: OutputFormat, filter: ScanFilter) -> anyhow::Result<()> {
+    let mut dump = DumpWriter::new(output, std::io::stdout().lock())?;
+
+    dump.section(
+        "authored_ops",
+        "Authored ops",
+        iter_dht_ops(&mut dbs.authored, filter.clone(), DEFAULT_PAGE_SIZE).map(|op| {
+            let op: ChainOp<AuthoredMeta> = op?.try_into()?;
+            op.as_human_readable_raw()
+        }),
+    )
+    .context("Could not convert authored ops")?;
+
+    dump.section(
+        "authored_actions",
+        "Authored actions",
+        iter_actions(&mut dbs.authored, filter.clone(), DEFAULT_PAGE_SIZE).map(|action| {
+            let action: SignedAction = action?.try_into()?;
+            action.as_human_readable_summary_raw()
+        }),
+    )
+    .context("Could not convert authored actions")?;
+
+    dump.section(
+        "authored_entries",
+        "Authored entries",
+        iter_entries(&mut dbs.authored, filter.clone(), DEFAULT_PAGE_SIZE).map(|entry| {
+            let entry: Entry = entry?.try_into()?;
+            entry.as_human_readable_summary_raw()
+        }),
+    )
+    .context("Could not convert authored entries")?;
+
+    dump.section(
+        "dht_ops",
+        "DHT ops",
+        iter_dht_ops(&mut dbs.dht, filter.clone(), DEFAULT_PAGE_SIZE).map(|op| {
+            let op: ChainOp<DhtMeta> = op?.try_into()?;
+            op.as_human_readable_raw()
+        }),
+    )?;
+
+    dump.section(
+        "dht_actions",
+        "DHT actions",
+        iter_actions(&mut dbs.dht, filter.clone(), DEFAULT_PAGE_SIZE).map(|action| {
+            let action: SignedAction = action?.try_into()?;
+            action.as_human_readable_summary_raw()
+        }),
+    )?;
+
+    dump.section(
+        "cache_ops",
+        "Cache ops",
+        iter_dht_ops(&mut dbs.cache, filter.clone(), DEFAULT_PAGE_SIZE).map(|op| {
+            let op: ChainOp<CacheMeta> = op?.try_into()?;
+            op.as_human_readable_raw()
+        }),
+    )?;
+
+    dump.section(
+        "cache_actions",
+        "Cache actions",
+        iter_actions(&mut dbs.cache, filter, DEFAULT_PAGE_SIZE).map(|action| {
+            let action: SignedAction = action?.try_into()?;
+            action.as_human_readable_summary_raw()
+        }),
+    )?;
+
+    dump.finish()
+}
+
+/// Writes dump sections as they are read, so that large databases never have to be held in
+/// memory.
+///
+/// Table output matches a pretty printed JSON array per section, JSON output is a single object
+/// with one array per section and NDJSON output is one `{section, item}` line per item.
+struct DumpWriter<W: Write> {
+    output: OutputFormat,
+    write: W,
+    first_section: bool,
+}
+
+impl<W: Write> DumpWriter<W> {
+    fn new(output: OutputFormat, mut write: W) -> anyhow::Result<Self> {
+        if output == OutputFormat::Json {
+            write!(write, "{{")?;
+        }
+
+        Ok(Self {
+            output,
+            write,
+            first_section: true,
+        })
+    }
+
+    fn section(
+        &mut self,
+        name: &str,
+        label: &str,
+        items: impl Iterator<Item = HcOpsResult<serde_json::Value>>,
+    ) -> anyhow::Result<()> {
+        match self.output {
+            OutputFormat::Table => write!(self.write, "{label}: [")?,
+            OutputFormat::Json => {
+                if !self.first_section {
+                    write!(self.write, ",")?;
+                }
+                write!(self.write, "{}:[", serde_json::to_string(name)?)?;
+            }
+            OutputFormat::Ndjson => {}
+        }
+        self.first_section = false;
+
+        let mut first_item = true;
+        for item in items {
+            let item = item?;
+
+            match self.output {
+                OutputFormat::Table => {
+                    if !first_item {
+                        write!(self.write, ",")?;
+                    }
+                    write!(self.write, "\n{}", serde_json::to_string_pretty(&item)?)?;
+                }
+                OutputFormat::Json => {
+                    if !first_item {
+                        write!(self.write, ",")?;
+                    }
+                    serde_json::to_writer(&mut self.write, &item)?;
+                }
+                OutputFormat::Ndjson => {
+                    serde_json::to_writer(
+                        &mut self.write,
+                        &serde_json::json!({ "section": name, "item": item }),
+                    )?;
+                    writeln!(self.write)?;
+                }
+            }
+
+            first_item = false;
+        }
+
+        match self.output {
+            OutputFormat::Table => write!(self.write, "\n]\n\n")?,
+            OutputFormat::Json => write!(self.write, "]")?,
+            OutputFormat::Ndjson => {}
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        if self.output == OutputFormat::Json {
+            writeln!(self.write, "}}")?;
+        }
+
+        self.write.flush()?;
+
+        Ok(())
+    }
+}
+
+fn parse_optional<T: std::str::FromStr>(input: &str) -> Result<Option<T>, T::Err> {
+    let input = input.trim();
+    if input.is_empty() {
+        Ok(None)
+    } else {
+        input.parse().map(Some)
+    }
+}
+
+fn select_app<'a>(apps: &[&'a AppInfo]) -> anyhow::Result<Option<&'a AppInfo>> {
     if apps.is_empty() {
         anyhow::bail!("No apps found");
     }
@@ -383,50 +1813,102 @@ fn select_app(apps: &[AppInfo]) -> anyhow::Result<Option<&AppInfo>> {
         return Ok(None);
     }
 
-    Ok(Some(&apps[selected]))
-}
-
-fn select_dna(app: &AppInfo) -> anyhow::Result<Option<&DnaHash>> {
-    let dna_hashes = app
-        .cell_info
-        .values()
-        .flat_map(|cells| {
-            cells.iter().filter_map(|c| match c {
-                CellInfo::Provisioned(cell) => Some((
-                    cell.name.clone(),
-                    cell.cell_id.agent_pubkey(),
-                    cell.cell_id.dna_hash(),
-                )),
-                CellInfo::Cloned(cell) => Some((
-                    format!("{}/{}", cell.name, cell.clone_id),
-                    cell.cell_id.agent_pubkey(),
-                    cell.cell_id.dna_hash(),
-                )),
-                _ => None,
-            })
-        })
-        .collect::<Vec<_>>();
+    Ok(Some(apps[selected]))
+}
 
-    if dna_hashes.is_empty() {
-        eprintln!("No DNAs found");
-        return Ok(None);
+fn select_offline_cell(cells: &[DiscoveredCell]) -> anyhow::Result<Option<&DiscoveredCell>> {
+    if cells.is_empty() {
+        anyhow::bail!("No cells found");
     }
 
     let selected = dialoguer::Select::new()
-        .with_prompt("Select a DNA")
+        .with_prompt("Select a cell")
         .default(0)
         .items(
-            &dna_hashes
+            &cells
                 .iter()
-                .map(|d| format!("{} ({:?}): {:?}", d.0, d.1, d.2))
+                .map(|c| {
+                    let mut missing = Vec::new();
+                    if !c.has_dht {
+                        missing.push("dht");
+                    }
+                    if !c.has_cache {
+                        missing.push("cache");
+                    }
+
+                    if missing.is_empty() {
+                        format!("{:?} ({:?})", c.dna_hash, c.agent_pub_key)
+                    } else {
+                        format!(
+                            "{:?} ({:?}), missing: {}",
+                            c.dna_hash,
+                            c.agent_pub_key,
+                            missing.join(", ")
+                        )
+                    }
+                })
                 .collect::<Vec<_>>(),
         )
+        .item(":exit")
+        .interact()?;
+
+    if selected == cells.len() {
+        return Ok(None);
+    }
+
+    Ok(Some(&cells[selected]))
+}
+
+fn select_cell<'a, 'b>(cells: &'b [AppCell<'a>]) -> anyhow::Result<Option<&'b AppCell<'a>>> {
+    if cells.is_empty() {
+        eprintln!("No cells found");
+        return Ok(None);
+    }
+
+    let selected = dialoguer::Select::new()
+        .with_prompt("Select a cell")
+        .default(0)
+        .items(&cells.iter().map(|c| c.to_string()).collect::<Vec<_>>())
         .item(":back")
         .interact()?;
 
-    if selected == dna_hashes.len() {
+    if selected == cells.len() {
         return Ok(None);
     }
 
-    Ok(Some(dna_hashes[selected].2))
+    Ok(Some(&cells[selected]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(dna: u8, agent: u8) -> DiscoveredCell {
+        DiscoveredCell {
+            dna_hash: DnaHash::from_raw_36(vec![dna; 36]),
+            agent_pub_key: AgentPubKey::from_raw_36(vec![agent; 36]),
+            has_dht: true,
+            has_cache: true,
+        }
+    }
+
+    #[test]
+    fn agents_sharing_a_dna_resolve_to_that_dna() {
+        let cells = [cell(1, 10), cell(1, 11)];
+
+        assert_eq!(
+            DnaHash::from_raw_36(vec![1; 36]),
+            select_dna(&cells, None).unwrap()
+        );
+    }
+
+    #[test]
+    fn several_dnas_need_one_to_be_selected() {
+        let cells = [cell(1, 10), cell(1, 11), cell(2, 10)];
+        let second = DnaHash::from_raw_36(vec![2; 36]);
+
+        assert!(select_dna(&cells, None).is_err());
+        assert_eq!(second, select_dna(&cells, Some(&second)).unwrap());
+        assert!(select_dna(&cells, Some(&DnaHash::from_raw_36(vec![3; 36]))).is_err());
+    }
 }
@@ -0,0 +1,627 @@
+use crate::cli::{ExportArgs, ExportFormat, OutputFormat};
+use crate::connect_admin_client;
+use crate::explore::{
+    AsAnyhowPretty, CellDatabases, CellSelector, resolve_cell, resolve_offline_cell,
+    unlock_database_key,
+};
+use crate::render::Render;
+use anyhow::Context;
+use diesel::SqliteConnection;
+use hc_ops::readable::HumanReadable;
+use hc_ops::retrieve::{
+    AuthoredMeta, CacheMeta, ChainOp, DEFAULT_PAGE_SIZE, DbDhtOp, DbKind, DhtMeta, Feature,
+    OpenMode, ScanFilter, get_slice_hashes, get_warrants, iter_actions, iter_dht_ops, iter_entries,
+};
+use hc_ops::{HcOpsError, HcOpsResult};
+use holo_hash::{AgentPubKeyB64, DnaHashB64};
+use holochain_zome_types::prelude::{AgentPubKey, DnaHash, Entry, SignedAction, Timestamp};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use tabled::Tabled;
+
+/// The version of the archive layout, bumped when sections or their encoding change.
+pub const ARCHIVE_VERSION: u32 = 2;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes an exported archive, written to `manifest.json` alongside the section files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub version: u32,
+    pub format: ExportFormat,
+    pub dna_hash: DnaHashB64,
+    pub agent: AgentPubKeyB64,
+    pub conductor_tag: Option<String>,
+    pub exported_at: String,
+    pub sections: Vec<ExportSection>,
+    /// Sections that were not exported, because the Holochain version doesn't support them.
+    #[serde(default)]
+    pub skipped: Vec<SkippedSection>,
+}
+
+#[derive(Debug, Tabled, Serialize, Deserialize)]
+pub struct ExportSection {
+    pub name: String,
+    pub file: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedSection {
+    pub name: String,
+    pub reason: String,
+}
+
+pub async fn handle_export_command(
+    conn: &mut SqliteConnection,
+    args: ExportArgs,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let dna: Option<DnaHash> = args.dna.map(Into::into);
+    let agent: Option<AgentPubKey> = args.agent.map(Into::into);
+
+    let (dna, agent, conductor_tag) = if args.offline {
+        let (dna, agent) =
+            resolve_offline_cell(&args.data_root_path, dna.as_ref(), agent.as_ref())?;
+
+        (dna, agent, None)
+    } else {
+        let tag = args
+            .tag
+            .ok_or_else(|| anyhow::anyhow!("A tag is required unless --offline is set"))?;
+        let (client, tag) = connect_admin_client(conn, &tag, &args.origin).await?;
+
+        let (dna, agent) = resolve_cell(
+            &client,
+            &CellSelector {
+                app: args.app,
+                role: args.role,
+                clone_id: args.clone_id,
+                dna,
+                agent,
+            },
+        )
+        .await?;
+
+        (dna, agent, Some(tag.tag))
+    };
+
+    let mut key = unlock_database_key(&args.data_root_path, &args.passphrase)?;
+    let mut dbs = CellDatabases::open(
+        &args.data_root_path,
+        &agent,
+        &dna,
+        &mut key,
+        OpenMode::ReadOnly,
+    )?;
+
+    if args.out_dir.join(MANIFEST_FILE).exists() {
+        anyhow::bail!("An archive already exists at: {}", args.out_dir.display());
+    }
+    std::fs::create_dir_all(&args.out_dir).context("Failed to create the archive directory")?;
+
+    let mut archive = ArchiveWriter::new(&args.out_dir, args.format);
+
+    export_databases(&mut dbs, &agent, &mut archive)?;
+
+    let manifest = ExportManifest {
+        version: ARCHIVE_VERSION,
+        format: args.format,
+        dna_hash: dna.into(),
+        agent: agent.into(),
+        conductor_tag,
+        exported_at: Timestamp::now().to_string(),
+        sections: archive.sections,
+        skipped: archive.skipped,
+    };
+
+    let manifest_file = File::create(args.out_dir.join(MANIFEST_FILE))
+        .context("Failed to create the archive manifest")?;
+    serde_json::to_writer_pretty(manifest_file, &manifest)?;
+
+    output.note(format!("Exported to: {}", args.out_dir.display()));
+    for skipped in &manifest.skipped {
+        output.note(format!("Skipped {}: {}", skipped.name, skipped.reason));
+    }
+    manifest.sections.render(output, std::io::stdout())?;
+
+    Ok(())
+}
+
+fn export_databases(
+    dbs: &mut CellDatabases,
+    agent: &AgentPubKey,
+    archive: &mut ArchiveWriter,
+) -> anyhow::Result<()> {
+    let authored = DbKind::Authored(agent.clone());
+
+    if archive.supported(dbs, &authored, Feature::Ops, "authored_ops") {
+        archive.ops_section::<AuthoredMeta>(
+            "authored_ops",
+            iter_dht_ops(&mut dbs.authored, ScanFilter::default(), DEFAULT_PAGE_SIZE),
+        )?;
+    }
+    if archive.supported(dbs, &authored, Feature::Chains, "authored_actions") {
+        archive.section(
+            "authored_actions",
+            iter_actions(&mut dbs.authored, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|action| -> HcOpsResult<SignedAction> { action?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &authored, Feature::Chains, "authored_entries") {
+        archive.section(
+            "authored_entries",
+            iter_entries(&mut dbs.authored, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|entry| -> HcOpsResult<Entry> { entry?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &authored, Feature::Warrants, "authored_warrants") {
+        archive.section(
+            "authored_warrants",
+            get_warrants(&mut dbs.authored, None)
+                .into_anyhow()?
+                .into_iter()
+                .map(Ok),
+        )?;
+    }
+
+    if archive.supported(dbs, &DbKind::Dht, Feature::Ops, "dht_ops") {
+        archive.ops_section::<DhtMeta>(
+            "dht_ops",
+            iter_dht_ops(&mut dbs.dht, ScanFilter::default(), DEFAULT_PAGE_SIZE),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Dht, Feature::Chains, "dht_actions") {
+        archive.section(
+            "dht_actions",
+            iter_actions(&mut dbs.dht, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|action| -> HcOpsResult<SignedAction> { action?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Dht, Feature::Chains, "dht_entries") {
+        archive.section(
+            "dht_entries",
+            iter_entries(&mut dbs.dht, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|entry| -> HcOpsResult<Entry> { entry?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Dht, Feature::Warrants, "dht_warrants") {
+        archive.section(
+            "dht_warrants",
+            get_warrants(&mut dbs.dht, None)
+                .into_anyhow()?
+                .into_iter()
+                .map(Ok),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Dht, Feature::Slices, "slice_hashes") {
+        archive.section(
+            "slice_hashes",
+            get_slice_hashes(&mut dbs.dht)
+                .into_anyhow()?
+                .into_iter()
+                .map(Ok),
+        )?;
+    }
+
+    if archive.supported(dbs, &DbKind::Cache, Feature::Ops, "cache_ops") {
+        archive.ops_section::<CacheMeta>(
+            "cache_ops",
+            iter_dht_ops(&mut dbs.cache, ScanFilter::default(), DEFAULT_PAGE_SIZE),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Cache, Feature::Chains, "cache_actions") {
+        archive.section(
+            "cache_actions",
+            iter_actions(&mut dbs.cache, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|action| -> HcOpsResult<SignedAction> { action?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Cache, Feature::Chains, "cache_entries") {
+        archive.section(
+            "cache_entries",
+            iter_entries(&mut dbs.cache, ScanFilter::default(), DEFAULT_PAGE_SIZE)
+                .map(|entry| -> HcOpsResult<Entry> { entry?.try_into() }),
+        )?;
+    }
+    if archive.supported(dbs, &DbKind::Cache, Feature::Warrants, "cache_warrants") {
+        archive.section(
+            "cache_warrants",
+            get_warrants(&mut dbs.cache, None)
+                .into_anyhow()?
+                .into_iter()
+                .map(Ok),
+        )?;
+    }
+
+    Ok(())
+}
+
+struct ArchiveWriter<'a> {
+    dir: &'a Path,
+    format: ExportFormat,
+    sections: Vec<ExportSection>,
+    skipped: Vec<SkippedSection>,
+}
+
+impl<'a> ArchiveWriter<'a> {
+    fn new(dir: &'a Path, format: ExportFormat) -> Self {
+        Self {
+            dir,
+            format,
+            sections: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Whether the database supports the feature that a section needs, recording the section as
+    /// skipped if it doesn't.
+    fn supported(
+        &mut self,
+        dbs: &CellDatabases,
+        kind: &DbKind,
+        feature: Feature,
+        name: &str,
+    ) -> bool {
+        match dbs.require_in(kind, feature) {
+            Ok(()) => true,
+            Err(e) => {
+                self.skipped.push(SkippedSection {
+                    name: name.to_string(),
+                    reason: e.to_string(),
+                });
+                false
+            }
+        }
+    }
+
+    /// Write one section of the archive to its own file.
+    ///
+    /// JSON sections are written one readable item per line. Binary sections are written as a
+    /// big-endian `u32` length followed by the MessagePack encoding, for each item.
+    fn section<T: HumanReadable + Serialize + Debug>(
+        &mut self,
+        name: &str,
+        items: impl Iterator<Item = HcOpsResult<T>>,
+    ) -> anyhow::Result<()> {
+        let format = self.format;
+        self.write_section(name, items, |write, item| match format {
+            ExportFormat::Json => write_json_item(write, &item),
+            ExportFormat::Binary => write_binary_item(write, &item),
+        })
+    }
+
+    /// Write a section of ops.
+    ///
+    /// JSON sections have the readable form of each op, with the metadata for its database.
+    /// Binary sections have the whole row, so that every column can be read back.
+    fn ops_section<Meta>(
+        &mut self,
+        name: &str,
+        ops: impl Iterator<Item = HcOpsResult<DbDhtOp>>,
+    ) -> anyhow::Result<()>
+    where
+        Meta: Debug + Serialize + DeserializeOwned,
+        ChainOp<Meta>: TryFrom<DbDhtOp, Error = HcOpsError>,
+    {
+        let format = self.format;
+        self.write_section(name, ops, |write, op| match format {
+            ExportFormat::Json => write_json_item(write, &ChainOp::<Meta>::try_from(op)?),
+            ExportFormat::Binary => write_binary_item(write, &op),
+        })
+    }
+
+    fn write_section<T>(
+        &mut self,
+        name: &str,
+        items: impl Iterator<Item = HcOpsResult<T>>,
+        mut write_item: impl FnMut(&mut BufWriter<File>, T) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let file = match self.format {
+            ExportFormat::Json => format!("{name}.ndjson"),
+            ExportFormat::Binary => format!("{name}.msgpack"),
+        };
+
+        let mut write = BufWriter::new(
+            File::create(self.dir.join(&file))
+                .with_context(|| format!("Failed to create archive file: {file}"))?,
+        );
+
+        let mut count = 0;
+        for item in items {
+            write_item(&mut write, item.into_anyhow()?)?;
+            count += 1;
+        }
+
+        write.flush()?;
+
+        self.sections.push(ExportSection {
+            name: name.to_string(),
+            file,
+            count,
+        });
+
+        Ok(())
+    }
+}
+
+fn write_json_item<T: HumanReadable>(write: &mut impl Write, item: &T) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *write, &item.as_human_readable_raw()?)?;
+    writeln!(write)?;
+
+    Ok(())
+}
+
+fn write_binary_item<T: Serialize + Debug>(write: &mut impl Write, item: &T) -> anyhow::Result<()> {
+    let bytes = holochain_serialized_bytes::encode(item)?;
+    write.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    write.write_all(&bytes)?;
+
+    Ok(())
+}
+
+/// Whether a directory contains an exported archive.
+pub fn is_archive(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).is_file()
+}
+
+pub fn read_manifest(dir: &Path) -> anyhow::Result<ExportManifest> {
+    let file =
+        File::open(dir.join(MANIFEST_FILE)).context("Failed to open the archive manifest")?;
+    let manifest: ExportManifest =
+        serde_json::from_reader(BufReader::new(file)).context("Invalid archive manifest")?;
+
+    if manifest.version != ARCHIVE_VERSION {
+        anyhow::bail!(
+            "Unsupported archive version {}, expected {}",
+            manifest.version,
+            ARCHIVE_VERSION
+        );
+    }
+
+    Ok(manifest)
+}
+
+/// Read every item in a section of a binary archive.
+///
+/// JSON archives use the readable form, which can't be read back, so they are rejected.
+pub fn read_binary_section<T: DeserializeOwned>(
+    dir: &Path,
+    manifest: &ExportManifest,
+    name: &str,
+) -> anyhow::Result<Vec<T>> {
+    if manifest.format != ExportFormat::Binary {
+        anyhow::bail!(
+            "The archive at {} is not binary, re-export it with `--format binary`",
+            dir.display()
+        );
+    }
+
+    if let Some(skipped) = manifest.skipped.iter().find(|s| s.name == name) {
+        anyhow::bail!(
+            "The archive has no {name} section, it was skipped on export: {}",
+            skipped.reason
+        );
+    }
+
+    let section = manifest
+        .sections
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| anyhow::anyhow!("The archive has no {name} section"))?;
+
+    let mut read = BufReader::new(
+        File::open(dir.join(&section.file))
+            .with_context(|| format!("Failed to open archive file: {}", section.file))?,
+    );
+
+    let mut out = Vec::with_capacity(section.count);
+    loop {
+        let mut len = [0u8; 4];
+        match read.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut bytes = vec![0; u32::from_be_bytes(len) as usize];
+        read.read_exact(&mut bytes)
+            .with_context(|| format!("Truncated archive file: {}", section.file))?;
+
+        out.push(holochain_serialized_bytes::decode(&bytes)?);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hc_ops::retrieve::{DbOpType, ValidationStage, ValidationStatus};
+    use std::path::PathBuf;
+
+    fn temp_archive_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hc-ops-export-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn manifest(format: ExportFormat, sections: Vec<ExportSection>) -> ExportManifest {
+        ExportManifest {
+            version: ARCHIVE_VERSION,
+            format,
+            dna_hash: DnaHash::from_raw_36(vec![1; 36]).into(),
+            agent: AgentPubKey::from_raw_36(vec![2; 36]).into(),
+            conductor_tag: None,
+            exported_at: Timestamp(0).to_string(),
+            sections,
+            skipped: Vec::new(),
+        }
+    }
+
+    fn db_op(i: u8) -> DbDhtOp {
+        DbDhtOp {
+            hash: holo_hash::DhtOpHash::from_raw_36(vec![i; 36])
+                .get_raw_39()
+                .to_vec(),
+            typ: Some(DbOpType::StoreRecord),
+            basis_hash: Some(
+                holo_hash::ActionHash::from_raw_36(vec![i; 36])
+                    .get_raw_39()
+                    .to_vec(),
+            ),
+            action_hash: Some(
+                holo_hash::ActionHash::from_raw_36(vec![i; 36])
+                    .get_raw_39()
+                    .to_vec(),
+            ),
+            require_receipt: Some(true),
+            storage_center_loc: Some(3_000_000_000),
+            authored_timestamp: Some(10),
+            op_order: format!("0-{i}"),
+            validation_status: Some(ValidationStatus::Valid),
+            when_integrated: Some(20),
+            withhold_publish: None,
+            receipts_complete: None,
+            last_publish_time: None,
+            validation_stage: Some(ValidationStage::AwaitingIntegration),
+            num_validation_attempts: Some(2),
+            last_validation_attempt: Some(15),
+            when_sys_validated: Some(12),
+            when_app_validated: Some(14),
+            when_stored: Some(11),
+            serialized_size: Some(200),
+            transfer_source: Some(vec![i; 39]),
+            transfer_method: Some(1),
+            transfer_time: Some(11),
+        }
+    }
+
+    #[test]
+    fn binary_ops_round_trip() {
+        let dir = temp_archive_dir("round-trip");
+
+        let mut archive = ArchiveWriter::new(&dir, ExportFormat::Binary);
+        archive
+            .ops_section::<DhtMeta>("dht_ops", [Ok(db_op(1)), Ok(db_op(2))].into_iter())
+            .unwrap();
+        let manifest = manifest(ExportFormat::Binary, archive.sections);
+
+        // Each item is framed by its length, as a big-endian `u32`.
+        let bytes = std::fs::read(dir.join("dht_ops.msgpack")).unwrap();
+        let first_len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        let second_len =
+            u32::from_be_bytes(bytes[4 + first_len..8 + first_len].try_into().unwrap()) as usize;
+        assert_eq!(bytes.len(), 8 + first_len + second_len);
+
+        let ops = read_binary_section::<DbDhtOp>(&dir, &manifest, "dht_ops").unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(2, manifest.sections[0].count);
+        assert_eq!(2, ops.len());
+        let op = &ops[1];
+        let expected = db_op(2);
+        assert_eq!(expected.hash, op.hash);
+        assert_eq!(expected.storage_center_loc, op.storage_center_loc);
+        assert_eq!(expected.op_order, op.op_order);
+        assert_eq!(expected.when_integrated, op.when_integrated);
+        assert_eq!(expected.when_sys_validated, op.when_sys_validated);
+        assert_eq!(expected.when_app_validated, op.when_app_validated);
+        assert_eq!(expected.serialized_size, op.serialized_size);
+        assert_eq!(expected.transfer_source, op.transfer_source);
+        assert_eq!(expected.transfer_method, op.transfer_method);
+        assert_eq!(expected.transfer_time, op.transfer_time);
+    }
+
+    #[test]
+    fn truncated_binary_section_is_an_error() {
+        let dir = temp_archive_dir("truncated");
+
+        let mut archive = ArchiveWriter::new(&dir, ExportFormat::Binary);
+        archive
+            .ops_section::<DhtMeta>("dht_ops", [Ok(db_op(1))].into_iter())
+            .unwrap();
+        let manifest = manifest(ExportFormat::Binary, archive.sections);
+
+        let path = dir.join("dht_ops.msgpack");
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+
+        let result = read_binary_section::<DbDhtOp>(&dir, &manifest, "dht_ops");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn json_archive_is_not_read_as_binary() {
+        let dir = temp_archive_dir("json");
+
+        let mut archive = ArchiveWriter::new(&dir, ExportFormat::Json);
+        archive
+            .ops_section::<DhtMeta>("dht_ops", [Ok(db_op(1))].into_iter())
+            .unwrap();
+        let manifest = manifest(ExportFormat::Json, archive.sections);
+
+        let result = read_binary_section::<DbDhtOp>(&dir, &manifest, "dht_ops");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn skipped_section_is_reported() {
+        let dir = temp_archive_dir("skipped");
+
+        let mut manifest = manifest(ExportFormat::Binary, Vec::new());
+        manifest.skipped.push(SkippedSection {
+            name: "slice_hashes".to_string(),
+            reason: "The DHT database is missing: SliceHash".to_string(),
+        });
+
+        let result = read_binary_section::<DbDhtOp>(&dir, &manifest, "slice_hashes");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("skipped on export: The DHT database is missing: SliceHash")
+        );
+    }
+
+    #[test]
+    fn reject_other_archive_versions() {
+        let dir = temp_archive_dir("version");
+
+        let write_manifest = |manifest: &ExportManifest| {
+            let file = File::create(dir.join(MANIFEST_FILE)).unwrap();
+            serde_json::to_writer_pretty(file, manifest).unwrap();
+        };
+
+        let mut current = manifest(ExportFormat::Binary, Vec::new());
+        write_manifest(&current);
+        let read_current = read_manifest(&dir);
+
+        current.version = ARCHIVE_VERSION - 1;
+        write_manifest(&current);
+        let read_old = read_manifest(&dir);
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(ARCHIVE_VERSION, read_current.unwrap().version);
+        assert!(
+            read_old
+                .unwrap_err()
+                .to_string()
+                .contains("Unsupported archive version")
+        );
+    }
+}
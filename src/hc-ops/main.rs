@@ -2,10 +2,17 @@ use crate::cli::admin::handle_admin_command;
 use crate::cli::agent_tag::handle_agent_tag_command;
 use crate::cli::conductor_tag::handle_conductor_tag_command;
 use crate::cli::explore::handle_explore_command;
+use crate::cli::health::handle_health_command;
 use crate::cli::init::handle_init_command;
+use crate::cli::key::handle_key_command;
+use crate::cli::metrics::handle_serve_metrics_command;
+use crate::cli::report::handle_report_command;
+#[cfg(unix)]
+use crate::cli::unlock::handle_unlock_command;
 use crate::cli::{Cli, Commands};
 use crate::compare::handle_compare_command;
 use crate::data::ConductorTag;
+use crate::export::handle_export_command;
 use anyhow::Context;
 use clap::Parser;
 use diesel::{Connection, SqliteConnection};
@@ -20,9 +27,11 @@ mod cli;
 mod compare;
 mod data;
 mod explore;
+mod export;
 mod interactive;
 mod render;
 mod schema;
+mod slice_file;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -44,24 +53,46 @@ async fn main() -> anyhow::Result<()> {
     conn.run_pending_migrations(MIGRATIONS)
         .map_err(|e| anyhow::anyhow!("Failed to run migrations: {}", e))?;
 
+    let output = cli.output;
+
     match cli.command {
         Commands::ConductorTag(args) => {
-            handle_conductor_tag_command(&mut conn, args).await?;
+            handle_conductor_tag_command(&mut conn, args, output).await?;
         }
         Commands::AgentTag(args) => {
-            handle_agent_tag_command(&mut conn, args).await?;
+            handle_agent_tag_command(&mut conn, args, output).await?;
         }
         Commands::Admin(args) => {
-            handle_admin_command(&mut conn, args).await?;
+            handle_admin_command(&mut conn, args, output).await?;
         }
         Commands::Init(args) => {
-            handle_init_command(&mut conn, args).await?;
+            handle_init_command(&mut conn, args, output).await?;
         }
         Commands::Explore(args) => {
-            handle_explore_command(&mut conn, args).await?;
+            handle_explore_command(&mut conn, args, output).await?;
         }
         Commands::Compare(args) => {
-            handle_compare_command(args)?;
+            handle_compare_command(&mut conn, args, output).await?;
+        }
+        Commands::Export(args) => {
+            handle_export_command(&mut conn, args, output).await?;
+        }
+        Commands::Health(args) => {
+            let status = handle_health_command(&mut conn, args, output).await?;
+            std::process::exit(status.exit_code());
+        }
+        Commands::ServeMetrics(args) => {
+            handle_serve_metrics_command(&mut conn, args, output).await?;
+        }
+        Commands::Report(args) => {
+            handle_report_command(&mut conn, args, output).await?;
+        }
+        #[cfg(unix)]
+        Commands::Unlock(args) => {
+            handle_unlock_command(args, output)?;
+        }
+        Commands::Key(args) => {
+            handle_key_command(args, output)?;
         }
     }
 
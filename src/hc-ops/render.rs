@@ -1,9 +1,17 @@
+use crate::cli::OutputFormat;
 use crate::data::{AgentTag, ConductorTag};
 use base64::Engine;
-use hc_ops::retrieve::SliceHash;
-use holochain_conductor_api::{StorageBlob, StorageInfo};
+use hc_ops::readable::HumanReadable;
+use hc_ops::retrieve::{
+    AgentArc, CoverageBucket, DiagnosedOp, DiagnosisGroup, PageStats, SliceHash, SliceOp,
+    SliceSummary, WatchEvent, WatchEventKind,
+};
+use hc_ops::verify::ChainViolation;
+use holochain_conductor_api::DnaStorageInfo;
 use holochain_zome_types::prelude::{AgentPubKey, DnaHash};
 use kitsune2_api::DhtArc;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
 use std::io;
 use std::io::Write;
 use tabled::settings::Style;
@@ -15,37 +23,139 @@ fn flush(mut write: impl Write) -> io::Result<()> {
     Ok(())
 }
 
-#[derive(Tabled)]
+fn serialize_display<T: Display, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(value)
+}
+
+fn serialize_optional_display<T: Display, S: Serializer>(
+    value: &Option<T>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => s.collect_str(value),
+        None => s.serialize_none(),
+    }
+}
+
+/// Write rows as a table, for types that are only meant for table output.
+///
+/// Table rows are formatted for reading, so structured output should be written from the values
+/// they are made from instead.
+pub fn write_table<Row: Tabled>(rows: &[Row], mut write: impl Write) -> io::Result<()> {
+    let _ = write.write(
+        Table::new(rows)
+            .with(Style::modern_rounded())
+            .to_string()
+            .as_bytes(),
+    )?;
+    flush(write)
+}
+
+impl OutputFormat {
+    pub fn is_structured(&self) -> bool {
+        !matches!(self, OutputFormat::Table)
+    }
+
+    /// Print a progress or status message.
+    ///
+    /// Goes to stdout for table output, and to stderr for structured output so that stdout only
+    /// contains data.
+    pub fn note(&self, message: impl Display) {
+        if self.is_structured() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    }
+
+    /// Write a JSON value in a structured format.
+    ///
+    /// For NDJSON, arrays are written as one line per item.
+    pub fn write_value(&self, value: &serde_json::Value, mut write: impl Write) -> io::Result<()> {
+        match (self, value) {
+            (OutputFormat::Ndjson, serde_json::Value::Array(items)) => {
+                for item in items {
+                    serde_json::to_writer(&mut write, item)?;
+                    let _ = write.write(b"\n")?;
+                }
+                write.flush()
+            }
+            (OutputFormat::Table, _) => {
+                serde_json::to_writer_pretty(&mut write, value)?;
+                flush(write)
+            }
+            _ => {
+                serde_json::to_writer(&mut write, value)?;
+                flush(write)
+            }
+        }
+    }
+
+    /// Write a [HumanReadable] value, prefixed with a label for table output.
+    pub fn write_human_readable(
+        &self,
+        label: &str,
+        value: &impl HumanReadable,
+        summary: bool,
+        mut write: impl Write,
+    ) -> anyhow::Result<()> {
+        let value = if summary {
+            value.as_human_readable_summary_raw()?
+        } else {
+            value.as_human_readable_raw()?
+        };
+
+        if !self.is_structured() {
+            write!(write, "{label}: ")?;
+        }
+
+        self.write_value(&value, write)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Tabled, Serialize)]
 pub struct InitStatus<'a> {
     pub app_id: &'a str,
     pub role: &'a str,
+    #[serde(serialize_with = "serialize_display")]
     pub dna_hash: &'a DnaHash,
     pub initialised: bool,
 }
 
 pub trait Render {
-    fn render(&self, write: impl Write) -> io::Result<()>;
+    fn render(&self, format: OutputFormat, write: impl Write) -> io::Result<()>;
 }
 
 impl<Item> Render for Vec<Item>
 where
-    Item: Tabled,
+    Item: Tabled + Serialize,
 {
-    fn render(&self, mut write: impl Write) -> io::Result<()> {
-        let _ = write.write(
-            Table::new(self)
-                .with(Style::modern_rounded())
-                .to_string()
-                .as_bytes(),
-        )?;
-        flush(write)
+    fn render(&self, format: OutputFormat, write: impl Write) -> io::Result<()> {
+        match format {
+            OutputFormat::Table => write_table(self, write),
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                format.write_value(&serde_json::to_value(self)?, write)
+            }
+        }
     }
 }
 
+/// A DNA's storage info for structured output, with sizes in bytes as Holochain reports them.
+#[derive(Serialize)]
+pub struct StorageInfoRecord<'a> {
+    /// Holochain doesn't say which DNA is which, so this is `None` if it couldn't be worked out.
+    #[serde(serialize_with = "serialize_optional_display")]
+    pub dna: Option<&'a DnaHash>,
+    #[serde(flatten)]
+    pub info: &'a DnaStorageInfo,
+}
+
 #[derive(Tabled)]
 pub struct StorageInfoBlob {
     pub referenced_by_apps: String,
-    // TODO Holochain API does not return which DNA is which!
+    /// Holochain doesn't say which DNA is which, so this is "unknown" if it couldn't be worked out.
     pub dna: String,
     pub authored: String,
     pub authored_on_disk: String,
@@ -55,32 +165,120 @@ pub struct StorageInfoBlob {
     pub cache_on_disk: String,
 }
 
-impl Render for StorageInfo {
-    fn render(&self, write: impl Write) -> io::Result<()> {
-        let t = self
-            .blobs
-            .iter()
-            .map(|b| match b {
-                StorageBlob::Dna(dna) => StorageInfoBlob {
-                    referenced_by_apps: dna.used_by.join(", "),
-                    dna: "unknown".to_string(),
-                    authored: human_bytes::human_bytes(dna.authored_data_size as f64),
-                    authored_on_disk: human_bytes::human_bytes(
-                        dna.authored_data_size_on_disk as f64,
-                    ),
-                    dht: human_bytes::human_bytes(dna.dht_data_size as f64),
-                    dht_on_disk: human_bytes::human_bytes(dna.dht_data_size_on_disk as f64),
-                    cache: human_bytes::human_bytes(dna.cache_data_size as f64),
-                    cache_on_disk: human_bytes::human_bytes(dna.cache_data_size_on_disk as f64),
-                },
-            })
-            .collect::<Vec<_>>();
+impl StorageInfoBlob {
+    pub fn new(dna_hash: Option<&DnaHash>, dna: &DnaStorageInfo) -> Self {
+        Self {
+            referenced_by_apps: dna.used_by.join(", "),
+            dna: dna_hash
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            authored: human_bytes::human_bytes(dna.authored_data_size as f64),
+            authored_on_disk: human_bytes::human_bytes(dna.authored_data_size_on_disk as f64),
+            dht: human_bytes::human_bytes(dna.dht_data_size as f64),
+            dht_on_disk: human_bytes::human_bytes(dna.dht_data_size_on_disk as f64),
+            cache: human_bytes::human_bytes(dna.cache_data_size as f64),
+            cache_on_disk: human_bytes::human_bytes(dna.cache_data_size_on_disk as f64),
+        }
+    }
+}
+
+/// The storage used by a DNA, with sizes in bytes.
+#[derive(Serialize)]
+pub struct StorageReport {
+    /// `None` if the storage couldn't be attributed to a DNA.
+    #[serde(serialize_with = "serialize_optional_display")]
+    pub dna: Option<DnaHash>,
+    pub roles: Vec<String>,
+    pub authored_on_disk: u64,
+    pub dht_on_disk: u64,
+    pub cache_on_disk: u64,
+    pub used: u64,
+    /// The total size of the database files, if the data root path was given.
+    pub measured_on_disk: Option<u64>,
+}
 
-        t.render(write)
+#[derive(Tabled)]
+pub struct StorageReportTable {
+    pub dna: String,
+    pub roles: String,
+    pub authored_on_disk: String,
+    pub dht_on_disk: String,
+    pub cache_on_disk: String,
+    pub used: String,
+    pub measured_on_disk: String,
+}
+
+impl From<&StorageReport> for StorageReportTable {
+    fn from(report: &StorageReport) -> Self {
+        Self {
+            dna: report
+                .dna
+                .as_ref()
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            roles: report.roles.join(", "),
+            authored_on_disk: human_bytes::human_bytes(report.authored_on_disk as f64),
+            dht_on_disk: human_bytes::human_bytes(report.dht_on_disk as f64),
+            cache_on_disk: human_bytes::human_bytes(report.cache_on_disk as f64),
+            used: human_bytes::human_bytes(report.used as f64),
+            measured_on_disk: report
+                .measured_on_disk
+                .map(|m| human_bytes::human_bytes(m as f64))
+                .unwrap_or_else(|| "-".to_string()),
+        }
+    }
+}
+
+/// How the pages of one database are used, with sizes in bytes.
+#[derive(Serialize)]
+pub struct DatabasePages {
+    #[serde(serialize_with = "serialize_display")]
+    pub dna: DnaHash,
+    pub database: String,
+    #[serde(flatten)]
+    pub stats: PageStats,
+    pub on_disk: u64,
+    pub free: u64,
+}
+
+impl DatabasePages {
+    pub fn new(dna: &DnaHash, database: String, stats: PageStats) -> Self {
+        Self {
+            dna: dna.clone(),
+            database,
+            stats,
+            on_disk: stats.on_disk(),
+            free: stats.free(),
+        }
     }
 }
 
 #[derive(Tabled)]
+pub struct DatabasePagesTable {
+    pub dna: String,
+    pub database: String,
+    pub page_size: i64,
+    pub pages: i64,
+    pub free_pages: i64,
+    pub on_disk: String,
+    pub free: String,
+}
+
+impl From<&DatabasePages> for DatabasePagesTable {
+    fn from(pages: &DatabasePages) -> Self {
+        Self {
+            dna: pages.dna.to_string(),
+            database: pages.database.clone(),
+            page_size: pages.stats.page_size,
+            pages: pages.stats.page_count,
+            free_pages: pages.stats.freelist_count,
+            on_disk: human_bytes::human_bytes(pages.on_disk as f64),
+            free: human_bytes::human_bytes(pages.free as f64),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
 pub struct AgentTagTable {
     pub agent: String,
     pub tag: String,
@@ -98,7 +296,7 @@ impl From<AgentTag> for AgentTagTable {
     }
 }
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 pub struct ConductorTagTable {
     pub tag: String,
     pub address: String,
@@ -115,7 +313,7 @@ impl From<ConductorTag> for ConductorTagTable {
     }
 }
 
-#[derive(Tabled)]
+#[derive(Tabled, Serialize)]
 pub struct SliceHashTable {
     pub dht_arc: String,
     pub slice_index: u64,
@@ -134,3 +332,230 @@ impl From<SliceHash> for SliceHashTable {
         }
     }
 }
+
+#[derive(Tabled)]
+pub struct SliceSummaryTable {
+    pub slice_index: u64,
+    pub start: String,
+    pub end: String,
+    pub ops: usize,
+    pub size: String,
+}
+
+impl From<&SliceSummary> for SliceSummaryTable {
+    fn from(slice: &SliceSummary) -> Self {
+        Self {
+            slice_index: slice.slice_index,
+            start: slice.start.to_string(),
+            end: slice.end.to_string(),
+            ops: slice.op_count,
+            size: human_bytes::human_bytes(slice.total_size as f64),
+        }
+    }
+}
+
+#[derive(Tabled)]
+pub struct SliceOpTable {
+    pub op_hash: String,
+    pub loc: u32,
+    pub size: String,
+}
+
+impl From<SliceOp> for SliceOpTable {
+    fn from(op: SliceOp) -> Self {
+        Self {
+            op_hash: op.hash.to_string(),
+            loc: op.storage_center_loc,
+            size: op
+                .serialized_size
+                .map(|size| human_bytes::human_bytes(size as f64))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
+pub struct WatchEventTable {
+    pub seen_at: String,
+    pub database: String,
+    pub event: String,
+    pub hash: String,
+    pub detail: String,
+}
+
+impl From<WatchEvent> for WatchEventTable {
+    fn from(event: WatchEvent) -> Self {
+        let (name, hash, detail) = match event.kind {
+            WatchEventKind::NewOp {
+                hash,
+                typ,
+                validation_status,
+            } => (
+                "new_op",
+                hash.to_string(),
+                format!(
+                    "{} {}",
+                    display_debug_optional(&typ),
+                    display_debug_optional(&validation_status)
+                ),
+            ),
+            WatchEventKind::Integrated {
+                hash,
+                when_integrated,
+            } => ("integrated", hash.to_string(), when_integrated.to_string()),
+            WatchEventKind::ValidationStatusChanged { hash, from, to } => (
+                "validation_status",
+                hash.to_string(),
+                format!(
+                    "{} -> {}",
+                    display_debug_optional(&from),
+                    display_debug_optional(&to)
+                ),
+            ),
+            WatchEventKind::ValidationAttempted {
+                hash,
+                num_validation_attempts,
+            } => (
+                "validation_attempt",
+                hash.to_string(),
+                format!("attempt {num_validation_attempts}"),
+            ),
+            WatchEventKind::NewAction { hash, typ, seq } => (
+                "new_action",
+                hash.to_string(),
+                format!("{typ} at seq {seq}"),
+            ),
+        };
+
+        Self {
+            seen_at: event.seen_at.to_string(),
+            database: event.database.to_string(),
+            event: name.to_string(),
+            hash,
+            detail,
+        }
+    }
+}
+
+/// The widest histogram bar in a coverage table.
+const HISTOGRAM_WIDTH: usize = 40;
+
+#[derive(Tabled, Serialize)]
+pub struct CoverageBucketTable {
+    pub range: String,
+    pub ops: usize,
+    pub histogram: String,
+    pub slice_hashes: String,
+    pub agents: usize,
+    pub gap: String,
+}
+
+impl CoverageBucketTable {
+    /// Histogram bars are scaled so that the bucket with `max_ops` fills the full width.
+    pub fn new(bucket: &CoverageBucket, max_ops: usize) -> Self {
+        let bar = if max_ops == 0 {
+            0
+        } else {
+            (bucket.op_count * HISTOGRAM_WIDTH).div_ceil(max_ops)
+        };
+
+        Self {
+            range: format!("{:?}", DhtArc::Arc(bucket.start, bucket.end)),
+            ops: bucket.op_count,
+            histogram: "#".repeat(bar),
+            slice_hashes: if bucket.in_slice_hash_arc { "yes" } else { "" }.to_string(),
+            agents: bucket.agent_count,
+            gap: if bucket.has_gap { "UNCOVERED" } else { "" }.to_string(),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
+pub struct AgentArcTable {
+    pub agent: String,
+    pub arc: String,
+}
+
+impl From<&AgentArc> for AgentArcTable {
+    fn from(agent_arc: &AgentArc) -> Self {
+        Self {
+            agent: agent_arc.agent.to_string(),
+            arc: format!("{:?}", agent_arc.arc),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
+pub struct ChainViolationTable {
+    pub seq: u32,
+    pub action_hash: String,
+    pub problem: String,
+}
+
+impl From<ChainViolation> for ChainViolationTable {
+    fn from(violation: ChainViolation) -> Self {
+        Self {
+            seq: violation.seq,
+            action_hash: violation.action_hash.to_string(),
+            problem: violation.kind.to_string(),
+        }
+    }
+}
+
+#[derive(Tabled, Serialize)]
+pub struct DiagnosisGroupTable {
+    pub stage: String,
+    pub status: String,
+    pub count: usize,
+}
+
+impl From<DiagnosisGroup> for DiagnosisGroupTable {
+    fn from(group: DiagnosisGroup) -> Self {
+        Self {
+            stage: display_debug_optional(&group.stage),
+            status: display_debug_optional(&group.status),
+            count: group.count,
+        }
+    }
+}
+
+#[derive(Tabled)]
+pub struct DiagnosedOpTable {
+    pub op_hash: String,
+    pub op_type: String,
+    pub action_hash: String,
+    pub stage: String,
+    pub status: String,
+    pub attempts: u32,
+    pub last_attempt: String,
+    pub missing: String,
+    pub retried_often: bool,
+}
+
+impl From<DiagnosedOp> for DiagnosedOpTable {
+    fn from(op: DiagnosedOp) -> Self {
+        Self {
+            op_hash: op.hash.to_string(),
+            op_type: display_debug_optional(&op.typ),
+            action_hash: op.action.as_hash().to_string(),
+            stage: display_debug_optional(&op.stage),
+            status: display_debug_optional(&op.status),
+            attempts: op.num_validation_attempts,
+            last_attempt: op
+                .last_validation_attempt
+                .map(|t| t.to_string())
+                .unwrap_or_default(),
+            missing: op
+                .missing
+                .iter()
+                .map(|m| format!("{} {}", m.kind, m.hash))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            retried_often: op.retried_often,
+        }
+    }
+}
+
+fn display_debug_optional<T: std::fmt::Debug>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| format!("{v:?}")).unwrap_or_default()
+}
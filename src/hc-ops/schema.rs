@@ -15,4 +15,20 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(addr_tag, agent_tag,);
+diesel::table! {
+    snapshot (id) {
+        id -> Integer,
+        tag -> Text,
+        taken_at -> BigInt,
+        apps -> Text,
+        authored -> BigInt,
+        authored_on_disk -> BigInt,
+        dht -> BigInt,
+        dht_on_disk -> BigInt,
+        cache -> BigInt,
+        cache_on_disk -> BigInt,
+        dna_hash -> Nullable<Text>,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(addr_tag, agent_tag, snapshot,);
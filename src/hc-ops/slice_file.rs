@@ -0,0 +1,165 @@
+use anyhow::Context;
+use base64::Engine;
+use hc_ops::retrieve::SliceHash;
+use holo_hash::DnaHashB64;
+use holochain_zome_types::prelude::{DnaHash, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The version of the slice hash file format, bumped on any incompatible change.
+pub const SLICE_HASH_FILE_VERSION: u32 = 1;
+
+/// Slice hashes written by `explore slice-hashes --out`, for comparing with another node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SliceHashFile {
+    pub version: u32,
+    pub dna_hash: DnaHashB64,
+    pub created_at: String,
+    pub slice_hashes: Vec<SliceHashRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SliceHashRecord {
+    pub arc_start: u32,
+    pub arc_end: u32,
+    pub slice_index: u64,
+    /// Standard base64, with padding.
+    pub hash: String,
+}
+
+impl From<&SliceHash> for SliceHashRecord {
+    fn from(slice_hash: &SliceHash) -> Self {
+        Self {
+            arc_start: slice_hash.arc_start as u32,
+            arc_end: slice_hash.arc_end as u32,
+            slice_index: slice_hash.slice_index as u64,
+            hash: base64::prelude::BASE64_STANDARD.encode(&slice_hash.hash),
+        }
+    }
+}
+
+impl TryFrom<SliceHashRecord> for SliceHash {
+    type Error = anyhow::Error;
+
+    fn try_from(record: SliceHashRecord) -> anyhow::Result<Self> {
+        Ok(SliceHash {
+            arc_start: record.arc_start as i32,
+            arc_end: record.arc_end as i32,
+            slice_index: record.slice_index as i64,
+            hash: base64::prelude::BASE64_STANDARD
+                .decode(&record.hash)
+                .with_context(|| format!("Invalid slice hash: {}", record.hash))?,
+        })
+    }
+}
+
+pub fn write_slice_hash_file(
+    path: &Path,
+    dna_hash: &DnaHash,
+    slice_hashes: &[SliceHash],
+) -> anyhow::Result<()> {
+    let file = SliceHashFile {
+        version: SLICE_HASH_FILE_VERSION,
+        dna_hash: dna_hash.clone().into(),
+        created_at: Timestamp::now().to_string(),
+        slice_hashes: slice_hashes.iter().map(Into::into).collect(),
+    };
+
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)
+        .with_context(|| format!("Failed to write slice hash file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Parse the contents of a slice hash file.
+///
+/// Returns `None` if the content isn't a slice hash file at all, so that the caller can fall back
+/// to another format. A slice hash file with an unsupported version is an error.
+pub fn parse_slice_hash_file(content: &str) -> anyhow::Result<Option<SliceHashFile>> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(content) else {
+        return Ok(None);
+    };
+
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow::anyhow!("Slice hash file has no version"))?;
+    if version != SLICE_HASH_FILE_VERSION as u64 {
+        anyhow::bail!(
+            "Unsupported slice hash file version {version}, expected {SLICE_HASH_FILE_VERSION}"
+        );
+    }
+
+    Ok(Some(
+        serde_json::from_value(value).context("Invalid slice hash file")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slice_hash(slice_index: i64, hash: u8) -> SliceHash {
+        SliceHash {
+            arc_start: 3_000_000_000u32 as i32,
+            arc_end: 100,
+            slice_index,
+            hash: vec![hash; 32],
+        }
+    }
+
+    #[test]
+    fn written_file_parses_back_to_the_same_slice_hashes() {
+        let path = std::env::temp_dir().join(format!(
+            "hc-ops-slice-file-test-{}.json",
+            std::process::id()
+        ));
+        let dna_hash = DnaHash::from_raw_36(vec![1; 36]);
+        let slice_hashes = vec![slice_hash(0, 1), slice_hash(1, 2)];
+
+        write_slice_hash_file(&path, &dna_hash, &slice_hashes).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let file = parse_slice_hash_file(&content).unwrap().unwrap();
+        assert_eq!(SLICE_HASH_FILE_VERSION, file.version);
+        assert_eq!(dna_hash, DnaHash::from(file.dna_hash));
+        assert_eq!(
+            slice_hashes,
+            file.slice_hashes
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<Vec<SliceHash>>>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let content = serde_json::json!({
+            "version": SLICE_HASH_FILE_VERSION + 1,
+            "dna_hash": DnaHashB64::from(DnaHash::from_raw_36(vec![1; 36])),
+            "created_at": "",
+            "slice_hashes": [],
+        })
+        .to_string();
+
+        let err = parse_slice_hash_file(&content).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unsupported slice hash file version")
+        );
+
+        assert!(parse_slice_hash_file("{}").is_err());
+    }
+
+    #[test]
+    fn other_formats_are_left_to_the_caller() {
+        assert!(parse_slice_hash_file("").unwrap().is_none());
+        assert!(
+            parse_slice_hash_file("│ Arc(0, 100) │ 0 │ AAAA │")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -3,6 +3,7 @@ pub mod discover;
 pub mod ops;
 pub mod readable;
 pub mod retrieve;
+pub mod verify;
 
 #[derive(Debug, thiserror::Error)]
 pub enum HcOpsError {
@@ -1,12 +1,15 @@
-use crate::retrieve::{ChainOp, ChainRecord, Record};
+use crate::retrieve::{
+    ChainOp, ChainRecord, DiagnosedOp, EntryHistory, HistoryNode, HistoryOp, LinkRecord, Record,
+    ResolvedWarrant, SliceHash, SliceOp, SliceSummary, WarrantEvidence,
+};
 use crate::{HcOpsError, HcOpsResult, HcOpsResultContextExt};
 use base64::Engine;
 use holo_hash::WarrantHash;
 use holochain_conductor_api::AppInfo;
 use holochain_types::network::Kitsune2NetworkMetrics;
 use holochain_zome_types::prelude::{
-    Action, ActionHash, AgentPubKey, AnyDhtHash, DhtOpHash, DnaHash, Entry, EntryHash,
-    SignedAction, SignedActionHashed, Timestamp,
+    Action, ActionHash, AgentPubKey, AnyDhtHash, ChainIntegrityWarrant, DhtOpHash, DnaHash, Entry,
+    EntryHash, SignedAction, SignedActionHashed, SignedWarrant, Timestamp, WarrantProof,
 };
 use kitsune2_api::{AgentInfoSigned, TransportStats};
 use serde::Serialize;
@@ -158,10 +161,12 @@ impl<S: Debug + Serialize + DeserializeOwned> HumanReadable for ChainOp<S> {
         replace_field(&mut dht_op, "action_hash", transform_action_or_warrant_hash)?;
         replace_field(&mut dht_op, "authored_timestamp", transform_timestamp)?;
 
-        if let Some(meta) = dht_op.get_mut("meta").and_then(|v| v.as_object_mut())
-            && let Some(last_validation_attempt) = meta.get("last_validation_attempt")
-        {
-            meta["last_validation_attempt"] = transform_timestamp(last_validation_attempt)?;
+        if let Some(meta) = dht_op.get_mut("meta").and_then(|v| v.as_object_mut()) {
+            for field in ["when_integrated", "last_validation_attempt"] {
+                if let Some(timestamp) = meta.get(field) {
+                    meta[field] = transform_timestamp(timestamp)?;
+                }
+            }
         }
 
         Ok(dht_op)
@@ -429,6 +434,324 @@ impl HumanReadable for ChainRecord {
     }
 }
 
+impl HumanReadable for LinkRecord {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "state".to_string(),
+            serde_json::Value::String(format!("{:?}", self.state())),
+        );
+        obj.insert(
+            "create_link".to_string(),
+            self.create_link.as_human_readable_raw()?,
+        );
+        obj.insert(
+            "validation_status".to_string(),
+            self.validation_status
+                .map(|s| serde_json::Value::String(format!("{:?}", s)))
+                .unwrap_or(serde_json::Value::Null),
+        );
+
+        let mut deletes = Vec::with_capacity(self.deletes.len());
+        for delete in &self.deletes {
+            let mut delete_obj = serde_json::Map::new();
+            delete_obj.insert(
+                "delete_link".to_string(),
+                delete.delete_link.as_human_readable_raw()?,
+            );
+            delete_obj.insert(
+                "validation_status".to_string(),
+                delete
+                    .validation_status
+                    .map(|s| serde_json::Value::String(format!("{:?}", s)))
+                    .unwrap_or(serde_json::Value::Null),
+            );
+            deletes.push(serde_json::Value::Object(delete_obj));
+        }
+        obj.insert("deletes".to_string(), serde_json::Value::Array(deletes));
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for EntryHistory {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert("roots".to_string(), self.roots.as_human_readable_raw()?);
+        obj.insert(
+            "likely_resolves_to".to_string(),
+            self.likely_resolves_to
+                .as_ref()
+                .map(|h| serde_json::Value::String(format!("{:?}", h)))
+                .unwrap_or(serde_json::Value::Null),
+        );
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for HistoryNode {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert("action".to_string(), self.action.as_human_readable_raw()?);
+        obj.insert(
+            "valid".to_string(),
+            serde_json::Value::Bool(self.is_valid()),
+        );
+        obj.insert(
+            "deleted".to_string(),
+            serde_json::Value::Bool(self.is_deleted()),
+        );
+        obj.insert("ops".to_string(), self.ops.as_human_readable_raw()?);
+        obj.insert("updates".to_string(), self.updates.as_human_readable_raw()?);
+
+        let mut deletes = Vec::with_capacity(self.deletes.len());
+        for delete in &self.deletes {
+            let mut delete_obj = serde_json::Map::new();
+            delete_obj.insert("action".to_string(), delete.action.as_human_readable_raw()?);
+            delete_obj.insert("ops".to_string(), delete.ops.as_human_readable_raw()?);
+            deletes.push(serde_json::Value::Object(delete_obj));
+        }
+        obj.insert("deletes".to_string(), serde_json::Value::Array(deletes));
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for HistoryOp {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "type".to_string(),
+            self.typ
+                .map(|t| serde_json::Value::String(format!("{:?}", t)))
+                .unwrap_or(serde_json::Value::Null),
+        );
+        obj.insert(
+            "validation_status".to_string(),
+            self.validation_status
+                .map(|s| serde_json::Value::String(format!("{:?}", s)))
+                .unwrap_or(serde_json::Value::Null),
+        );
+        obj.insert(
+            "when_integrated".to_string(),
+            self.when_integrated
+                .map(|t| serde_json::Value::String(t.to_string()))
+                .unwrap_or(serde_json::Value::Null),
+        );
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for SignedWarrant {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut out = self.as_human_readable_summary_raw()?;
+
+        let sig = serde_json::from_str(&serde_json::to_string(self.signature())?)?;
+        if let Some(out) = out.as_object_mut() {
+            out.insert("signature".to_string(), transform_flatten_byte_array(&sig)?);
+        }
+
+        Ok(out)
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut out = serde_json::Map::new();
+
+        out.insert(
+            "author".to_string(),
+            serde_json::Value::String(format!("{:?}", self.author)),
+        );
+        out.insert(
+            "timestamp".to_string(),
+            serde_json::Value::String(self.timestamp.to_string()),
+        );
+        out.insert(
+            "warrantee".to_string(),
+            serde_json::Value::String(format!("{:?}", self.warrantee)),
+        );
+
+        let proof = match &self.proof {
+            WarrantProof::ChainIntegrity(ChainIntegrityWarrant::InvalidChainOp {
+                action_author,
+                action,
+                chain_op_type,
+            }) => serde_json::json!({
+                "type": "InvalidChainOp",
+                "action_author": format!("{:?}", action_author),
+                "action": format!("{:?}", action.0),
+                "chain_op_type": format!("{:?}", chain_op_type),
+            }),
+            WarrantProof::ChainIntegrity(ChainIntegrityWarrant::ChainFork {
+                chain_author,
+                action_pair,
+            }) => serde_json::json!({
+                "type": "ChainFork",
+                "chain_author": format!("{:?}", chain_author),
+                "action_pair": [
+                    format!("{:?}", action_pair.0.0),
+                    format!("{:?}", action_pair.1.0),
+                ],
+            }),
+        };
+        out.insert("proof".to_string(), proof);
+
+        Ok(serde_json::Value::Object(out))
+    }
+}
+
+impl HumanReadable for ResolvedWarrant {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert("warrant".to_string(), self.warrant.as_human_readable_raw()?);
+        obj.insert(
+            "evidence".to_string(),
+            self.evidence.as_human_readable_raw()?,
+        );
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "warrant".to_string(),
+            self.warrant.as_human_readable_summary_raw()?,
+        );
+        obj.insert(
+            "evidence".to_string(),
+            self.evidence.as_human_readable_summary_raw()?,
+        );
+
+        Ok(serde_json::Value::Object(obj))
+    }
+}
+
+impl HumanReadable for WarrantEvidence {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "action_hash".to_string(),
+            serde_json::Value::String(format!("{:?}", self.action_hash)),
+        );
+        obj.insert(
+            "action".to_string(),
+            self.action
+                .as_ref()
+                .map(|a| a.as_human_readable_raw())
+                .transpose()?
+                .unwrap_or(serde_json::Value::Null),
+        );
+
+        Ok(serde_json::Value::Object(obj))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for SliceHash {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        Ok(serde_json::json!({
+            "arc_start": self.arc_start as u32,
+            "arc_end": self.arc_end as u32,
+            "slice_index": self.slice_index as u64,
+            "hash": base64::prelude::BASE64_STANDARD.encode(&self.hash),
+        }))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for SliceSummary {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        Ok(serde_json::json!({
+            "slice_index": self.slice_index,
+            "start": self.start.to_string(),
+            "end": self.end.to_string(),
+            "op_count": self.op_count,
+            "total_size": self.total_size,
+        }))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for SliceOp {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        Ok(serde_json::json!({
+            "hash": format!("{:?}", self.hash),
+            "storage_center_loc": self.storage_center_loc,
+            "serialized_size": self.serialized_size,
+        }))
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        self.as_human_readable_raw()
+    }
+}
+
+impl HumanReadable for DiagnosedOp {
+    fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let mut out = self.as_human_readable_summary_raw()?;
+
+        if let Some(out) = out.as_object_mut() {
+            out.insert("action".to_string(), self.action.as_human_readable_raw()?);
+        }
+
+        Ok(out)
+    }
+
+    fn as_human_readable_summary_raw(&self) -> HcOpsResult<serde_json::Value> {
+        let missing = self
+            .missing
+            .iter()
+            .map(|m| {
+                serde_json::json!({
+                    "kind": format!("{:?}", m.kind),
+                    "hash": format!("{:?}", m.hash),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::json!({
+            "hash": format!("{:?}", self.hash),
+            "type": self.typ.map(|t| format!("{:?}", t)),
+            "action_hash": format!("{:?}", self.action.as_hash()),
+            "stage": self.stage.map(|s| format!("{:?}", s)),
+            "status": self.status.map(|s| format!("{:?}", s)),
+            "sys_validated": self.sys_validated,
+            "app_validated": self.app_validated,
+            "num_validation_attempts": self.num_validation_attempts,
+            "last_validation_attempt": self.last_validation_attempt.map(|t| t.to_string()),
+            "missing": missing,
+            "retried_often": self.retried_often,
+        }))
+    }
+}
+
 impl HumanReadable for Record {
     fn as_human_readable_raw(&self) -> HcOpsResult<serde_json::Value> {
         let mut out = serde_json::Map::new();
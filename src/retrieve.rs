@@ -1,24 +1,58 @@
 use crate::{HcOpsError, HcOpsResult};
-use diesel::{Connection, RunQueryDsl, SqliteConnection, sql_query};
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, RunQueryDsl, SqliteConnection};
 use holo_hash::{ActionHash, EntryHash};
 use holochain_types::chain::ChainItem;
 use holochain_types::prelude::{DhtOpHash, Entry, SignedActionHashedExt};
-use holochain_zome_types::prelude::{AgentPubKey, DnaHash, SignedActionHashed};
+use holochain_zome_types::prelude::{
+    Action, AgentPubKey, AgentPubKeyB64, AnyLinkableHash, DnaHash, DnaHashB64, SignedActionHashed,
+};
 use kitsune2_api::{Timestamp, UNIX_TIMESTAMP};
 use kitsune2_dht::UNIT_TIME;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+mod compat;
+pub use compat::*;
+
+mod coverage;
+pub use coverage::*;
+
 mod crypt;
 pub use crypt::*;
 
+mod diagnose;
+pub use diagnose::*;
+
+mod history;
+pub use history::*;
+
 mod model;
 pub use model::*;
 
+mod scan;
+pub use scan::*;
+
 mod schema;
 
+mod snapshot;
+pub use snapshot::*;
+
+mod storage;
+pub use storage::*;
+
+#[cfg(test)]
+mod test_db;
+
+mod warrant;
+pub use warrant::*;
+
+mod watch;
+pub use watch::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DbKind {
     Authored(AgentPubKey),
     Dht,
@@ -31,48 +65,219 @@ pub fn load_database_key<P: AsRef<Path>>(
 ) -> HcOpsResult<Option<Key>> {
     let db_key = data_root_path.as_ref().join("databases").join("db.key");
     Ok(if db_key.exists() {
-        println!("Found database key at {}", db_key.display());
+        eprintln!("Found database key at {}", db_key.display());
         Some(Key::load(db_key, passphrase)?)
     } else {
         None
     })
 }
 
-pub fn open_holochain_database<P: AsRef<Path>>(
+/// A cell found by scanning the `databases` directory of a Holochain data root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredCell {
+    pub dna_hash: DnaHash,
+    pub agent_pub_key: AgentPubKey,
+    pub has_dht: bool,
+    pub has_cache: bool,
+}
+
+/// Find cells from the database files on disk, without needing a running conductor.
+///
+/// Each authored database, named `authored/<dna>-<agent>`, identifies a cell. The DHT and cache
+/// databases are shared by all cells with the same DNA hash.
+pub fn discover_cells<P: AsRef<Path>>(data_root_path: P) -> HcOpsResult<Vec<DiscoveredCell>> {
+    let database_path = data_root_path.as_ref().join("databases");
+
+    let authored_dir = database_path.join("authored");
+    if !authored_dir.is_dir() {
+        return Err(HcOpsError::Other(
+            format!("No authored databases found at {}", authored_dir.display()).into(),
+        ));
+    }
+
+    let mut out = Vec::new();
+    for entry in std::fs::read_dir(&authored_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let Some((dna_hash, agent_pub_key)) = entry
+            .file_name()
+            .to_str()
+            .and_then(parse_authored_database_name)
+        else {
+            continue;
+        };
+
+        let has_dht = database_path
+            .join("dht")
+            .join(dna_hash.to_string())
+            .is_file();
+        let has_cache = database_path
+            .join("cache")
+            .join(dna_hash.to_string())
+            .is_file();
+
+        out.push(DiscoveredCell {
+            dna_hash,
+            agent_pub_key,
+            has_dht,
+            has_cache,
+        });
+    }
+
+    out.sort_by_key(|c| (c.dna_hash.to_string(), c.agent_pub_key.to_string()));
+
+    Ok(out)
+}
+
+/// Parse an authored database file name of the form `<dna>-<agent>`.
+///
+/// The base64 alphabet used for hashes includes `-`, so each `-` is tried as the separator.
+/// Other files, such as `-wal` and `-shm` files, do not parse and are ignored.
+fn parse_authored_database_name(name: &str) -> Option<(DnaHash, AgentPubKey)> {
+    name.match_indices('-').find_map(|(i, _)| {
+        let dna_hash = DnaHashB64::from_b64_str(&name[..i]).ok()?;
+        let agent_pub_key = AgentPubKeyB64::from_b64_str(&name[i + 1..]).ok()?;
+
+        Some((dna_hash.into(), agent_pub_key.into()))
+    })
+}
+
+/// The path to a database file under a Holochain data root.
+pub fn database_path<P: AsRef<Path>>(
     data_root_path: P,
     kind: &DbKind,
     dna_hash: &DnaHash,
-    key: Option<&mut Key>,
-) -> HcOpsResult<SqliteConnection> {
+) -> PathBuf {
     let database_path = data_root_path.as_ref().join("databases");
 
-    let path = match kind {
+    match kind {
         DbKind::Authored(agent_pub_key) => database_path
             .join("authored")
             .join(format!("{}-{}", dna_hash, agent_pub_key)),
         DbKind::Dht => database_path.join("dht").join(dna_hash.to_string()),
         DbKind::Cache => database_path.join("cache").join(dna_hash.to_string()),
+    }
+}
+
+/// How long to wait for the conductor to release a lock on a database before giving up.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How to open a Holochain database, which may be in use by a running conductor.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Open the live database read-only, waiting up to [BUSY_TIMEOUT] for the conductor's locks.
+    #[default]
+    ReadOnly,
+    /// Copy a consistent snapshot of the database to the temp directory, then open the copy.
+    ///
+    /// The conductor's database is only read while copying, so queries never hold its locks. The
+    /// copy doesn't see changes made after it was taken.
+    Snapshot,
+}
+
+pub fn open_holochain_database<P: AsRef<Path>>(
+    data_root_path: P,
+    kind: &DbKind,
+    dna_hash: &DnaHash,
+    mut key: Option<&mut Key>,
+    mode: OpenMode,
+) -> HcOpsResult<SqliteConnection> {
+    let path = database_path(data_root_path, kind, dna_hash);
+
+    // Opening a missing database would otherwise create it in the conductor's data directory.
+    if !path.is_file() {
+        return Err(HcOpsError::Other(
+            format!("Database not found: {}", path.display()).into(),
+        ));
+    }
+
+    let (uri, snapshot) = match mode {
+        OpenMode::ReadOnly => (sqlite_uri(&path, "mode=ro")?, None),
+        OpenMode::Snapshot => {
+            let snapshot = SnapshotDir::create()?;
+            snapshot_database(&path, &snapshot.path(), key.as_deref_mut())?;
+
+            // Nothing else knows about the copy, so SQLite can skip locking it altogether.
+            (
+                sqlite_uri(&snapshot.path(), "mode=ro&immutable=1")?,
+                Some(snapshot),
+            )
+        }
     };
 
-    let mut conn = SqliteConnection::establish(
-        path.to_str()
-            .ok_or_else(|| HcOpsError::Other("Invalid database path".into()))?,
-    )
-    .map_err(HcOpsError::other)?;
+    let mut conn = SqliteConnection::establish(&uri).map_err(HcOpsError::other)?;
+
+    // Set before keying, because applying the key reads the first page of the database.
+    conn.batch_execute(&format!(
+        "PRAGMA busy_timeout = {};",
+        BUSY_TIMEOUT.as_millis()
+    ))?;
 
     if let Some(key) = key {
         apply_key(&mut conn, key)?;
     }
 
+    if let Some(snapshot) = snapshot {
+        // Once the copy has been read it is open, so it can be removed and will be cleaned up when
+        // the connection is closed. On any error above, it is removed when `snapshot` is dropped.
+        check_database_readable(&mut conn)?;
+        drop(snapshot);
+    }
+
     Ok(conn)
 }
 
-pub fn get_all_dht_ops(conn: &mut SqliteConnection) -> Vec<DbDhtOp> {
-    schema::DhtOp::table.load(conn).unwrap()
+/// Write an unencrypted copy of a Holochain database to `out_path`, which must not exist yet.
+///
+/// Attaching the plaintext copy needs a writable connection, so a snapshot is taken and the export
+/// runs on that. The conductor's database is only read, as with [OpenMode::Snapshot].
+pub fn decrypt_database_copy<P: AsRef<Path>>(
+    data_root_path: P,
+    kind: &DbKind,
+    dna_hash: &DnaHash,
+    mut key: Option<&mut Key>,
+    out_path: &Path,
+) -> HcOpsResult<()> {
+    let path = database_path(data_root_path, kind, dna_hash);
+
+    if !path.is_file() {
+        return Err(HcOpsError::Other(
+            format!("Database not found: {}", path.display()).into(),
+        ));
+    }
+
+    let snapshot = SnapshotDir::create()?;
+    snapshot_database(&path, &snapshot.path(), key.as_deref_mut())?;
+
+    let mut conn = SqliteConnection::establish(&sqlite_uri(&snapshot.path(), "mode=rw")?)
+        .map_err(HcOpsError::other)?;
+
+    if let Some(key) = key {
+        apply_key(&mut conn, key)?;
+    }
+
+    export_plaintext(&mut conn, out_path)?;
+
+    // The copy is writable, so SQLite may need its directory until the connection is closed.
+    drop(conn);
+    drop(snapshot);
+
+    Ok(())
 }
 
-pub fn get_all_actions(conn: &mut SqliteConnection) -> Vec<DbAction> {
-    schema::Action::table.load(conn).unwrap()
+/// A `file:` URI for a database, so that it can be opened with query parameters such as `mode`.
+pub(crate) fn sqlite_uri(path: &Path, params: &str) -> HcOpsResult<String> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| HcOpsError::Other("Invalid database path".into()))?
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+
+    Ok(format!("file:{path}?{params}"))
 }
 
 /// Get all DHT ops for a given action hash.
@@ -93,10 +298,6 @@ pub fn get_ops_by_action_hash(
     Ok(loaded)
 }
 
-pub fn get_all_entries(conn: &mut SqliteConnection) -> Vec<DbEntry> {
-    schema::Entry::table.load(conn).unwrap()
-}
-
 /// Get all DHT ops for a given entry hash.
 ///
 /// This will return all ops for the entry, including those that have not yet been integrated into the DHT (i.e. those with `when_integrated` set to null).
@@ -323,6 +524,135 @@ impl TryFrom<(DbAction, ValidationStatus, Option<Vec<u8>>)> for ChainRecord {
     }
 }
 
+/// A link, as created by a `CreateLink` action, along with any `DeleteLink` actions for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub create_link: SignedActionHashed,
+    /// The validation status of the `RegisterAddLink` op.
+    pub validation_status: Option<ValidationStatus>,
+    pub deletes: Vec<LinkDelete>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkDelete {
+    pub delete_link: SignedActionHashed,
+    /// The validation status of the `RegisterRemoveLink` op.
+    pub validation_status: Option<ValidationStatus>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkState {
+    /// The link has been validated and has not been deleted.
+    Live,
+    /// The link has been deleted by a valid `DeleteLink`.
+    Tombstoned,
+    /// The link has not been validated yet.
+    Pending,
+    /// The link was rejected or abandoned by validation.
+    Invalid,
+}
+
+impl LinkRecord {
+    pub fn state(&self) -> LinkState {
+        match self.validation_status {
+            None => LinkState::Pending,
+            Some(ValidationStatus::Rejected) | Some(ValidationStatus::Abandoned) => {
+                LinkState::Invalid
+            }
+            Some(ValidationStatus::Valid) => {
+                if self
+                    .deletes
+                    .iter()
+                    .any(|d| matches!(d.validation_status, Some(ValidationStatus::Valid)))
+                {
+                    LinkState::Tombstoned
+                } else {
+                    LinkState::Live
+                }
+            }
+        }
+    }
+}
+
+/// Get all links from a base, including deleted links.
+///
+/// Works against either a DHT or a cache database. Links can be narrowed down by zome index,
+/// link type and a prefix of the link tag.
+pub fn get_links_by_base(
+    conn: &mut SqliteConnection,
+    base: &AnyLinkableHash,
+    zome_index: Option<u8>,
+    link_type: Option<u8>,
+    tag_prefix: Option<&[u8]>,
+) -> HcOpsResult<Vec<LinkRecord>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    let creates = schema::Action::table
+        .inner_join(schema::DhtOp::table)
+        .filter(action_fields::typ.eq("CreateLink"))
+        .filter(dht_op_fields::typ.eq("RegisterAddLink"))
+        .filter(action_fields::base_hash.eq(base.get_raw_39()))
+        .select((DbAction::as_select(), dht_op_fields::validation_status))
+        .distinct()
+        .load::<(DbAction, Option<ValidationStatus>)>(conn)?;
+
+    let deletes = schema::Action::table
+        .inner_join(schema::DhtOp::table)
+        .filter(action_fields::typ.eq("DeleteLink"))
+        .filter(dht_op_fields::typ.eq("RegisterRemoveLink"))
+        .filter(action_fields::base_hash.eq(base.get_raw_39()))
+        .select((DbAction::as_select(), dht_op_fields::validation_status))
+        .distinct()
+        .load::<(DbAction, Option<ValidationStatus>)>(conn)?;
+
+    let mut deletes_by_create = HashMap::<ActionHash, Vec<LinkDelete>>::new();
+    for (action, validation_status) in deletes {
+        let delete_link = SignedActionHashed::from_content_sync(action.try_into()?);
+
+        if let Action::DeleteLink(delete) = delete_link.action() {
+            deletes_by_create
+                .entry(delete.link_add_address.clone())
+                .or_default()
+                .push(LinkDelete {
+                    delete_link,
+                    validation_status,
+                });
+        }
+    }
+
+    let mut out = Vec::new();
+    for (action, validation_status) in creates {
+        let create_link = SignedActionHashed::from_content_sync(action.try_into()?);
+
+        let Action::CreateLink(create) = create_link.action() else {
+            continue;
+        };
+
+        if zome_index.is_some_and(|z| create.zome_index.0 != z)
+            || link_type.is_some_and(|t| create.link_type.0 != t)
+            || tag_prefix.is_some_and(|p| !create.tag.0.starts_with(p))
+        {
+            continue;
+        }
+
+        let deletes = deletes_by_create
+            .remove(create_link.as_hash())
+            .unwrap_or_default();
+
+        out.push(LinkRecord {
+            create_link,
+            validation_status,
+            deletes,
+        });
+    }
+
+    out.sort_by_key(|l| l.create_link.action().timestamp());
+
+    Ok(out)
+}
+
 pub struct Record {
     pub dht_op: ChainOp<DhtMeta>,
     pub action: SignedActionHashed,
@@ -356,6 +686,37 @@ pub fn get_pending_ops(dht: &mut SqliteConnection) -> HcOpsResult<Vec<Record>> {
         .collect::<HcOpsResult<Vec<_>>>()
 }
 
+/// Count the DHT ops that have not yet been integrated.
+pub fn count_pending_ops(dht: &mut SqliteConnection) -> HcOpsResult<usize> {
+    use diesel::prelude::*;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    let count = schema::DhtOp::table
+        .filter(dht_op_fields::when_integrated.is_null())
+        .count()
+        .get_result::<i64>(dht)?;
+
+    Ok(count as usize)
+}
+
+/// Count the DHT ops that have not yet been integrated, by validation stage.
+pub fn count_pending_ops_by_stage(
+    dht: &mut SqliteConnection,
+) -> HcOpsResult<Vec<(Option<ValidationStage>, usize)>> {
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    Ok(schema::DhtOp::table
+        .filter(dht_op_fields::when_integrated.is_null())
+        .group_by(dht_op_fields::validation_stage)
+        .select((dht_op_fields::validation_stage, count_star()))
+        .load::<(Option<ValidationStage>, i64)>(dht)?
+        .into_iter()
+        .map(|(stage, count)| (stage, count as usize))
+        .collect())
+}
+
 impl TryFrom<(DbDhtOp, Vec<u8>, Option<Vec<u8>>)> for Record {
     type Error = HcOpsError;
 
@@ -374,65 +735,169 @@ impl TryFrom<(DbDhtOp, Vec<u8>, Option<Vec<u8>>)> for Record {
     }
 }
 
-pub fn get_slice_hashes(authored: &mut SqliteConnection) -> HcOpsResult<Vec<SliceHash>> {
+pub fn get_slice_hashes(dht: &mut SqliteConnection) -> HcOpsResult<Vec<SliceHash>> {
     use diesel::prelude::*;
 
     let loaded = schema::SliceHash::table
         .select(SliceHash::as_select())
-        .load::<SliceHash>(authored)?;
+        .load::<SliceHash>(dht)?;
 
     Ok(loaded)
 }
 
+/// Get DHT ops by their hashes, along with the author of the action that each op is for.
+///
+/// Hashes that are not held are skipped.
+pub fn get_ops_with_authors(
+    dht: &mut SqliteConnection,
+    hashes: &[DhtOpHash],
+) -> HcOpsResult<Vec<(ChainOp<DhtMeta>, AgentPubKey)>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    let loaded = schema::DhtOp::table
+        .inner_join(schema::Action::table)
+        .filter(dht_op_fields::hash.eq_any(hashes.iter().map(|h| h.get_raw_39().to_vec())))
+        .select((DbDhtOp::as_select(), action_fields::author))
+        .load::<(DbDhtOp, Vec<u8>)>(dht)?;
+
+    loaded
+        .into_iter()
+        .map(
+            |(op, author)| -> HcOpsResult<(ChainOp<DhtMeta>, AgentPubKey)> {
+                Ok((op.try_into()?, AgentPubKey::try_from_raw_39(author)?))
+            },
+        )
+        .collect()
+}
+
+/// An op in a time slice, as it is counted towards the slice hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceOp {
+    pub hash: DhtOpHash,
+    pub storage_center_loc: u32,
+    pub serialized_size: Option<u32>,
+}
+
+/// Get the integrated ops in one full time slice of an arc, which make up its slice hash.
 pub fn get_ops_in_slice(
     dht: &mut SqliteConnection,
     arc_start: u32,
     arc_end: u32,
     slice_index: u64,
-) -> HcOpsResult<Vec<DhtOpHash>> {
+) -> HcOpsResult<Vec<SliceOp>> {
     use diesel::prelude::*;
+    use schema::DhtOp::dsl as dht_op_fields;
 
     let (time_start, time_end) = time_bounds_for_slice_index(slice_index);
 
-    #[derive(QueryableByName, PartialEq, Debug)]
-    #[diesel(table_name = crate::retrieve::schema::DhtOp)]
-    #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-    struct SizedOps {
-        hash: Vec<u8>,
-        storage_center_loc: Option<i32>,
-        serialized_size: Option<i32>,
-    }
+    // The same ops as `OP_HASHES_IN_TIME_SLICE` in `holochain_sqlite`, but built against our own
+    // schema, so that a change to the conductor's query can't break this one.
+    let loaded = schema::DhtOp::table
+        .filter(dht_op_fields::when_integrated.is_not_null())
+        .filter(dht_op_fields::storage_center_loc.ge(arc_start as i64))
+        .filter(dht_op_fields::storage_center_loc.le(arc_end as i64))
+        .filter(dht_op_fields::authored_timestamp.ge(time_start.as_micros()))
+        .filter(dht_op_fields::authored_timestamp.lt(time_end.as_micros()))
+        .select((
+            dht_op_fields::hash,
+            dht_op_fields::storage_center_loc,
+            dht_op_fields::serialized_size,
+        ))
+        .load::<(Vec<u8>, Option<i64>, Option<i32>)>(dht)?;
 
-    let mut query = holochain_sqlite::sql::sql_dht::OP_HASHES_IN_TIME_SLICE.to_string();
-    query = query.replace("SELECT", "SELECT storage_center_loc,");
-
-    let rows: Vec<SizedOps> = sql_query(query)
-        .bind::<diesel::sql_types::Integer, _>(arc_start as i32)
-        .bind::<diesel::sql_types::Integer, _>(arc_end as i32)
-        .bind::<diesel::sql_types::BigInt, _>(time_start.as_micros())
-        .bind::<diesel::sql_types::BigInt, _>(time_end.as_micros())
-        .get_results(dht)?;
-
-    println!("Found hashes with locations:");
-    for r in &rows {
-        println!(
-            "{:?} @ {}",
-            DhtOpHash::from_raw_39(r.hash.clone()),
-            r.storage_center_loc.unwrap() as u32
-        );
+    loaded
+        .into_iter()
+        .map(
+            |(hash, storage_center_loc, serialized_size)| -> HcOpsResult<SliceOp> {
+                Ok(SliceOp {
+                    hash: DhtOpHash::try_from_raw_39(hash)?,
+                    storage_center_loc: storage_center_loc.unwrap_or_default() as u32,
+                    serialized_size: serialized_size.map(|s| s as u32),
+                })
+            },
+        )
+        .collect()
+}
+
+/// The integrated ops in one full time slice of an arc.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceSummary {
+    pub slice_index: u64,
+    pub start: holochain_zome_types::prelude::Timestamp,
+    pub end: holochain_zome_types::prelude::Timestamp,
+    pub op_count: usize,
+    pub total_size: u64,
+}
+
+/// List the full time slices of an arc, from the slice holding its earliest integrated op up to
+/// the last slice that has ended.
+///
+/// Slices with no ops in between are included, so that gaps are visible. Ops authored after the
+/// last full slice are not in any full slice, so they are not counted.
+pub fn list_slices(
+    dht: &mut SqliteConnection,
+    arc_start: u32,
+    arc_end: u32,
+) -> HcOpsResult<Vec<SliceSummary>> {
+    use diesel::prelude::*;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    // Locations are stored as unsigned 32-bit values, so compare them as 64-bit integers.
+    let loaded = schema::DhtOp::table
+        .filter(dht_op_fields::when_integrated.is_not_null())
+        .filter(dht_op_fields::storage_center_loc.ge(arc_start as i64))
+        .filter(dht_op_fields::storage_center_loc.le(arc_end as i64))
+        .select((
+            dht_op_fields::authored_timestamp,
+            dht_op_fields::serialized_size,
+        ))
+        .load::<(Option<i64>, Option<i32>)>(dht)?;
+
+    let slice_micros = full_slice_duration().as_micros() as i64;
+    let full_slices =
+        ((Timestamp::now().as_micros() - UNIX_TIMESTAMP.as_micros()) / slice_micros).max(0) as u64;
+
+    let mut by_index = HashMap::<u64, (usize, u64)>::new();
+    for (authored_timestamp, serialized_size) in loaded {
+        let Some(authored_timestamp) = authored_timestamp else {
+            continue;
+        };
+
+        let slice_index = ((authored_timestamp - UNIX_TIMESTAMP.as_micros()) / slice_micros) as u64;
+        if slice_index >= full_slices {
+            continue;
+        }
+
+        let (count, size) = by_index.entry(slice_index).or_default();
+        *count += 1;
+        *size += serialized_size.unwrap_or_default() as u64;
     }
 
-    println!("\n\n");
+    let Some(first) = by_index.keys().min().copied() else {
+        return Ok(Vec::new());
+    };
 
-    Ok(rows
-        .into_iter()
-        .map(|o| DhtOpHash::try_from_raw_39(o.hash))
-        .collect::<Result<Vec<_>, _>>()?)
+    Ok((first..full_slices)
+        .map(|slice_index| {
+            let (start, end) = time_bounds_for_slice_index(slice_index);
+            let (op_count, total_size) = by_index.get(&slice_index).copied().unwrap_or_default();
+
+            SliceSummary {
+                slice_index,
+                start: holochain_zome_types::prelude::Timestamp(start.as_micros()),
+                end: holochain_zome_types::prelude::Timestamp(end.as_micros()),
+                op_count,
+                total_size,
+            }
+        })
+        .collect())
 }
 
-fn time_bounds_for_slice_index(slice_index: u64) -> (Timestamp, Timestamp) {
-    // See [TimePartition::new] in `kitsune2_dht`.
-    let full_slice_duration = Duration::from_secs((1u64 << 9) * UNIT_TIME.as_secs());
+/// Get the start and end of a full time slice, as used by the slice hashes.
+pub fn time_bounds_for_slice_index(slice_index: u64) -> (Timestamp, Timestamp) {
+    let full_slice_duration = full_slice_duration();
 
     // See [TimePartition::time_bounds_for_full_slice_index] in `kitsune2_dht`.
     let start = UNIX_TIMESTAMP + Duration::from_secs(slice_index * full_slice_duration.as_secs());
@@ -441,6 +906,11 @@ fn time_bounds_for_slice_index(slice_index: u64) -> (Timestamp, Timestamp) {
     (start, end)
 }
 
+fn full_slice_duration() -> Duration {
+    // See [TimePartition::new] in `kitsune2_dht`.
+    Duration::from_secs((1u64 << 9) * UNIT_TIME.as_secs())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -520,6 +990,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn parse_authored_database_names() {
+        let dna_hash = DnaHash::from_raw_36(vec![1; 36]);
+        let agent_pub_key = AgentPubKey::from_raw_36(vec![2; 36]);
+
+        let name = format!("{dna_hash}-{agent_pub_key}");
+        assert_eq!(
+            Some((dna_hash.clone(), agent_pub_key.clone())),
+            parse_authored_database_name(&name)
+        );
+
+        assert_eq!(None, parse_authored_database_name(&format!("{name}-wal")));
+        assert_eq!(None, parse_authored_database_name(&format!("{name}-shm")));
+        assert_eq!(None, parse_authored_database_name(&dna_hash.to_string()));
+    }
+
     #[test]
     fn skip_record_already_in_chain() {
         let mut chain = Vec::new();
@@ -533,4 +1019,61 @@ mod tests {
 
         assert_eq!(chain.len(), 5);
     }
+
+    #[test]
+    fn list_slices_with_ops_in_the_upper_half_of_the_ring() {
+        let mut dht = test_db::open();
+        let (start, _) = time_bounds_for_slice_index(0);
+
+        for (i, loc) in [(1, 100), (2, 3_000_000_000), (3, u32::MAX)] {
+            let mut op = test_db::TestOp::new(i);
+            op.storage_center_loc = loc;
+            op.authored_timestamp = start.as_micros() + i as i64;
+            op.insert(&mut dht);
+        }
+
+        let full = list_slices(&mut dht, 0, u32::MAX).unwrap();
+        assert_eq!(3, full[0].op_count);
+        assert_eq!(300, full[0].total_size);
+
+        let upper = list_slices(&mut dht, 1 << 31, u32::MAX).unwrap();
+        assert_eq!(0, upper[0].slice_index);
+        assert_eq!(2, upper[0].op_count);
+
+        let lower = list_slices(&mut dht, 0, (1 << 31) - 1).unwrap();
+        assert_eq!(1, lower[0].op_count);
+    }
+
+    #[test]
+    fn get_ops_in_slice_in_the_upper_half_of_the_ring() {
+        let mut dht = test_db::open();
+        let (start, end) = time_bounds_for_slice_index(1);
+
+        let mut in_slice = test_db::TestOp::new(1);
+        in_slice.storage_center_loc = 3_000_000_000;
+        in_slice.authored_timestamp = start.as_micros();
+        in_slice.insert(&mut dht);
+
+        let mut lower = test_db::TestOp::new(2);
+        lower.storage_center_loc = 100;
+        lower.authored_timestamp = start.as_micros();
+        lower.insert(&mut dht);
+
+        let mut next_slice = test_db::TestOp::new(3);
+        next_slice.storage_center_loc = 3_000_000_000;
+        next_slice.authored_timestamp = end.as_micros();
+        next_slice.insert(&mut dht);
+
+        let mut not_integrated = test_db::TestOp::new(4);
+        not_integrated.storage_center_loc = 3_000_000_000;
+        not_integrated.authored_timestamp = start.as_micros();
+        not_integrated.when_integrated = None;
+        not_integrated.insert(&mut dht);
+
+        let ops = get_ops_in_slice(&mut dht, 1 << 31, u32::MAX, 1).unwrap();
+
+        assert_eq!(1, ops.len());
+        assert_eq!(in_slice.hash, ops[0].hash);
+        assert_eq!(3_000_000_000, ops[0].storage_center_loc);
+    }
 }
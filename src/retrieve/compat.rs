@@ -0,0 +1,247 @@
+use crate::HcOpsResult;
+use crate::retrieve::{DbKind, schema};
+use diesel::{Column, QueryableByName, RunQueryDsl, SqliteConnection, sql_query};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+/// The columns that hc-ops reads from each table, which must match `schema.rs`.
+macro_rules! expected_columns {
+    ($table:ident: $($column:ident),* $(,)?) => {
+        (
+            stringify!($table),
+            &[$(<schema::$table::$column as Column>::NAME),*],
+        )
+    };
+}
+
+static EXPECTED: &[(&str, &[&str])] = &[
+    expected_columns!(DhtOp:
+        hash, typ, basis_hash, action_hash, require_receipt, storage_center_loc,
+        authored_timestamp, op_order, validation_status, when_integrated, withhold_publish,
+        receipts_complete, last_publish_time, validation_stage, num_validation_attempts,
+        last_validation_attempt, when_sys_validated, when_app_validated, when_stored,
+        serialized_size, transfer_source, transfer_method, transfer_time,
+    ),
+    expected_columns!(Entry:
+        hash, blob, tag, grantor, cap_secret, functions, access_type, access_secret,
+        access_assignees,
+    ),
+    expected_columns!(Action:
+        hash, typ, seq, author, blob, prev_hash, entry_hash, entry_type, private_entry,
+        original_entry_hash, original_action_hash, deletes_entry_hash, deletes_action_hash,
+        base_hash, zome_index, link_type, tag, create_link_hash, membrane_proof, prev_dna_hash,
+    ),
+    expected_columns!(Warrant: hash, author, timestamp, warrantee, typ, blob),
+    expected_columns!(SliceHash: arc_start, arc_end, slice_index, hash),
+];
+
+/// The tables and columns of a Holochain database, as found when it was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseSchema {
+    /// The schema version, which Holochain increments with each of its migrations.
+    pub user_version: i64,
+    pub tables: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DatabaseSchema {
+    /// The expected tables and columns that this database doesn't have, as `Table` for a missing
+    /// table or `Table.column` for a missing column.
+    pub fn missing(&self, tables: &[&str]) -> Vec<String> {
+        let mut missing = Vec::new();
+
+        for (table, columns) in EXPECTED.iter().filter(|(t, _)| tables.contains(t)) {
+            let Some(found) = self.tables.get(*table) else {
+                missing.push(table.to_string());
+                continue;
+            };
+
+            missing.extend(
+                columns
+                    .iter()
+                    .filter(|c| !found.contains(**c))
+                    .map(|c| format!("{table}.{c}")),
+            );
+        }
+
+        missing
+    }
+
+    /// Columns of the expected tables that hc-ops doesn't know about, as `Table.column`.
+    ///
+    /// These are usually added by a newer version of Holochain. They are ignored, so they don't
+    /// stop anything from working.
+    pub fn unknown(&self) -> Vec<String> {
+        EXPECTED
+            .iter()
+            .filter_map(|(table, columns)| Some((table, columns, self.tables.get(*table)?)))
+            .flat_map(|(table, columns, found)| {
+                found
+                    .iter()
+                    .filter(|c| !columns.contains(&c.as_str()))
+                    .map(move |c| format!("{table}.{c}"))
+            })
+            .collect()
+    }
+
+    /// Check that this database has everything a feature needs, returning what is missing.
+    pub fn check(&self, feature: Feature) -> Result<(), Vec<String>> {
+        let missing = self.missing(feature.tables());
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+}
+
+/// Read the schema version, tables and columns of a database.
+pub fn read_database_schema(conn: &mut SqliteConnection) -> HcOpsResult<DatabaseSchema> {
+    #[derive(QueryableByName)]
+    struct UserVersion {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        user_version: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct TableColumn {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        table_name: String,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        column_name: String,
+    }
+
+    let user_version = sql_query("SELECT user_version FROM pragma_user_version()")
+        .get_result::<UserVersion>(conn)?
+        .user_version;
+
+    let mut tables = BTreeMap::<String, BTreeSet<String>>::new();
+    for row in sql_query(
+        "SELECT m.name AS table_name, c.name AS column_name \
+         FROM sqlite_master AS m, pragma_table_info(m.name) AS c \
+         WHERE m.type = 'table'",
+    )
+    .load::<TableColumn>(conn)?
+    {
+        tables
+            .entry(row.table_name)
+            .or_default()
+            .insert(row.column_name);
+    }
+
+    Ok(DatabaseSchema {
+        user_version,
+        tables,
+    })
+}
+
+/// A group of hc-ops features that read the same tables.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Feature {
+    /// Source chains and entries, such as the agent chain and entry history. The validation status
+    /// of each action comes from its ops.
+    Chains,
+    /// Ops and their validation and integration state, such as pending ops and links.
+    Ops,
+    Warrants,
+    /// Slice hashes and the ops in each time slice, used for comparing DHTs.
+    Slices,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [
+        Feature::Chains,
+        Feature::Ops,
+        Feature::Warrants,
+        Feature::Slices,
+    ];
+
+    pub fn tables(&self) -> &'static [&'static str] {
+        match self {
+            Feature::Chains => &["Action", "Entry", "DhtOp"],
+            Feature::Ops => &["DhtOp", "Action"],
+            Feature::Warrants => &["Warrant"],
+            Feature::Slices => &["SliceHash", "DhtOp"],
+        }
+    }
+
+    /// Whether anything using the feature reads this kind of database.
+    ///
+    /// Warrants are read from every database, because exports include the warrants that were
+    /// authored. Slice hashes are only kept in the DHT database.
+    pub fn reads(&self, kind: &DbKind) -> bool {
+        match self {
+            Feature::Chains | Feature::Ops | Feature::Warrants => true,
+            Feature::Slices => matches!(kind, DbKind::Dht),
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::Chains => write!(f, "chains"),
+            Feature::Ops => write!(f, "ops"),
+            Feature::Warrants => write!(f, "warrants"),
+            Feature::Slices => write!(f, "slices"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(tables: &[(&str, &[&str])]) -> DatabaseSchema {
+        DatabaseSchema {
+            user_version: 1,
+            tables: tables
+                .iter()
+                .map(|(table, columns)| {
+                    (
+                        table.to_string(),
+                        columns.iter().map(|c| c.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_columns() {
+        let mut tables = EXPECTED.to_vec();
+        tables.retain(|(table, _)| *table != "SliceHash");
+        let mut schema = schema(&tables);
+
+        let warrant = schema.tables.get_mut("Warrant").unwrap();
+        warrant.remove("warrantee");
+        warrant.insert("added_later".to_string());
+
+        assert_eq!(Ok(()), schema.check(Feature::Chains));
+        assert_eq!(Ok(()), schema.check(Feature::Ops));
+        assert_eq!(
+            Err(vec!["Warrant.warrantee".to_string()]),
+            schema.check(Feature::Warrants)
+        );
+        assert_eq!(
+            Err(vec!["SliceHash".to_string()]),
+            schema.check(Feature::Slices)
+        );
+        assert_eq!(vec!["Warrant.added_later".to_string()], schema.unknown());
+    }
+
+    #[test]
+    fn features_read_the_databases_they_query() {
+        let authored = DbKind::Authored(holo_hash::AgentPubKey::from_raw_36(vec![1; 36]));
+
+        for kind in [&authored, &DbKind::Dht, &DbKind::Cache] {
+            assert!(Feature::Chains.reads(kind));
+            assert!(Feature::Ops.reads(kind));
+            assert!(Feature::Warrants.reads(kind));
+        }
+
+        assert!(Feature::Slices.reads(&DbKind::Dht));
+        assert!(!Feature::Slices.reads(&authored));
+        assert!(!Feature::Slices.reads(&DbKind::Cache));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::HcOpsResult;
+use crate::retrieve::{SliceHash, schema};
+use diesel::SqliteConnection;
+use holo_hash::AgentPubKey;
+use kitsune2_api::DhtArc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The number of buckets the location ring is split into by default.
+pub const DEFAULT_COVERAGE_BUCKETS: u32 = 64;
+
+const RING_SIZE: u64 = 1 << 32;
+
+/// How the ops in the DHT database are spread over the location ring, and which parts of the ring
+/// are claimed by slice hashes and by known agents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverageMap {
+    pub buckets: Vec<CoverageBucket>,
+    /// The distinct arcs that slice hashes have been computed for.
+    pub slice_hash_arcs: Vec<DhtArc>,
+    pub agent_arcs: Vec<AgentArc>,
+    /// Inclusive ranges of the ring that no known agent's storage arc covers.
+    pub uncovered: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverageBucket {
+    pub start: u32,
+    /// Inclusive.
+    pub end: u32,
+    pub op_count: usize,
+    /// Whether any slice hash arc overlaps this bucket.
+    pub in_slice_hash_arc: bool,
+    /// The number of agents whose storage arc overlaps this bucket.
+    pub agent_count: usize,
+    /// Whether any part of this bucket is covered by no known agent.
+    pub has_gap: bool,
+}
+
+/// The storage arc claimed by an agent in its agent info.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentArc {
+    pub agent: AgentPubKey,
+    pub arc: DhtArc,
+}
+
+/// Get the storage location of every op in the DHT database.
+pub fn get_op_locations(dht: &mut SqliteConnection) -> HcOpsResult<Vec<u32>> {
+    use diesel::prelude::*;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    Ok(schema::DhtOp::table
+        .filter(dht_op_fields::storage_center_loc.is_not_null())
+        .select(dht_op_fields::storage_center_loc)
+        .load::<Option<i64>>(dht)?
+        .into_iter()
+        .flatten()
+        .map(|loc| loc as u32)
+        .collect())
+}
+
+/// Bucket op locations across the ring, and overlay slice hash and agent arcs.
+///
+/// The bucket count is clamped to at least one.
+pub fn coverage_map(
+    locations: &[u32],
+    slice_hashes: &[SliceHash],
+    agent_arcs: Vec<AgentArc>,
+    bucket_count: u32,
+) -> CoverageMap {
+    let bucket_count = bucket_count.max(1) as u64;
+    let bucket_size = RING_SIZE.div_ceil(bucket_count);
+
+    let mut buckets = (0..bucket_count)
+        .map(|i| i * bucket_size)
+        .take_while(|start| *start < RING_SIZE)
+        .map(|start| CoverageBucket {
+            start: start as u32,
+            end: (start + bucket_size - 1).min(u32::MAX as u64) as u32,
+            op_count: 0,
+            in_slice_hash_arc: false,
+            agent_count: 0,
+            has_gap: false,
+        })
+        .collect::<Vec<_>>();
+
+    for loc in locations {
+        buckets[(*loc as u64 / bucket_size) as usize].op_count += 1;
+    }
+
+    let slice_hash_arcs = slice_hashes
+        .iter()
+        .map(|h| (h.arc_start as u32, h.arc_end as u32))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|(start, end)| DhtArc::Arc(start, end))
+        .collect::<Vec<_>>();
+
+    let uncovered = uncovered_ranges(agent_arcs.iter().map(|a| &a.arc));
+
+    for bucket in &mut buckets {
+        let range = (bucket.start, bucket.end);
+
+        bucket.in_slice_hash_arc = slice_hash_arcs.iter().any(|arc| arc_overlaps(arc, range));
+        bucket.agent_count = agent_arcs
+            .iter()
+            .filter(|a| arc_overlaps(&a.arc, range))
+            .count();
+        bucket.has_gap = uncovered.iter().any(|gap| overlaps(*gap, range));
+    }
+
+    CoverageMap {
+        buckets,
+        slice_hash_arcs,
+        agent_arcs,
+        uncovered,
+    }
+}
+
+/// The ranges of the ring that none of the arcs cover, in order.
+fn uncovered_ranges<'a>(arcs: impl Iterator<Item = &'a DhtArc>) -> Vec<(u32, u32)> {
+    let mut intervals = arcs.flat_map(arc_intervals).collect::<Vec<_>>();
+    intervals.sort();
+
+    let mut gaps = Vec::new();
+    // The first location that is not yet known to be covered.
+    let mut next = 0u64;
+    for (start, end) in intervals {
+        if start as u64 > next {
+            gaps.push((next as u32, start - 1));
+        }
+        next = next.max(end as u64 + 1);
+    }
+
+    if next < RING_SIZE {
+        gaps.push((next as u32, u32::MAX));
+    }
+
+    gaps
+}
+
+/// Split an arc into inclusive intervals that don't wrap around the ring.
+fn arc_intervals(arc: &DhtArc) -> Vec<(u32, u32)> {
+    match *arc {
+        DhtArc::Empty => Vec::new(),
+        DhtArc::Arc(start, end) if start <= end => vec![(start, end)],
+        DhtArc::Arc(start, end) => vec![(start, u32::MAX), (0, end)],
+    }
+}
+
+fn arc_overlaps(arc: &DhtArc, range: (u32, u32)) -> bool {
+    arc_intervals(arc)
+        .into_iter()
+        .any(|interval| overlaps(interval, range))
+}
+
+fn overlaps(a: (u32, u32), b: (u32, u32)) -> bool {
+    a.0 <= b.1 && b.0 <= a.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncovered_ranges_handle_wrapping_arcs() {
+        assert_eq!(
+            vec![(0, u32::MAX)],
+            uncovered_ranges([DhtArc::Empty].iter())
+        );
+
+        let arcs = [
+            DhtArc::Arc(u32::MAX - 9, 9),
+            DhtArc::Arc(5, 100),
+            DhtArc::Arc(200, 300),
+        ];
+        assert_eq!(
+            vec![(101, 199), (301, u32::MAX - 10)],
+            uncovered_ranges(arcs.iter())
+        );
+    }
+
+    #[test]
+    fn ops_and_arcs_are_bucketed() {
+        let quarter = 1u32 << 30;
+        let agent = AgentPubKey::from_raw_36(vec![1; 36]);
+
+        let map = coverage_map(
+            &[0, 1, quarter, u32::MAX],
+            &[SliceHash {
+                arc_start: 0,
+                arc_end: (quarter - 1) as i32,
+                slice_index: 0,
+                hash: vec![],
+            }],
+            vec![AgentArc {
+                agent,
+                arc: DhtArc::Arc(3 * quarter, quarter - 1),
+            }],
+            4,
+        );
+
+        assert_eq!(
+            vec![2, 1, 0, 1],
+            map.buckets.iter().map(|b| b.op_count).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![true, false, false, false],
+            map.buckets
+                .iter()
+                .map(|b| b.in_slice_hash_arc)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![false, true, true, false],
+            map.buckets.iter().map(|b| b.has_gap).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![(quarter, 3 * quarter - 1)], map.uncovered);
+    }
+}
@@ -2,7 +2,8 @@ use crate::{HcOpsError, HcOpsResult};
 use base64::Engine;
 use diesel::SqliteConnection;
 use diesel::connection::SimpleConnection;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 pub struct Key {
     key: sodoken::SizedLockedArray<{ sodoken::secretbox::XSALSA_KEYBYTES }>,
@@ -20,17 +21,10 @@ impl std::fmt::Debug for Key {
 
 impl Key {
     pub fn load(key_path: PathBuf, mut passphrase: sodoken::LockedArray) -> HcOpsResult<Self> {
-        let key = std::fs::read_to_string(key_path)?;
-        let key = base64::prelude::BASE64_URL_SAFE_NO_PAD
-            .decode(key)
-            .map_err(HcOpsError::other)?;
+        let key = decode_key_file(&key_path)?;
 
         let mut salt = [0; sodoken::argon2::ARGON2_ID_SALTBYTES];
-
-        let salt_index = sodoken::secretbox::XSALSA_NONCEBYTES
-            + sodoken::secretbox::XSALSA_MACBYTES
-            + sodoken::secretbox::XSALSA_KEYBYTES;
-        salt.copy_from_slice(&key[salt_index..salt_index + sodoken::argon2::ARGON2_ID_SALTBYTES]);
+        salt.copy_from_slice(&key[KEY_FILE_SALT_OFFSET..KEY_FILE_LEN]);
 
         let mut secret =
             sodoken::SizedLockedArray::<{ sodoken::secretbox::XSALSA_KEYBYTES }>::new()?;
@@ -38,8 +32,8 @@ impl Key {
             &mut *secret.lock(),
             &passphrase.lock(),
             &salt,
-            sodoken::argon2::ARGON2_ID_OPSLIMIT_MODERATE,
-            sodoken::argon2::ARGON2_ID_MEMLIMIT_MODERATE,
+            KEY_FILE_ARGON2_OPSLIMIT,
+            KEY_FILE_ARGON2_MEMLIMIT,
         )?;
 
         let mut nonce = [0; sodoken::secretbox::XSALSA_NONCEBYTES];
@@ -61,9 +55,141 @@ impl Key {
 
         Ok(Key { key, salt })
     }
+
+    /// Write the derived key and its salt, to hand it to another process over a private channel.
+    pub fn write_to(&mut self, mut write: impl std::io::Write) -> HcOpsResult<()> {
+        write.write_all(&*self.key.lock())?;
+        write.write_all(&self.salt)?;
+
+        Ok(())
+    }
+
+    /// Read a key written by [Key::write_to], directly into locked memory.
+    pub fn read_from(mut read: impl std::io::Read) -> HcOpsResult<Self> {
+        let mut key = sodoken::SizedLockedArray::<{ sodoken::secretbox::XSALSA_KEYBYTES }>::new()?;
+        read.read_exact(&mut *key.lock())?;
+
+        let mut salt = [0; sodoken::argon2::ARGON2_ID_SALTBYTES];
+        read.read_exact(&mut salt)?;
+
+        Ok(Key { key, salt })
+    }
+}
+
+/// Where the salt starts in a decoded `db.key` file, after the nonce and the encrypted key with its
+/// MAC.
+const KEY_FILE_SALT_OFFSET: usize = sodoken::secretbox::XSALSA_NONCEBYTES
+    + sodoken::secretbox::XSALSA_MACBYTES
+    + sodoken::secretbox::XSALSA_KEYBYTES;
+
+/// The length of a decoded `db.key` file: a nonce, the encrypted key with its MAC, then the salt.
+const KEY_FILE_LEN: usize = KEY_FILE_SALT_OFFSET + sodoken::argon2::ARGON2_ID_SALTBYTES;
+
+/// The Argon2id ops limit for deriving the key that encrypts the database key.
+///
+/// This isn't stored in the `db.key` file, Holochain always uses the same limits.
+pub const KEY_FILE_ARGON2_OPSLIMIT: u32 = sodoken::argon2::ARGON2_ID_OPSLIMIT_MODERATE;
+
+/// The Argon2id memory limit, in bytes, that goes with [KEY_FILE_ARGON2_OPSLIMIT].
+pub const KEY_FILE_ARGON2_MEMLIMIT: u32 = sodoken::argon2::ARGON2_ID_MEMLIMIT_MODERATE;
+
+/// The SQLCipher compatibility version set by [apply_key].
+pub const CIPHER_COMPATIBILITY: u32 = 4;
+
+/// The number of bytes at the start of each database that [apply_key] leaves unencrypted.
+pub const CIPHER_PLAINTEXT_HEADER_SIZE: u32 = 32;
+
+fn decode_key_file(key_path: &Path) -> HcOpsResult<Vec<u8>> {
+    let key = std::fs::read_to_string(key_path)?;
+    let key = base64::prelude::BASE64_URL_SAFE_NO_PAD
+        .decode(key.trim_end())
+        .map_err(HcOpsError::other)?;
+
+    if key.len() < KEY_FILE_LEN {
+        return Err(HcOpsError::Other(
+            format!(
+                "Database key file is {} bytes, expected at least {KEY_FILE_LEN}: {}",
+                key.len(),
+                key_path.display()
+            )
+            .into(),
+        ));
+    }
+
+    Ok(key)
+}
+
+/// The parameters of a `db.key` file that can be read without the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyFileInfo {
+    /// The salt for deriving the key that encrypts the database key, from the passphrase.
+    pub salt: Vec<u8>,
+    /// Always [KEY_FILE_ARGON2_OPSLIMIT], the limits aren't stored in the file.
+    pub argon2_opslimit: u32,
+    /// Always [KEY_FILE_ARGON2_MEMLIMIT].
+    pub argon2_memlimit: u32,
+    /// The nonce that the database key was encrypted with.
+    pub nonce: Vec<u8>,
+    pub key_len: usize,
+}
+
+/// Read the parameters of a `db.key` file, without decrypting it.
+pub fn read_key_file_info(key_path: &Path) -> HcOpsResult<KeyFileInfo> {
+    let key = decode_key_file(key_path)?;
+
+    Ok(KeyFileInfo {
+        salt: key[KEY_FILE_SALT_OFFSET..KEY_FILE_LEN].to_vec(),
+        argon2_opslimit: KEY_FILE_ARGON2_OPSLIMIT,
+        argon2_memlimit: KEY_FILE_ARGON2_MEMLIMIT,
+        nonce: key[..sodoken::secretbox::XSALSA_NONCEBYTES].to_vec(),
+        key_len: sodoken::secretbox::XSALSA_KEYBYTES,
+    })
+}
+
+/// Check that a database can be read.
+///
+/// A database opened with the wrong key can't be read, but that is only found out on first use.
+pub fn check_database_readable(conn: &mut SqliteConnection) -> HcOpsResult<()> {
+    conn.batch_execute("SELECT count(*) FROM sqlite_master;")?;
+
+    Ok(())
+}
+
+/// Write an unencrypted copy of a database, which must have been opened with its key.
+///
+/// The copy can be opened by any SQLite tool. The file at `out_path` must not exist yet.
+pub fn export_plaintext(conn: &mut SqliteConnection, out_path: &Path) -> HcOpsResult<()> {
+    if out_path.exists() {
+        return Err(HcOpsError::Other(
+            format!("File already exists: {}", out_path.display()).into(),
+        ));
+    }
+
+    let out_path = out_path
+        .to_str()
+        .ok_or_else(|| HcOpsError::Other("Invalid output path".into()))?
+        .replace('\'', "''");
+
+    conn.batch_execute(&format!(
+        "ATTACH DATABASE '{out_path}' AS plaintext KEY '';
+SELECT sqlcipher_export('plaintext');
+DETACH DATABASE plaintext;"
+    ))?;
+
+    Ok(())
 }
 
 pub fn apply_key(conn: &mut SqliteConnection, key: &mut Key) -> HcOpsResult<()> {
+    let mut stmt = key_statements(key)?;
+    let lock = stmt.lock();
+
+    conn.batch_execute(std::str::from_utf8(&lock[..lock.len() - 1]).map_err(HcOpsError::other)?)?;
+
+    Ok(())
+}
+
+/// The statements that set the key on a connection, in locked memory and NUL terminated.
+pub(crate) fn key_statements(key: &mut Key) -> HcOpsResult<sodoken::LockedArray> {
     static PRAGMA: &[u8] = br#"
 PRAGMA key = "x'----------------------------------------------------------------'";
 PRAGMA cipher_salt = "x'--------------------------------'";
@@ -71,11 +197,13 @@ PRAGMA cipher_compatibility = 4;
 PRAGMA cipher_plaintext_header_size = 32;
 "#;
 
-    let mut stmt = sodoken::LockedArray::new(PRAGMA.len())?;
-    stmt.lock().copy_from_slice(PRAGMA);
+    let mut stmt = sodoken::LockedArray::new(PRAGMA.len() + 1)?;
 
     {
         let mut lock = stmt.lock();
+        lock[..PRAGMA.len()].copy_from_slice(PRAGMA);
+        lock[PRAGMA.len()] = 0;
+
         for (i, b) in key.key.lock().iter().enumerate() {
             let c = format!("{b:02X}");
             let idx = 17 + (i * 2);
@@ -88,7 +216,5 @@ PRAGMA cipher_plaintext_header_size = 32;
         }
     }
 
-    conn.batch_execute(std::str::from_utf8(&stmt.lock()).map_err(HcOpsError::other)?)?;
-
-    Ok(())
+    Ok(stmt)
 }
@@ -0,0 +1,313 @@
+use crate::HcOpsResult;
+use crate::retrieve::{DbDhtOp, DbOpType, ValidationStage, ValidationStatus, schema};
+use diesel::SqliteConnection;
+use holo_hash::{
+    ActionHash, AnyDhtHash, AnyDhtHashPrimitive, AnyLinkableHash, AnyLinkableHashPrimitive,
+    DhtOpHash, EntryHash,
+};
+use holochain_types::prelude::SignedActionHashedExt;
+use holochain_zome_types::prelude::{Action, SignedAction, SignedActionHashed, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// The number of validation attempts after which an op is flagged as retried often.
+pub const DEFAULT_RETRY_THRESHOLD: u32 = 10;
+
+/// Why ops in the DHT database are pending, rejected or abandoned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationDiagnosis {
+    /// Op counts by validation stage and status, largest first.
+    pub groups: Vec<DiagnosisGroup>,
+    /// The ops themselves, most retried first.
+    pub ops: Vec<DiagnosedOp>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosisGroup {
+    pub stage: Option<ValidationStage>,
+    pub status: Option<ValidationStatus>,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosedOp {
+    pub hash: DhtOpHash,
+    pub typ: Option<DbOpType>,
+    pub action: SignedActionHashed,
+    pub stage: Option<ValidationStage>,
+    pub status: Option<ValidationStatus>,
+    pub sys_validated: bool,
+    pub app_validated: bool,
+    pub num_validation_attempts: u32,
+    pub last_validation_attempt: Option<Timestamp>,
+    /// Dependencies that are held in neither the DHT nor the cache database.
+    pub missing: Vec<MissingDependency>,
+    /// Whether validation has been attempted at least as many times as the retry threshold.
+    pub retried_often: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingDependency {
+    pub kind: DependencyKind,
+    pub hash: AnyDhtHash,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DependencyKind {
+    PrevAction,
+    Entry,
+    UpdatedAction,
+    DeletedAction,
+    CreateLink,
+    LinkBase,
+}
+
+impl Display for DependencyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyKind::PrevAction => write!(f, "previous action"),
+            DependencyKind::Entry => write!(f, "entry"),
+            DependencyKind::UpdatedAction => write!(f, "updated action"),
+            DependencyKind::DeletedAction => write!(f, "deleted action"),
+            DependencyKind::CreateLink => write!(f, "create link"),
+            DependencyKind::LinkBase => write!(f, "link base"),
+        }
+    }
+}
+
+/// Diagnose ops in the DHT database that are not integrated, or that failed validation.
+///
+/// Dependencies are looked up in both the DHT and the cache database, since either can satisfy
+/// validation.
+pub fn diagnose_validation(
+    dht: &mut SqliteConnection,
+    cache: &mut SqliteConnection,
+    retry_threshold: u32,
+) -> HcOpsResult<ValidationDiagnosis> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+    use schema::DhtOp::dsl as dht_op_fields;
+
+    let loaded = schema::DhtOp::table
+        .inner_join(schema::Action::table)
+        .filter(dht_op_fields::when_integrated.is_null().or(
+            dht_op_fields::validation_status.eq_any(vec![
+                ValidationStatus::Rejected,
+                ValidationStatus::Abandoned,
+            ]),
+        ))
+        .select((DbDhtOp::as_select(), action_fields::blob))
+        .load::<(DbDhtOp, Vec<u8>)>(dht)?;
+
+    let mut held = HashMap::<AnyDhtHash, bool>::new();
+    let mut groups = HashMap::<(Option<ValidationStage>, Option<ValidationStatus>), usize>::new();
+    let mut ops = Vec::with_capacity(loaded.len());
+
+    for (op, action_blob) in loaded {
+        let action: SignedAction = holochain_serialized_bytes::decode(&action_blob)?;
+        let action = SignedActionHashed::from_content_sync(action);
+
+        let mut missing = Vec::new();
+        for (kind, hash) in dependencies(op.typ, action.action()) {
+            let found = match held.get(&hash) {
+                Some(found) => *found,
+                None => {
+                    let found = is_held(dht, &hash)? || is_held(cache, &hash)?;
+                    held.insert(hash.clone(), found);
+                    found
+                }
+            };
+
+            if !found {
+                missing.push(MissingDependency { kind, hash });
+            }
+        }
+
+        *groups
+            .entry((op.validation_stage, op.validation_status))
+            .or_default() += 1;
+
+        let num_validation_attempts = op.num_validation_attempts.unwrap_or_default() as u32;
+        ops.push(DiagnosedOp {
+            hash: DhtOpHash::try_from_raw_39(op.hash)?,
+            typ: op.typ,
+            action,
+            stage: op.validation_stage,
+            status: op.validation_status,
+            sys_validated: op.when_sys_validated.is_some(),
+            app_validated: op.when_app_validated.is_some(),
+            num_validation_attempts,
+            last_validation_attempt: op.last_validation_attempt.map(Timestamp),
+            missing,
+            retried_often: num_validation_attempts >= retry_threshold,
+        });
+    }
+
+    let mut groups = groups
+        .into_iter()
+        .map(|((stage, status), count)| DiagnosisGroup {
+            stage,
+            status,
+            count,
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| b.count.cmp(&a.count));
+
+    ops.sort_by(|a, b| b.num_validation_attempts.cmp(&a.num_validation_attempts));
+
+    Ok(ValidationDiagnosis { groups, ops })
+}
+
+/// The hashes that must be held before an op of this type, for this action, can be validated.
+fn dependencies(typ: Option<DbOpType>, action: &Action) -> Vec<(DependencyKind, AnyDhtHash)> {
+    let mut deps = Vec::new();
+
+    if matches!(
+        typ,
+        Some(DbOpType::StoreRecord) | Some(DbOpType::RegisterAgentActivity)
+    ) && let Some(prev_action) = action.prev_action()
+    {
+        deps.push((DependencyKind::PrevAction, prev_action.clone().into()));
+    }
+
+    if matches!(
+        typ,
+        Some(DbOpType::StoreRecord)
+            | Some(DbOpType::StoreEntry)
+            | Some(DbOpType::RegisterUpdatedContent)
+    ) && let (Some(entry_hash), Some(entry_type)) = (action.entry_hash(), action.entry_type())
+        && entry_type.visibility().is_public()
+    {
+        deps.push((DependencyKind::Entry, entry_hash.clone().into()));
+    }
+
+    match action {
+        Action::Update(update) => {
+            deps.push((
+                DependencyKind::UpdatedAction,
+                update.original_action_address.clone().into(),
+            ));
+        }
+        Action::Delete(delete) => {
+            deps.push((
+                DependencyKind::DeletedAction,
+                delete.deletes_address.clone().into(),
+            ));
+        }
+        Action::CreateLink(create_link) => {
+            deps.extend(
+                linkable_to_dht_hash(&create_link.base_address)
+                    .map(|h| (DependencyKind::LinkBase, h)),
+            );
+        }
+        Action::DeleteLink(delete_link) => {
+            deps.push((
+                DependencyKind::CreateLink,
+                delete_link.link_add_address.clone().into(),
+            ));
+            deps.extend(
+                linkable_to_dht_hash(&delete_link.base_address)
+                    .map(|h| (DependencyKind::LinkBase, h)),
+            );
+        }
+        _ => {}
+    }
+
+    deps
+}
+
+/// Links to external hashes have no dependency to look up.
+fn linkable_to_dht_hash(hash: &AnyLinkableHash) -> Option<AnyDhtHash> {
+    match hash.clone().into_primitive() {
+        AnyLinkableHashPrimitive::Action(action_hash) => Some(action_hash.into()),
+        AnyLinkableHashPrimitive::Entry(entry_hash) => Some(entry_hash.into()),
+        AnyLinkableHashPrimitive::External(_) => None,
+    }
+}
+
+fn is_held(conn: &mut SqliteConnection, hash: &AnyDhtHash) -> HcOpsResult<bool> {
+    match hash.clone().into_primitive() {
+        AnyDhtHashPrimitive::Action(action_hash) => is_action_held(conn, &action_hash),
+        AnyDhtHashPrimitive::Entry(entry_hash) => is_entry_held(conn, &entry_hash),
+    }
+}
+
+fn is_action_held(conn: &mut SqliteConnection, action_hash: &ActionHash) -> HcOpsResult<bool> {
+    use diesel::dsl::{exists, select};
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+
+    Ok(select(exists(
+        schema::Action::table.filter(action_fields::hash.eq(action_hash.get_raw_39())),
+    ))
+    .get_result(conn)?)
+}
+
+fn is_entry_held(conn: &mut SqliteConnection, entry_hash: &EntryHash) -> HcOpsResult<bool> {
+    use diesel::dsl::{exists, select};
+    use diesel::prelude::*;
+    use schema::Entry::dsl as entry_fields;
+
+    Ok(select(exists(
+        schema::Entry::table.filter(entry_fields::hash.eq(entry_hash.get_raw_39())),
+    ))
+    .get_result(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use holochain_zome_types::prelude::*;
+
+    #[test]
+    fn delete_link_depends_on_create_link_and_base() {
+        let base = EntryHash::from_raw_36(vec![1; 36]);
+        let create_link = ActionHash::from_raw_36(vec![2; 36]);
+        let prev_action = ActionHash::from_raw_36(vec![3; 36]);
+
+        let action = Action::DeleteLink(DeleteLink {
+            author: AgentPubKey::from_raw_36(vec![4; 36]),
+            timestamp: Timestamp::now(),
+            action_seq: 5,
+            prev_action: prev_action.clone(),
+            link_add_address: create_link.clone(),
+            base_address: base.clone().into(),
+        });
+
+        assert_eq!(
+            vec![
+                (DependencyKind::CreateLink, create_link.clone().into()),
+                (DependencyKind::LinkBase, base.clone().into()),
+            ],
+            dependencies(Some(DbOpType::RegisterRemoveLink), &action)
+        );
+
+        assert_eq!(
+            vec![
+                (DependencyKind::PrevAction, prev_action.into()),
+                (DependencyKind::CreateLink, create_link.into()),
+                (DependencyKind::LinkBase, base.into()),
+            ],
+            dependencies(Some(DbOpType::RegisterAgentActivity), &action)
+        );
+    }
+
+    #[test]
+    fn external_link_base_is_not_a_dependency() {
+        let action = Action::CreateLink(CreateLink {
+            author: AgentPubKey::from_raw_36(vec![1; 36]),
+            timestamp: Timestamp::now(),
+            action_seq: 3,
+            prev_action: ActionHash::from_raw_36(vec![2; 36]),
+            base_address: ExternalHash::from_raw_36(vec![3; 36]).into(),
+            target_address: EntryHash::from_raw_36(vec![4; 36]).into(),
+            zome_index: 0.into(),
+            link_type: 0.into(),
+            tag: LinkTag::new(vec![]),
+            weight: Default::default(),
+        });
+
+        assert!(dependencies(Some(DbOpType::RegisterAddLink), &action).is_empty());
+    }
+}
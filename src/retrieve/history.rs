@@ -0,0 +1,444 @@
+use crate::retrieve::{DbAction, DbOpType, ValidationStatus, get_ops_by_action_hash, schema};
+use crate::{HcOpsError, HcOpsResult};
+use diesel::SqliteConnection;
+use holo_hash::{ActionHash, AnyDhtHash, AnyDhtHashPrimitive, EntryHash};
+use holochain_types::prelude::SignedActionHashedExt;
+use holochain_zome_types::prelude::{Action, SignedActionHashed, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// The lifecycle of a record, from its original `Create` through every `Update` and `Delete`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryHistory {
+    /// The original `Create` actions. There is more than one when looking up an entry hash
+    /// that was created several times.
+    pub roots: Vec<HistoryNode>,
+    /// The version that a `get` following the newest valid update would most likely resolve to.
+    pub likely_resolves_to: Option<ActionHash>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryNode {
+    pub action: SignedActionHashed,
+    pub ops: Vec<HistoryOp>,
+    pub updates: Vec<HistoryNode>,
+    pub deletes: Vec<HistoryDelete>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryDelete {
+    pub action: SignedActionHashed,
+    pub ops: Vec<HistoryOp>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryOp {
+    pub typ: Option<DbOpType>,
+    pub validation_status: Option<ValidationStatus>,
+    pub when_integrated: Option<Timestamp>,
+}
+
+impl HistoryNode {
+    /// Whether this version has been validated, based on the ops held for it.
+    ///
+    /// Versions with no ops held, or where any op was rejected or abandoned, are not valid.
+    pub fn is_valid(&self) -> bool {
+        is_valid(&self.ops)
+    }
+
+    /// Whether this version has been deleted by a valid `Delete`.
+    pub fn is_deleted(&self) -> bool {
+        self.deletes.iter().any(|d| is_valid(&d.ops))
+    }
+}
+
+fn is_valid(ops: &[HistoryOp]) -> bool {
+    !ops.is_empty()
+        && ops
+            .iter()
+            .all(|op| matches!(op.validation_status, Some(ValidationStatus::Valid)))
+}
+
+/// Get the full history of a record, by action hash or entry hash.
+///
+/// For an action hash, the history starts from the `Create` that the action is, or that it
+/// updates. For an entry hash, the history starts from every `Create` of that entry.
+pub fn get_entry_history(
+    conn: &mut SqliteConnection,
+    hash: &AnyDhtHash,
+) -> HcOpsResult<EntryHistory> {
+    let roots = match hash.clone().into_primitive() {
+        AnyDhtHashPrimitive::Action(action_hash) => {
+            let action = get_action(conn, &action_hash)?.ok_or_else(|| {
+                HcOpsError::Other(format!("Action not found: {action_hash}").into())
+            })?;
+
+            vec![find_root(conn, action)?]
+        }
+        AnyDhtHashPrimitive::Entry(entry_hash) => {
+            let mut roots = Vec::new();
+            for action in get_actions_for_entry(conn, &entry_hash)? {
+                let root = find_root(conn, action)?;
+                if !roots
+                    .iter()
+                    .any(|r: &SignedActionHashed| r.as_hash() == root.as_hash())
+                {
+                    roots.push(root);
+                }
+            }
+
+            roots
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let roots = roots
+        .into_iter()
+        .map(|root| build_node(conn, root, &mut seen))
+        .collect::<HcOpsResult<Vec<_>>>()?;
+
+    let likely_resolves_to = roots
+        .iter()
+        .filter(|r| r.is_valid() && !r.is_deleted())
+        .map(find_latest)
+        .max_by_key(|n| n.action.action().timestamp())
+        .map(|n| n.action.as_hash().clone());
+
+    Ok(EntryHistory {
+        roots,
+        likely_resolves_to,
+    })
+}
+
+/// Follow the newest valid, non-deleted update at each level of the tree.
+fn find_latest(node: &HistoryNode) -> &HistoryNode {
+    match node
+        .updates
+        .iter()
+        .filter(|u| u.is_valid() && !u.is_deleted())
+        .max_by_key(|u| u.action.action().timestamp())
+    {
+        Some(update) => find_latest(update),
+        None => node,
+    }
+}
+
+/// Walk back through `Update` actions to the original `Create`.
+fn find_root(
+    conn: &mut SqliteConnection,
+    mut action: SignedActionHashed,
+) -> HcOpsResult<SignedActionHashed> {
+    let mut seen = HashSet::new();
+
+    while let Action::Update(update) = action.action() {
+        if !seen.insert(action.as_hash().clone()) {
+            return Err(HcOpsError::Other(
+                format!("Update cycle found at: {}", action.as_hash()).into(),
+            ));
+        }
+
+        match get_action(conn, &update.original_action_address)? {
+            Some(original) => action = original,
+            // The original isn't held, so this is as far back as we can see.
+            None => break,
+        }
+    }
+
+    Ok(action)
+}
+
+fn build_node(
+    conn: &mut SqliteConnection,
+    action: SignedActionHashed,
+    seen: &mut HashSet<ActionHash>,
+) -> HcOpsResult<HistoryNode> {
+    seen.insert(action.as_hash().clone());
+
+    let ops = get_history_ops(conn, action.as_hash())?;
+
+    let mut updates = Vec::new();
+    for update in get_updates(conn, action.as_hash())? {
+        if seen.contains(update.as_hash()) {
+            continue;
+        }
+
+        updates.push(build_node(conn, update, seen)?);
+    }
+    updates.sort_by_key(|u| u.action.action().timestamp());
+
+    let mut deletes = Vec::new();
+    for delete in get_deletes(conn, action.as_hash())? {
+        let ops = get_history_ops(conn, delete.as_hash())?;
+        deletes.push(HistoryDelete {
+            action: delete,
+            ops,
+        });
+    }
+
+    Ok(HistoryNode {
+        action,
+        ops,
+        updates,
+        deletes,
+    })
+}
+
+fn get_history_ops(
+    conn: &mut SqliteConnection,
+    action_hash: &ActionHash,
+) -> HcOpsResult<Vec<HistoryOp>> {
+    Ok(get_ops_by_action_hash(conn, action_hash)?
+        .into_iter()
+        .map(|op| HistoryOp {
+            typ: op.typ,
+            validation_status: op.validation_status,
+            when_integrated: op.when_integrated.map(Timestamp),
+        })
+        .collect())
+}
+
+pub(crate) fn get_action(
+    conn: &mut SqliteConnection,
+    action_hash: &ActionHash,
+) -> HcOpsResult<Option<SignedActionHashed>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+
+    schema::Action::table
+        .filter(action_fields::hash.eq(action_hash.get_raw_39()))
+        .select(DbAction::as_select())
+        .first::<DbAction>(conn)
+        .optional()?
+        .map(|a| -> HcOpsResult<SignedActionHashed> {
+            Ok(SignedActionHashed::from_content_sync(a.try_into()?))
+        })
+        .transpose()
+}
+
+fn get_actions_for_entry(
+    conn: &mut SqliteConnection,
+    entry_hash: &EntryHash,
+) -> HcOpsResult<Vec<SignedActionHashed>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+
+    schema::Action::table
+        .filter(action_fields::entry_hash.eq(entry_hash.get_raw_39()))
+        .filter(action_fields::typ.eq_any(vec!["Create", "Update"]))
+        .select(DbAction::as_select())
+        .load::<DbAction>(conn)?
+        .into_iter()
+        .map(|a| -> HcOpsResult<SignedActionHashed> {
+            Ok(SignedActionHashed::from_content_sync(a.try_into()?))
+        })
+        .collect()
+}
+
+fn get_updates(
+    conn: &mut SqliteConnection,
+    action_hash: &ActionHash,
+) -> HcOpsResult<Vec<SignedActionHashed>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+
+    schema::Action::table
+        .filter(action_fields::original_action_hash.eq(action_hash.get_raw_39()))
+        .filter(action_fields::typ.eq("Update"))
+        .select(DbAction::as_select())
+        .load::<DbAction>(conn)?
+        .into_iter()
+        .map(|a| -> HcOpsResult<SignedActionHashed> {
+            Ok(SignedActionHashed::from_content_sync(a.try_into()?))
+        })
+        .collect()
+}
+
+fn get_deletes(
+    conn: &mut SqliteConnection,
+    action_hash: &ActionHash,
+) -> HcOpsResult<Vec<SignedActionHashed>> {
+    use diesel::prelude::*;
+    use schema::Action::dsl as action_fields;
+
+    schema::Action::table
+        .filter(action_fields::deletes_action_hash.eq(action_hash.get_raw_39()))
+        .filter(action_fields::typ.eq("Delete"))
+        .select(DbAction::as_select())
+        .load::<DbAction>(conn)?
+        .into_iter()
+        .map(|a| -> HcOpsResult<SignedActionHashed> {
+            Ok(SignedActionHashed::from_content_sync(a.try_into()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieve::test_db::{self, TestOp};
+    use holo_hash::AgentPubKey;
+    use holochain_zome_types::prelude::{
+        Create, Delete, EntryType, SIGNATURE_BYTES, Signature, SignedAction, Update,
+    };
+
+    fn author() -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![100; 36])
+    }
+
+    fn entry_hash(i: u8) -> EntryHash {
+        EntryHash::from_raw_36(vec![i; 36])
+    }
+
+    /// Insert an action with one op, as the op with hashes made from `op`.
+    fn commit(
+        conn: &mut SqliteConnection,
+        action: Action,
+        op: u8,
+        validation_status: Option<ValidationStatus>,
+    ) -> ActionHash {
+        let action = SignedActionHashed::from_content_sync(SignedAction::new(
+            action,
+            Signature([0; SIGNATURE_BYTES]),
+        ));
+        test_db::insert_action(conn, &action);
+
+        let mut test_op = TestOp::new(op);
+        test_op.action_hash = action.as_hash().clone();
+        test_op.validation_status = validation_status;
+        test_op.insert(conn);
+
+        action.as_hash().clone()
+    }
+
+    fn create(timestamp: i64) -> Action {
+        Action::Create(Create {
+            author: author(),
+            timestamp: Timestamp(timestamp),
+            action_seq: timestamp as u32,
+            prev_action: ActionHash::from_raw_36(vec![0; 36]),
+            entry_type: EntryType::AgentPubKey,
+            entry_hash: entry_hash(1),
+            weight: Default::default(),
+        })
+    }
+
+    fn update(original: &ActionHash, timestamp: i64) -> Action {
+        Action::Update(Update {
+            author: author(),
+            timestamp: Timestamp(timestamp),
+            action_seq: timestamp as u32,
+            prev_action: ActionHash::from_raw_36(vec![0; 36]),
+            original_action_address: original.clone(),
+            original_entry_address: entry_hash(1),
+            entry_type: EntryType::AgentPubKey,
+            entry_hash: entry_hash(timestamp as u8),
+            weight: Default::default(),
+        })
+    }
+
+    fn delete(deletes: &ActionHash, timestamp: i64) -> Action {
+        Action::Delete(Delete {
+            author: author(),
+            timestamp: Timestamp(timestamp),
+            action_seq: timestamp as u32,
+            prev_action: ActionHash::from_raw_36(vec![0; 36]),
+            deletes_address: deletes.clone(),
+            deletes_entry_address: entry_hash(1),
+            weight: Default::default(),
+        })
+    }
+
+    fn hash_of(node: &HistoryNode) -> &ActionHash {
+        node.action.as_hash()
+    }
+
+    #[test]
+    fn history_follows_the_newest_valid_undeleted_update() {
+        let mut conn = test_db::open();
+        let valid = Some(ValidationStatus::Valid);
+
+        let root = commit(&mut conn, create(1), 1, valid);
+        let first = commit(&mut conn, update(&root, 2), 2, valid);
+        // The newest update of the root, but it was rejected.
+        let rejected = commit(
+            &mut conn,
+            update(&root, 6),
+            3,
+            Some(ValidationStatus::Rejected),
+        );
+        let kept = commit(&mut conn, update(&first, 4), 4, valid);
+        // Newer than `kept`, but deleted.
+        let deleted = commit(&mut conn, update(&first, 5), 5, valid);
+        commit(&mut conn, delete(&deleted, 7), 6, valid);
+        // A rejected delete doesn't delete anything.
+        commit(
+            &mut conn,
+            delete(&kept, 8),
+            7,
+            Some(ValidationStatus::Rejected),
+        );
+
+        let history = get_entry_history(&mut conn, &root.clone().into()).unwrap();
+
+        assert_eq!(1, history.roots.len());
+        let root_node = &history.roots[0];
+        assert_eq!(&root, hash_of(root_node));
+        assert_eq!(
+            vec![&first, &rejected],
+            root_node.updates.iter().map(hash_of).collect::<Vec<_>>()
+        );
+        assert!(!root_node.updates[1].is_valid());
+
+        let first_node = &root_node.updates[0];
+        assert_eq!(
+            vec![&kept, &deleted],
+            first_node.updates.iter().map(hash_of).collect::<Vec<_>>()
+        );
+        assert!(!first_node.updates[0].is_deleted());
+        assert_eq!(1, first_node.updates[0].deletes.len());
+        assert!(first_node.updates[1].is_deleted());
+
+        assert_eq!(Some(kept.clone()), history.likely_resolves_to);
+        assert_eq!(&kept, hash_of(find_latest(root_node)));
+
+        // Starting from an update finds the same history.
+        let from_update = get_entry_history(&mut conn, &kept.into()).unwrap();
+        assert_eq!(1, from_update.roots.len());
+        assert_eq!(&root, hash_of(&from_update.roots[0]));
+        assert_eq!(history.likely_resolves_to, from_update.likely_resolves_to);
+    }
+
+    #[test]
+    fn entry_hash_finds_every_create_of_the_entry() {
+        let mut conn = test_db::open();
+        let valid = Some(ValidationStatus::Valid);
+
+        let first = commit(&mut conn, create(1), 1, valid);
+        let second = commit(&mut conn, create(2), 2, valid);
+        commit(&mut conn, update(&second, 3), 3, None);
+
+        let history = get_entry_history(&mut conn, &entry_hash(1).into()).unwrap();
+
+        assert_eq!(
+            HashSet::from([&first, &second]),
+            history.roots.iter().map(hash_of).collect::<HashSet<_>>()
+        );
+        // The update hasn't been validated yet, so the newest create is used.
+        assert_eq!(Some(second), history.likely_resolves_to);
+    }
+
+    #[test]
+    fn deleted_record_resolves_to_nothing() {
+        let mut conn = test_db::open();
+        let valid = Some(ValidationStatus::Valid);
+
+        let root = commit(&mut conn, create(1), 1, valid);
+        commit(&mut conn, update(&root, 2), 2, valid);
+        commit(&mut conn, delete(&root, 3), 3, valid);
+
+        let history = get_entry_history(&mut conn, &root.into()).unwrap();
+
+        assert!(history.roots[0].is_deleted());
+        assert_eq!(None, history.likely_resolves_to);
+    }
+}
@@ -11,6 +11,7 @@ use holochain_zome_types::prelude::{
 };
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::str::FromStr;
 
 pub enum DhtOp {
     ChainOp(DbDhtOp),
@@ -29,7 +30,7 @@ impl From<DbWarrant> for DhtOp {
     }
 }
 
-#[derive(Debug, Queryable, Selectable)]
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::retrieve::schema::DhtOp)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct DbDhtOp {
@@ -39,7 +40,7 @@ pub struct DbDhtOp {
     pub action_hash: Option<Vec<u8>>,
     // DHT only
     pub require_receipt: Option<bool>,
-    pub storage_center_loc: Option<i32>,
+    pub storage_center_loc: Option<i64>,
     pub authored_timestamp: Option<i64>,
     pub op_order: String,
     pub validation_status: Option<ValidationStatus>,
@@ -72,7 +73,7 @@ pub struct DbDhtOp {
     pub transfer_time: Option<i64>,
 }
 
-#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, AsExpression, FromSqlRow, Serialize, Deserialize)]
 #[diesel(sql_type = Text)]
 pub enum DbOpType {
     StoreRecord,
@@ -87,13 +88,28 @@ pub enum DbOpType {
     ChainIntegrityWarrant,
 }
 
-impl<DB: Backend> FromSql<Text, DB> for DbOpType
-where
-    String: FromSql<Text, DB>,
-{
-    fn from_sql(bytes: DB::RawValue<'_>) -> diesel::deserialize::Result<Self> {
-        let v = String::from_sql(bytes)?;
-        Ok(match v.as_str() {
+impl DbOpType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DbOpType::StoreRecord => "StoreRecord",
+            DbOpType::StoreEntry => "StoreEntry",
+            DbOpType::RegisterAgentActivity => "RegisterAgentActivity",
+            DbOpType::RegisterUpdatedContent => "RegisterUpdatedContent",
+            DbOpType::RegisterUpdatedRecord => "RegisterUpdatedRecord",
+            DbOpType::RegisterDeletedBy => "RegisterDeletedBy",
+            DbOpType::RegisterDeletedEntryAction => "RegisterDeletedEntryAction",
+            DbOpType::RegisterAddLink => "RegisterAddLink",
+            DbOpType::RegisterRemoveLink => "RegisterRemoveLink",
+            DbOpType::ChainIntegrityWarrant => "ChainIntegrityWarrant",
+        }
+    }
+}
+
+impl FromStr for DbOpType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
             "StoreRecord" => DbOpType::StoreRecord,
             "StoreEntry" => DbOpType::StoreEntry,
             "RegisterAgentActivity" => DbOpType::RegisterAgentActivity,
@@ -104,12 +120,33 @@ where
             "RegisterAddLink" => DbOpType::RegisterAddLink,
             "RegisterRemoveLink" => DbOpType::RegisterRemoveLink,
             "ChainIntegrityWarrant" => DbOpType::ChainIntegrityWarrant,
-            typ => return Err(format!("Unknown DhtOpType: {typ}").into()),
+            typ => return Err(format!("Unknown DhtOpType: {typ}")),
         })
     }
 }
 
-#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, Serialize, Deserialize)]
+impl<DB: Backend> FromSql<Text, DB> for DbOpType
+where
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let v = String::from_sql(bytes)?;
+        Ok(v.parse()?)
+    }
+}
+
+impl<DB: Backend> ToSql<Text, DB> for DbOpType
+where
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> diesel::serialize::Result {
+        self.as_str().to_sql(out)
+    }
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize,
+)]
 #[diesel(sql_type = SmallInt)]
 pub enum ValidationStage {
     /// Is awaiting to be system validated
@@ -141,7 +178,9 @@ where
     }
 }
 
-#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Serialize, Deserialize,
+)]
 #[diesel(sql_type = SmallInt)]
 pub enum ValidationStatus {
     /// All dependencies were found and validation passed
@@ -168,6 +207,19 @@ where
     }
 }
 
+impl FromStr for ValidationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "Valid" => ValidationStatus::Valid,
+            "Rejected" => ValidationStatus::Rejected,
+            "Abandoned" => ValidationStatus::Abandoned,
+            status => return Err(format!("Unknown ValidationStatus: {status}")),
+        })
+    }
+}
+
 impl<DB: Backend> ToSql<SmallInt, DB> for ValidationStatus
 where
     i16: ToSql<SmallInt, DB>,
@@ -184,6 +236,7 @@ where
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DhtMeta {
     pub require_receipt: bool,
+    pub when_integrated: Option<Timestamp>,
     pub validation_stage: Option<ValidationStage>,
     pub num_validation_attempts: Option<u32>,
     pub last_validation_attempt: Option<Timestamp>,
@@ -252,6 +305,7 @@ impl TryFrom<DbDhtOp> for ChainOp<DhtMeta> {
         let dht_meta = DhtMeta {
             // TODO It's a boolean, why is it nullable?
             require_receipt: value.require_receipt.unwrap_or_default(),
+            when_integrated: value.when_integrated.map(Timestamp),
             validation_stage: value.validation_stage,
             num_validation_attempts: value.num_validation_attempts.map(|v| v as u32),
             last_validation_attempt: value.last_validation_attempt.map(Timestamp),
@@ -323,7 +377,7 @@ impl TryFrom<DbDhtOp> for ChainOp<AuthoredMeta> {
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 #[allow(dead_code)]
 pub struct DbEntry {
-    hash: Vec<u8>,
+    pub(crate) hash: Vec<u8>,
     blob: Vec<u8>,
     tag: Option<String>,
     grantor: Option<Vec<u8>>,
@@ -347,11 +401,11 @@ impl TryFrom<DbEntry> for Entry {
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 #[allow(dead_code)]
 pub struct DbAction {
-    hash: Vec<u8>,
+    pub(crate) hash: Vec<u8>,
     typ: String,
     seq: i32,
     author: Vec<u8>,
-    blob: Vec<u8>,
+    pub(crate) blob: Vec<u8>,
     prev_hash: Option<Vec<u8>>,
     entry_hash: Option<Vec<u8>>,
     entry_type: Option<String>,
@@ -397,7 +451,7 @@ impl TryFrom<DbWarrant> for SignedWarrant {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Queryable, Selectable)]
+#[derive(Debug, Eq, PartialEq, Queryable, Selectable, Serialize, Deserialize)]
 #[diesel(table_name = crate::retrieve::schema::SliceHash)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct SliceHash {
@@ -0,0 +1,478 @@
+use crate::HcOpsResult;
+use crate::retrieve::{DbAction, DbDhtOp, DbEntry, DbOpType, ValidationStatus, schema};
+use diesel::SqliteConnection;
+use holo_hash::AgentPubKey;
+use holochain_zome_types::prelude::{SignedAction, Timestamp};
+
+/// The number of rows loaded per query when scanning a table.
+pub const DEFAULT_PAGE_SIZE: i64 = 1000;
+
+/// Filters for scanning ops, actions and entries.
+///
+/// Not every filter applies to every table:
+/// - `op_type` and `validation_status` only apply to ops.
+/// - `author` applies to ops, actions and entries, by the author of the action.
+/// - `since` and `until` apply to ops and actions, by their authored timestamp.
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    pub op_type: Option<DbOpType>,
+    pub author: Option<AgentPubKey>,
+    /// Inclusive lower bound.
+    pub since: Option<Timestamp>,
+    /// Exclusive upper bound.
+    pub until: Option<Timestamp>,
+    pub validation_status: Option<ValidationStatus>,
+}
+
+impl ScanFilter {
+    fn timestamp_in_range(&self, timestamp: Timestamp) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp < until)
+    }
+}
+
+type LoadPage<'a, T> = dyn FnMut(&mut SqliteConnection, Option<&[u8]>) -> HcOpsResult<Page<T>> + 'a;
+
+struct Page<T> {
+    items: Vec<T>,
+    /// The primary key to continue from, or `None` if this was the last page.
+    next: Option<Vec<u8>>,
+}
+
+/// An iterator over the rows of a table, loaded a page at a time.
+///
+/// Pages are keyed on the primary key of the table, so rows inserted while scanning may or may
+/// not be seen, but no row is seen twice. Iteration stops after the first error.
+pub struct Pages<'a, T> {
+    conn: &'a mut SqliteConnection,
+    load_page: Box<LoadPage<'a, T>>,
+    cursor: Option<Vec<u8>>,
+    buffer: std::vec::IntoIter<T>,
+    done: bool,
+}
+
+impl<'a, T> Pages<'a, T> {
+    fn new(conn: &'a mut SqliteConnection, load_page: Box<LoadPage<'a, T>>) -> Self {
+        Self {
+            conn,
+            load_page,
+            cursor: None,
+            buffer: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl<T> Iterator for Pages<'_, T> {
+    type Item = HcOpsResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.next() {
+                return Some(Ok(item));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            match (self.load_page)(&mut *self.conn, self.cursor.as_deref()) {
+                Ok(page) => {
+                    self.done = page.next.is_none();
+                    self.cursor = page.next;
+                    self.buffer = page.items.into_iter();
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Scan the `DhtOp` table.
+pub fn iter_dht_ops(
+    conn: &mut SqliteConnection,
+    filter: ScanFilter,
+    page_size: i64,
+) -> Pages<'_, DbDhtOp> {
+    Pages::new(
+        conn,
+        Box::new(move |conn: &mut SqliteConnection, cursor: Option<&[u8]>| {
+            use diesel::prelude::*;
+            use schema::Action::dsl as action_fields;
+            use schema::DhtOp::dsl as dht_op_fields;
+
+            let mut query = schema::DhtOp::table
+                .select(DbDhtOp::as_select())
+                .order(dht_op_fields::hash.asc())
+                .limit(page_size)
+                .into_boxed();
+
+            if let Some(cursor) = cursor {
+                query = query.filter(dht_op_fields::hash.gt(cursor.to_vec()));
+            }
+
+            if let Some(op_type) = filter.op_type {
+                query = query.filter(dht_op_fields::typ.eq(op_type));
+            }
+
+            if let Some(validation_status) = filter.validation_status {
+                query = query.filter(dht_op_fields::validation_status.eq(validation_status));
+            }
+
+            if let Some(since) = filter.since {
+                query = query.filter(dht_op_fields::authored_timestamp.ge(since.as_micros()));
+            }
+
+            if let Some(until) = filter.until {
+                query = query.filter(dht_op_fields::authored_timestamp.lt(until.as_micros()));
+            }
+
+            if let Some(author) = &filter.author {
+                query = query.filter(
+                    dht_op_fields::action_hash.eq_any(
+                        schema::Action::table
+                            .filter(action_fields::author.eq(author.get_raw_39().to_vec()))
+                            .select(action_fields::hash.nullable()),
+                    ),
+                );
+            }
+
+            let items = query.load::<DbDhtOp>(conn)?;
+            let next = next_cursor(&items, page_size, |op| &op.hash);
+
+            Ok(Page { items, next })
+        }),
+    )
+}
+
+/// Scan the `Action` table.
+///
+/// The table has no timestamp column, so the time range is checked after decoding each page.
+pub fn iter_actions(
+    conn: &mut SqliteConnection,
+    filter: ScanFilter,
+    page_size: i64,
+) -> Pages<'_, DbAction> {
+    Pages::new(
+        conn,
+        Box::new(move |conn: &mut SqliteConnection, cursor: Option<&[u8]>| {
+            use diesel::prelude::*;
+            use schema::Action::dsl as action_fields;
+
+            let mut query = schema::Action::table
+                .select(DbAction::as_select())
+                .order(action_fields::hash.asc())
+                .limit(page_size)
+                .into_boxed();
+
+            if let Some(cursor) = cursor {
+                query = query.filter(action_fields::hash.gt(cursor.to_vec()));
+            }
+
+            if let Some(author) = &filter.author {
+                query = query.filter(action_fields::author.eq(author.get_raw_39().to_vec()));
+            }
+
+            let loaded = query.load::<DbAction>(conn)?;
+            let next = next_cursor(&loaded, page_size, |action| &action.hash);
+
+            let items = if filter.since.is_some() || filter.until.is_some() {
+                let mut items = Vec::with_capacity(loaded.len());
+                for action in loaded {
+                    let signed: SignedAction = holochain_serialized_bytes::decode(&action.blob)?;
+                    if filter.timestamp_in_range(signed.action().timestamp()) {
+                        items.push(action);
+                    }
+                }
+                items
+            } else {
+                loaded
+            };
+
+            Ok(Page { items, next })
+        }),
+    )
+}
+
+/// Scan the `Entry` table.
+pub fn iter_entries(
+    conn: &mut SqliteConnection,
+    filter: ScanFilter,
+    page_size: i64,
+) -> Pages<'_, DbEntry> {
+    Pages::new(
+        conn,
+        Box::new(move |conn: &mut SqliteConnection, cursor: Option<&[u8]>| {
+            use diesel::prelude::*;
+            use schema::Action::dsl as action_fields;
+            use schema::Entry::dsl as entry_fields;
+
+            let mut query = schema::Entry::table
+                .select(DbEntry::as_select())
+                .order(entry_fields::hash.asc())
+                .limit(page_size)
+                .into_boxed();
+
+            if let Some(cursor) = cursor {
+                query = query.filter(entry_fields::hash.gt(cursor.to_vec()));
+            }
+
+            if let Some(author) = &filter.author {
+                query = query.filter(
+                    entry_fields::hash.nullable().eq_any(
+                        schema::Action::table
+                            .filter(action_fields::author.eq(author.get_raw_39().to_vec()))
+                            .select(action_fields::entry_hash),
+                    ),
+                );
+            }
+
+            let items = query.load::<DbEntry>(conn)?;
+            let next = next_cursor(&items, page_size, |entry| &entry.hash);
+
+            Ok(Page { items, next })
+        }),
+    )
+}
+
+/// A short page means the end of the table has been reached.
+fn next_cursor<T>(items: &[T], page_size: i64, key: impl Fn(&T) -> &Vec<u8>) -> Option<Vec<u8>> {
+    if (items.len() as i64) < page_size {
+        return None;
+    }
+
+    items.last().map(|item| key(item).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieve::test_db::{self, TestOp};
+    use holo_hash::{ActionHash, EntryHash};
+    use holochain_zome_types::prelude::{
+        Action, Create, EntryType, SIGNATURE_BYTES, Signature, SignedActionHashed,
+    };
+
+    /// A `Create` action, and the op for it, where the hashes are made from `i`.
+    fn authored(
+        conn: &mut SqliteConnection,
+        i: u8,
+        author: &AgentPubKey,
+        timestamp: i64,
+    ) -> TestOp {
+        let action = SignedActionHashed::from_content_sync(SignedAction::new(
+            Action::Create(Create {
+                author: author.clone(),
+                timestamp: Timestamp(timestamp),
+                action_seq: i as u32,
+                prev_action: ActionHash::from_raw_36(vec![0; 36]),
+                entry_type: EntryType::AgentPubKey,
+                entry_hash: EntryHash::from_raw_36(vec![i; 36]),
+                weight: Default::default(),
+            }),
+            Signature([0; SIGNATURE_BYTES]),
+        ));
+        test_db::insert_action(conn, &action);
+
+        let mut op = TestOp::new(i);
+        op.action_hash = action.as_hash().clone();
+        op.authored_timestamp = timestamp;
+        op
+    }
+
+    fn op_hashes(ops: &[u8]) -> Vec<Vec<u8>> {
+        ops.iter()
+            .map(|i| TestOp::new(*i).hash.get_raw_39().to_vec())
+            .collect()
+    }
+
+    fn scan_ops(conn: &mut SqliteConnection, filter: ScanFilter) -> Vec<Vec<u8>> {
+        iter_dht_ops(conn, filter, 2)
+            .map(|op| op.unwrap().hash)
+            .collect()
+    }
+
+    #[test]
+    fn pages_cover_every_row_once_in_key_order() {
+        let mut conn = test_db::open();
+        for i in [5, 1, 4, 2, 3] {
+            TestOp::new(i).insert(&mut conn);
+        }
+
+        for page_size in [1, 2, 5, 6] {
+            assert_eq!(
+                op_hashes(&[1, 2, 3, 4, 5]),
+                iter_dht_ops(&mut conn, ScanFilter::default(), page_size)
+                    .map(|op| op.unwrap().hash)
+                    .collect::<Vec<_>>(),
+                "page size {page_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_a_full_page_has_a_next_cursor() {
+        let keys = vec![vec![1], vec![2]];
+
+        assert_eq!(Some(vec![2]), next_cursor(&keys, 2, |k| k));
+        assert_eq!(None, next_cursor(&keys, 3, |k| k));
+        assert_eq!(None, next_cursor::<Vec<u8>>(&[], 2, |k| k));
+    }
+
+    #[test]
+    fn op_filters_are_applied_across_pages() {
+        let mut conn = test_db::open();
+        let alice = AgentPubKey::from_raw_36(vec![100; 36]);
+        let bob = AgentPubKey::from_raw_36(vec![101; 36]);
+
+        authored(&mut conn, 1, &alice, 10).insert(&mut conn);
+
+        let mut store_entry = authored(&mut conn, 2, &alice, 20);
+        store_entry.typ = DbOpType::StoreEntry;
+        store_entry.insert(&mut conn);
+
+        let mut rejected = authored(&mut conn, 3, &bob, 30);
+        rejected.validation_status = Some(ValidationStatus::Rejected);
+        rejected.insert(&mut conn);
+
+        authored(&mut conn, 4, &bob, 40).insert(&mut conn);
+
+        let mut late_store_entry = authored(&mut conn, 5, &bob, 50);
+        late_store_entry.typ = DbOpType::StoreEntry;
+        late_store_entry.insert(&mut conn);
+
+        assert_eq!(
+            op_hashes(&[2, 5]),
+            scan_ops(
+                &mut conn,
+                ScanFilter {
+                    op_type: Some(DbOpType::StoreEntry),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            op_hashes(&[3, 4, 5]),
+            scan_ops(
+                &mut conn,
+                ScanFilter {
+                    author: Some(bob),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            op_hashes(&[2, 3, 4]),
+            scan_ops(
+                &mut conn,
+                ScanFilter {
+                    since: Some(Timestamp(20)),
+                    until: Some(Timestamp(50)),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            op_hashes(&[3]),
+            scan_ops(
+                &mut conn,
+                ScanFilter {
+                    validation_status: Some(ValidationStatus::Rejected),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn action_filters_skip_whole_pages_without_stopping() {
+        let mut conn = test_db::open();
+        let alice = AgentPubKey::from_raw_36(vec![100; 36]);
+        let bob = AgentPubKey::from_raw_36(vec![101; 36]);
+
+        let mut actions = Vec::new();
+        for i in 1..=6 {
+            let author = if i % 2 == 0 { &bob } else { &alice };
+            let op = authored(&mut conn, i, author, i as i64 * 10);
+            actions.push((op.action_hash.get_raw_39().to_vec(), i));
+        }
+        actions.sort();
+
+        let expect = |keep: &dyn Fn(u8) -> bool| {
+            actions
+                .iter()
+                .filter(|(_, i)| keep(*i))
+                .map(|(hash, _)| hash.clone())
+                .collect::<Vec<_>>()
+        };
+        let scan = |conn: &mut SqliteConnection, filter: ScanFilter| {
+            iter_actions(conn, filter, 1)
+                .map(|action| action.unwrap().hash)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            expect(&|i| i % 2 == 0),
+            scan(
+                &mut conn,
+                ScanFilter {
+                    author: Some(bob),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            expect(&|i| (2..5).contains(&i)),
+            scan(
+                &mut conn,
+                ScanFilter {
+                    since: Some(Timestamp(20)),
+                    until: Some(Timestamp(50)),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn entries_are_filtered_by_the_author_of_their_action() {
+        use diesel::prelude::*;
+        use schema::Entry::dsl as entry_fields;
+
+        let mut conn = test_db::open();
+        let alice = AgentPubKey::from_raw_36(vec![100; 36]);
+        let bob = AgentPubKey::from_raw_36(vec![101; 36]);
+
+        for i in 1..=4 {
+            authored(&mut conn, i, if i == 3 { &bob } else { &alice }, 0);
+
+            diesel::insert_into(schema::Entry::table)
+                .values((
+                    entry_fields::hash
+                        .eq(EntryHash::from_raw_36(vec![i; 36]).get_raw_39().to_vec()),
+                    entry_fields::blob.eq(vec![i]),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let entries = iter_entries(
+            &mut conn,
+            ScanFilter {
+                author: Some(bob),
+                ..Default::default()
+            },
+            1,
+        )
+        .map(|entry| entry.unwrap().hash)
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![EntryHash::from_raw_36(vec![3; 36]).get_raw_39().to_vec()],
+            entries
+        );
+    }
+}
@@ -10,7 +10,7 @@ diesel::table! {
         basis_hash -> Nullable<Blob>,
         action_hash -> Nullable<Blob>,
         require_receipt -> Nullable<Bool>,
-        storage_center_loc -> Nullable<Int4>,
+        storage_center_loc -> Nullable<Int8>,
         authored_timestamp -> Nullable<Int8>,
         op_order -> Text,
         validation_status -> Nullable<Int2>,
@@ -0,0 +1,272 @@
+use crate::retrieve::{BUSY_TIMEOUT, Key, key_statements, sqlite_uri};
+use crate::{HcOpsError, HcOpsResult};
+use libsqlite3_sys as ffi;
+use std::ffi::{CStr, CString, c_int};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// A new directory in the temp directory to hold a snapshot, which is removed when dropped.
+///
+/// A snapshot of an unencrypted database is a plaintext copy of the conductor's data, so the
+/// directory is only accessible to the current user. It is created fresh, rather than reusing a
+/// path that might already exist.
+pub(crate) struct SnapshotDir {
+    dir: PathBuf,
+}
+
+impl SnapshotDir {
+    pub(crate) fn create() -> HcOpsResult<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        loop {
+            let dir = std::env::temp_dir().join(format!(
+                "hc-ops-snapshot-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            match builder.create(&dir) {
+                Ok(()) => return Ok(Self { dir }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub(crate) fn path(&self) -> PathBuf {
+        self.dir.join("snapshot.sqlite3")
+    }
+}
+
+impl Drop for SnapshotDir {
+    fn drop(&mut self) {
+        // On Unix, an open snapshot stays readable after it is removed. Other platforms don't
+        // allow removing an open file, so it is left in the temp directory.
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Copy a consistent snapshot of a database to `dest_path`, using the SQLite backup API.
+///
+/// The source is opened read-only and the whole database is copied in a single step, so the copy
+/// is of one point in time even if the conductor is writing. If the conductor holds a lock, the
+/// copy is retried until [BUSY_TIMEOUT]. The copy is encrypted with the same key as the source.
+///
+/// If the copy fails, anything written to `dest_path` is removed.
+pub fn snapshot_database(
+    source_path: &Path,
+    dest_path: &Path,
+    key: Option<&mut Key>,
+) -> HcOpsResult<()> {
+    let result = copy_database(source_path, dest_path, key);
+    if result.is_err() {
+        let _ = std::fs::remove_file(dest_path);
+    }
+
+    result
+}
+
+fn copy_database(source_path: &Path, dest_path: &Path, key: Option<&mut Key>) -> HcOpsResult<()> {
+    let source = RawConnection::open(
+        &sqlite_uri(source_path, "mode=ro")?,
+        ffi::SQLITE_OPEN_READONLY,
+    )?;
+    let dest = RawConnection::open(
+        &sqlite_uri(dest_path, "mode=rwc")?,
+        ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE,
+    )?;
+
+    // SAFETY: The handle is open until `source` is dropped.
+    source
+        .check(unsafe { ffi::sqlite3_busy_timeout(source.0, BUSY_TIMEOUT.as_millis() as c_int) })?;
+
+    if let Some(key) = key {
+        let mut stmt = key_statements(key)?;
+        let lock = stmt.lock();
+        let stmt = CStr::from_bytes_with_nul(&lock).map_err(HcOpsError::other)?;
+
+        source.execute(stmt)?;
+        dest.execute(stmt)?;
+    }
+
+    // SAFETY: Both handles stay open until the backup is finished, below.
+    let backup =
+        unsafe { ffi::sqlite3_backup_init(dest.0, c"main".as_ptr(), source.0, c"main".as_ptr()) };
+    if backup.is_null() {
+        return Err(dest.error());
+    }
+
+    let started = Instant::now();
+    let result = loop {
+        // SAFETY: The backup was successfully initialised and has not been finished.
+        match unsafe { ffi::sqlite3_backup_step(backup, -1) } {
+            ffi::SQLITE_DONE => break Ok(()),
+            ffi::SQLITE_OK => {}
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if started.elapsed() < BUSY_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            rc => break Err(rc),
+        }
+    };
+
+    // SAFETY: The backup is not used after it is finished.
+    let finished = unsafe { ffi::sqlite3_backup_finish(backup) };
+
+    match result {
+        Ok(()) => dest.check(finished),
+        Err(rc) => Err(HcOpsError::Other(
+            format!(
+                "Failed to snapshot {}: {}",
+                source_path.display(),
+                error_string(rc)
+            )
+            .into(),
+        )),
+    }
+}
+
+/// A connection opened directly with SQLite, for the backup API which diesel doesn't expose.
+struct RawConnection(*mut ffi::sqlite3);
+
+impl RawConnection {
+    fn open(uri: &str, flags: c_int) -> HcOpsResult<Self> {
+        let uri = CString::new(uri).map_err(HcOpsError::other)?;
+
+        let mut db = std::ptr::null_mut();
+        // SAFETY: `uri` is NUL terminated, and the handle is closed on drop even if opening fails.
+        let rc = unsafe {
+            ffi::sqlite3_open_v2(
+                uri.as_ptr(),
+                &mut db,
+                flags | ffi::SQLITE_OPEN_URI,
+                std::ptr::null(),
+            )
+        };
+
+        let conn = RawConnection(db);
+        conn.check(rc)?;
+
+        Ok(conn)
+    }
+
+    fn execute(&self, sql: &CStr) -> HcOpsResult<()> {
+        // SAFETY: The handle is open and `sql` is NUL terminated. There is no callback.
+        self.check(unsafe {
+            ffi::sqlite3_exec(
+                self.0,
+                sql.as_ptr(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        })
+    }
+
+    fn check(&self, rc: c_int) -> HcOpsResult<()> {
+        if rc == ffi::SQLITE_OK {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn error(&self) -> HcOpsError {
+        if self.0.is_null() {
+            return HcOpsError::Other("Out of memory opening a database".into());
+        }
+
+        // SAFETY: The handle is not null, and the message is copied before the handle is used again.
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) };
+
+        HcOpsError::Other(message.to_string_lossy().into_owned().into())
+    }
+}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        // SAFETY: Closing a null handle is a no-op, and the handle is not used after this.
+        unsafe { ffi::sqlite3_close(self.0) };
+    }
+}
+
+fn error_string(rc: c_int) -> String {
+    // SAFETY: SQLite returns a static string for any result code.
+    unsafe { CStr::from_ptr(ffi::sqlite3_errstr(rc)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+    use diesel::{Connection, RunQueryDsl, SqliteConnection, sql_query};
+
+    #[derive(diesel::QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    #[test]
+    fn snapshot_is_readable_while_source_is_open() {
+        let source_dir = SnapshotDir::create().unwrap();
+        let dest_dir = SnapshotDir::create().unwrap();
+        let source_path = source_dir.path();
+        let dest_path = dest_dir.path();
+
+        let mut source =
+            SqliteConnection::establish(&sqlite_uri(&source_path, "mode=rwc").unwrap()).unwrap();
+        source
+            .batch_execute(
+                "PRAGMA journal_mode = WAL;
+CREATE TABLE op (id INTEGER PRIMARY KEY);
+INSERT INTO op (id) VALUES (1), (2), (3);",
+            )
+            .unwrap();
+
+        snapshot_database(&source_path, &dest_path, None).unwrap();
+
+        let mut dest =
+            SqliteConnection::establish(&sqlite_uri(&dest_path, "mode=ro&immutable=1").unwrap())
+                .unwrap();
+        let copied = sql_query("SELECT count(*) AS count FROM op")
+            .get_result::<Count>(&mut dest)
+            .unwrap();
+
+        assert_eq!(3, copied.count);
+    }
+
+    #[test]
+    fn failed_snapshot_is_removed() {
+        let dir = SnapshotDir::create().unwrap();
+        let source_path = dir.path().with_file_name("not-a-database.sqlite3");
+        let dest_path = dir.path();
+        std::fs::write(&source_path, vec![7; 4096]).unwrap();
+
+        assert!(snapshot_database(&source_path, &dest_path, None).is_err());
+        assert!(!dest_path.exists());
+    }
+
+    #[test]
+    fn snapshot_dir_is_private_and_removed() {
+        let dir = SnapshotDir::create().unwrap();
+        let path = dir.dir.clone();
+        std::fs::write(dir.path(), b"data").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o700, mode & 0o777);
+        }
+
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::HcOpsResult;
+use crate::retrieve::{DbKind, database_path, parse_authored_database_name};
+use diesel::{QueryableByName, RunQueryDsl, SqliteConnection, sql_query};
+use holochain_conductor_api::DnaStorageInfo;
+use holochain_zome_types::prelude::DnaHash;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+/// Sizes of the authored, DHT and cache databases for a DNA.
+///
+/// The authored size is the total over all agents with a cell for the DNA.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseSizes {
+    pub authored: u64,
+    pub dht: u64,
+    pub cache: u64,
+}
+
+impl DatabaseSizes {
+    pub fn total(&self) -> u64 {
+        self.authored + self.dht + self.cache
+    }
+
+    fn distance(&self, other: &DatabaseSizes) -> u64 {
+        self.authored.abs_diff(other.authored)
+            + self.dht.abs_diff(other.dht)
+            + self.cache.abs_diff(other.cache)
+    }
+}
+
+/// The part of a conductor's storage info that can be used to work out which DNA it is for.
+#[derive(Debug, Clone)]
+pub struct ReportedStorage {
+    pub used_by: BTreeSet<String>,
+    pub on_disk: DatabaseSizes,
+}
+
+impl From<&DnaStorageInfo> for ReportedStorage {
+    fn from(info: &DnaStorageInfo) -> Self {
+        Self {
+            used_by: info.used_by.iter().cloned().collect(),
+            on_disk: DatabaseSizes {
+                authored: info.authored_data_size_on_disk as u64,
+                dht: info.dht_data_size_on_disk as u64,
+                cache: info.cache_data_size_on_disk as u64,
+            },
+        }
+    }
+}
+
+/// Work out which DNA each entry in a conductor's storage info is for.
+///
+/// The conductor only reports which apps use each DNA. Where that identifies a single DNA, it is
+/// used. Otherwise, such as for an app with several roles, the sizes measured from the file
+/// system are matched against the reported sizes, closest first. Entries that can't be attributed
+/// are `None`.
+pub fn attribute_storage(
+    reported: &[ReportedStorage],
+    dna_apps: &BTreeMap<DnaHash, BTreeSet<String>>,
+    measured: &HashMap<DnaHash, DatabaseSizes>,
+) -> Vec<Option<DnaHash>> {
+    let candidates = |storage: &ReportedStorage| {
+        dna_apps
+            .iter()
+            .filter(|(_, apps)| **apps == storage.used_by)
+            .map(|(dna, _)| dna)
+            .collect::<Vec<_>>()
+    };
+
+    let mut attributed = reported
+        .iter()
+        .map(|storage| match candidates(storage).as_slice() {
+            [dna] => Some((*dna).clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut taken = attributed.iter().flatten().cloned().collect::<HashSet<_>>();
+
+    let mut matches = reported
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| attributed[*i].is_none())
+        .flat_map(|(i, storage)| {
+            candidates(storage).into_iter().filter_map(move |dna| {
+                measured
+                    .get(dna)
+                    .map(|sizes| (sizes.distance(&storage.on_disk), i, dna))
+            })
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(distance, _, _)| *distance);
+
+    for (_, i, dna) in matches {
+        if attributed[i].is_none() && !taken.contains(dna) {
+            attributed[i] = Some(dna.clone());
+            taken.insert(dna.clone());
+        }
+    }
+
+    attributed
+}
+
+/// Measure the size on disk of the databases for a DNA, from the database files.
+///
+/// Only the main database files are measured, not their `-wal` and `-shm` files, to match the
+/// sizes that the conductor reports. Missing databases have a size of 0.
+pub fn measure_database_sizes<P: AsRef<Path>>(
+    data_root_path: P,
+    dna_hash: &DnaHash,
+) -> HcOpsResult<DatabaseSizes> {
+    let data_root_path = data_root_path.as_ref();
+    let file_size = |path: &Path| -> HcOpsResult<u64> {
+        Ok(if path.is_file() {
+            std::fs::metadata(path)?.len()
+        } else {
+            0
+        })
+    };
+
+    let mut authored = 0;
+    let authored_dir = data_root_path.join("databases").join("authored");
+    if authored_dir.is_dir() {
+        for entry in std::fs::read_dir(&authored_dir)? {
+            let entry = entry?;
+            let is_for_dna = entry
+                .file_name()
+                .to_str()
+                .and_then(parse_authored_database_name)
+                .is_some_and(|(dna, _)| dna == *dna_hash);
+
+            if is_for_dna {
+                authored += file_size(&entry.path())?;
+            }
+        }
+    }
+
+    Ok(DatabaseSizes {
+        authored,
+        dht: file_size(&database_path(data_root_path, &DbKind::Dht, dna_hash))?,
+        cache: file_size(&database_path(data_root_path, &DbKind::Cache, dna_hash))?,
+    })
+}
+
+/// How the pages of a database are used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, QueryableByName, Serialize, Deserialize)]
+pub struct PageStats {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub page_size: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub page_count: i64,
+    /// Pages that are unused, and could be reclaimed by a vacuum.
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub freelist_count: i64,
+}
+
+impl PageStats {
+    pub fn on_disk(&self) -> u64 {
+        (self.page_size * self.page_count) as u64
+    }
+
+    pub fn free(&self) -> u64 {
+        (self.page_size * self.freelist_count) as u64
+    }
+}
+
+pub fn get_page_stats(conn: &mut SqliteConnection) -> HcOpsResult<PageStats> {
+    Ok(sql_query(
+        "SELECT page_size, page_count, freelist_count \
+         FROM pragma_page_size(), pragma_page_count(), pragma_freelist_count()",
+    )
+    .get_result(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(authored: u64, dht: u64, cache: u64) -> DatabaseSizes {
+        DatabaseSizes {
+            authored,
+            dht,
+            cache,
+        }
+    }
+
+    fn reported(apps: &[&str], on_disk: DatabaseSizes) -> ReportedStorage {
+        ReportedStorage {
+            used_by: apps.iter().map(|a| a.to_string()).collect(),
+            on_disk,
+        }
+    }
+
+    #[test]
+    fn attributes_by_apps_then_by_size() {
+        let solo = DnaHash::from_raw_36(vec![1; 36]);
+        let first_role = DnaHash::from_raw_36(vec![2; 36]);
+        let second_role = DnaHash::from_raw_36(vec![3; 36]);
+        let unmeasured = DnaHash::from_raw_36(vec![4; 36]);
+
+        let dna_apps = BTreeMap::from([
+            (solo.clone(), BTreeSet::from(["solo".to_string()])),
+            (first_role.clone(), BTreeSet::from(["multi".to_string()])),
+            (second_role.clone(), BTreeSet::from(["multi".to_string()])),
+            (unmeasured, BTreeSet::from(["other".to_string()])),
+            (
+                DnaHash::from_raw_36(vec![5; 36]),
+                BTreeSet::from(["other".to_string()]),
+            ),
+        ]);
+        let measured = HashMap::from([
+            (first_role.clone(), sizes(4096, 8192, 4096)),
+            (second_role.clone(), sizes(4096, 1 << 20, 4096)),
+        ]);
+
+        assert_eq!(
+            vec![Some(second_role), Some(solo), Some(first_role), None],
+            attribute_storage(
+                &[
+                    reported(&["multi"], sizes(4096, 1 << 20, 4096)),
+                    reported(&["solo"], sizes(0, 0, 0)),
+                    reported(&["multi"], sizes(4096, 12288, 4096)),
+                    reported(&["other"], sizes(0, 0, 0)),
+                ],
+                &dna_apps,
+                &measured,
+            )
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! In-memory databases with the tables that hc-ops reads, for tests.
+
+use crate::retrieve::{DbOpType, ValidationStatus, schema};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use holo_hash::{ActionHash, AnyLinkableHash, DhtOpHash};
+use holochain_zome_types::prelude::{Action, SignedAction, SignedActionHashed};
+
+/// The tables as Holochain creates them, with only the columns that hc-ops reads.
+const CREATE_TABLES: &str = "
+CREATE TABLE DhtOp (
+    hash BLOB PRIMARY KEY,
+    type TEXT,
+    basis_hash BLOB,
+    action_hash BLOB,
+    require_receipt INTEGER,
+    storage_center_loc INTEGER,
+    authored_timestamp INTEGER,
+    op_order TEXT NOT NULL,
+    validation_status INTEGER,
+    when_integrated INTEGER,
+    withhold_publish INTEGER,
+    receipts_complete INTEGER,
+    last_publish_time INTEGER,
+    validation_stage INTEGER,
+    num_validation_attempts INTEGER,
+    last_validation_attempt INTEGER,
+    when_sys_validated INTEGER,
+    when_app_validated INTEGER,
+    when_stored INTEGER,
+    serialized_size INTEGER,
+    transfer_source BLOB,
+    transfer_method INTEGER,
+    transfer_time INTEGER
+);
+CREATE TABLE Action (
+    hash BLOB PRIMARY KEY,
+    type TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    author BLOB NOT NULL,
+    blob BLOB NOT NULL,
+    prev_hash BLOB,
+    entry_hash BLOB,
+    entry_type TEXT,
+    private_entry INTEGER,
+    original_entry_hash BLOB,
+    original_action_hash BLOB,
+    deletes_entry_hash BLOB,
+    deletes_action_hash BLOB,
+    base_hash BLOB,
+    zome_index INTEGER,
+    link_type INTEGER,
+    tag BLOB,
+    create_link_hash BLOB,
+    membrane_proof BLOB,
+    prev_dna_hash BLOB
+);
+CREATE TABLE Entry (
+    hash BLOB PRIMARY KEY,
+    blob BLOB NOT NULL,
+    tag TEXT,
+    grantor BLOB,
+    cap_secret BLOB,
+    functions BLOB,
+    access_type TEXT,
+    access_secret BLOB,
+    access_assignees BLOB
+);
+CREATE TABLE Warrant (
+    hash BLOB PRIMARY KEY,
+    author BLOB NOT NULL,
+    timestamp INTEGER NOT NULL,
+    warrantee BLOB NOT NULL,
+    type TEXT NOT NULL,
+    blob BLOB NOT NULL
+);
+CREATE TABLE SliceHash (
+    arc_start INTEGER NOT NULL,
+    arc_end INTEGER NOT NULL,
+    slice_index INTEGER NOT NULL,
+    hash BLOB NOT NULL,
+    PRIMARY KEY (arc_start, arc_end, slice_index)
+);
+";
+
+pub(crate) fn open() -> SqliteConnection {
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.batch_execute(CREATE_TABLES).unwrap();
+
+    conn
+}
+
+/// An op row, with the columns that tests usually care about.
+#[derive(Debug, Clone)]
+pub(crate) struct TestOp {
+    pub hash: DhtOpHash,
+    pub typ: DbOpType,
+    pub action_hash: ActionHash,
+    pub storage_center_loc: u32,
+    pub authored_timestamp: i64,
+    pub validation_status: Option<ValidationStatus>,
+    pub when_integrated: Option<i64>,
+    pub last_validation_attempt: Option<i64>,
+    pub num_validation_attempts: Option<i32>,
+    pub serialized_size: Option<i32>,
+}
+
+impl TestOp {
+    /// An integrated, valid `StoreRecord` op, where the hashes are made from `i`.
+    pub fn new(i: u8) -> Self {
+        Self {
+            hash: DhtOpHash::from_raw_36(vec![i; 36]),
+            typ: DbOpType::StoreRecord,
+            action_hash: ActionHash::from_raw_36(vec![i; 36]),
+            storage_center_loc: i as u32,
+            authored_timestamp: i as i64,
+            validation_status: Some(ValidationStatus::Valid),
+            when_integrated: Some(i as i64),
+            last_validation_attempt: None,
+            num_validation_attempts: None,
+            serialized_size: Some(100),
+        }
+    }
+
+    pub fn insert(&self, conn: &mut SqliteConnection) {
+        use schema::DhtOp::dsl as f;
+
+        let basis: AnyLinkableHash = self.action_hash.clone().into();
+
+        diesel::insert_into(schema::DhtOp::table)
+            .values((
+                f::hash.eq(self.hash.get_raw_39().to_vec()),
+                f::typ.eq(Some(self.typ)),
+                f::basis_hash.eq(Some(basis.get_raw_39().to_vec())),
+                f::action_hash.eq(Some(self.action_hash.get_raw_39().to_vec())),
+                f::storage_center_loc.eq(Some(self.storage_center_loc as i64)),
+                f::authored_timestamp.eq(Some(self.authored_timestamp)),
+                f::op_order.eq(format!("{}", self.authored_timestamp)),
+                f::validation_status.eq(self.validation_status),
+                f::when_integrated.eq(self.when_integrated),
+                f::last_validation_attempt.eq(self.last_validation_attempt),
+                f::num_validation_attempts.eq(self.num_validation_attempts),
+                f::serialized_size.eq(self.serialized_size),
+            ))
+            .execute(conn)
+            .unwrap();
+    }
+}
+
+/// Insert an action, filling in the columns that Holochain derives from it.
+pub(crate) fn insert_action(conn: &mut SqliteConnection, action: &SignedActionHashed) {
+    use schema::Action::dsl as f;
+
+    let (original_action_hash, original_entry_hash, deletes_action_hash, deletes_entry_hash) =
+        match action.action() {
+            Action::Update(update) => (
+                Some(update.original_action_address.get_raw_39().to_vec()),
+                Some(update.original_entry_address.get_raw_39().to_vec()),
+                None,
+                None,
+            ),
+            Action::Delete(delete) => (
+                None,
+                None,
+                Some(delete.deletes_address.get_raw_39().to_vec()),
+                Some(delete.deletes_entry_address.get_raw_39().to_vec()),
+            ),
+            _ => (None, None, None, None),
+        };
+
+    let blob = holochain_serialized_bytes::encode(&SignedAction::new(
+        action.action().clone(),
+        action.signature().clone(),
+    ))
+    .unwrap();
+
+    diesel::insert_into(schema::Action::table)
+        .values((
+            f::hash.eq(action.as_hash().get_raw_39().to_vec()),
+            f::typ.eq(format!("{:?}", action.action().action_type())),
+            f::seq.eq(action.action().action_seq() as i32),
+            f::author.eq(action.action().author().get_raw_39().to_vec()),
+            f::blob.eq(blob),
+            f::prev_hash.eq(action
+                .action()
+                .prev_action()
+                .map(|h| h.get_raw_39().to_vec())),
+            f::entry_hash.eq(action
+                .action()
+                .entry_hash()
+                .map(|h| h.get_raw_39().to_vec())),
+            f::original_action_hash.eq(original_action_hash),
+            f::original_entry_hash.eq(original_entry_hash),
+            f::deletes_action_hash.eq(deletes_action_hash),
+            f::deletes_entry_hash.eq(deletes_entry_hash),
+        ))
+        .execute(conn)
+        .unwrap();
+}
@@ -0,0 +1,81 @@
+use crate::HcOpsResult;
+use crate::retrieve::history::get_action;
+use crate::retrieve::{DbWarrant, schema};
+use diesel::SqliteConnection;
+use holo_hash::{ActionHash, AgentPubKey};
+use holochain_zome_types::prelude::{
+    ChainIntegrityWarrant, SignedActionHashed, SignedWarrant, WarrantProof,
+};
+use serde::{Deserialize, Serialize};
+
+/// A warrant, with the actions it gives as evidence looked up locally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedWarrant {
+    pub warrant: SignedWarrant,
+    pub evidence: Vec<WarrantEvidence>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarrantEvidence {
+    pub action_hash: ActionHash,
+    /// The action, if it is held locally.
+    pub action: Option<SignedActionHashed>,
+}
+
+/// Get warrants, optionally only those issued against the given agent.
+pub fn get_warrants(
+    conn: &mut SqliteConnection,
+    warrantee: Option<&AgentPubKey>,
+) -> HcOpsResult<Vec<SignedWarrant>> {
+    use diesel::prelude::*;
+    use schema::Warrant::dsl as warrant_fields;
+
+    let mut query = schema::Warrant::table
+        .select(DbWarrant::as_select())
+        .order(warrant_fields::timestamp.asc())
+        .into_boxed();
+
+    if let Some(warrantee) = warrantee {
+        query = query.filter(warrant_fields::warrantee.eq(warrantee.get_raw_39()));
+    }
+
+    query
+        .load::<DbWarrant>(conn)?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
+}
+
+/// The hashes of the actions that a warrant gives as evidence.
+pub fn warrant_evidence_hashes(warrant: &SignedWarrant) -> Vec<ActionHash> {
+    match &warrant.proof {
+        WarrantProof::ChainIntegrity(ChainIntegrityWarrant::InvalidChainOp { action, .. }) => {
+            vec![action.0.clone()]
+        }
+        WarrantProof::ChainIntegrity(ChainIntegrityWarrant::ChainFork { action_pair, .. }) => {
+            vec![action_pair.0.0.clone(), action_pair.1.0.clone()]
+        }
+    }
+}
+
+/// Look up the evidence for a warrant, checking the DHT database before the cache.
+pub fn resolve_warrant(
+    dht: &mut SqliteConnection,
+    cache: &mut SqliteConnection,
+    warrant: SignedWarrant,
+) -> HcOpsResult<ResolvedWarrant> {
+    let mut evidence = Vec::new();
+    for action_hash in warrant_evidence_hashes(&warrant) {
+        let action = match get_action(dht, &action_hash)? {
+            Some(action) => Some(action),
+            None => get_action(cache, &action_hash)?,
+        };
+
+        evidence.push(WarrantEvidence {
+            action_hash,
+            action,
+        });
+    }
+
+    Ok(ResolvedWarrant { warrant, evidence })
+}
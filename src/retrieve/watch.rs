@@ -0,0 +1,428 @@
+use crate::HcOpsResult;
+use crate::retrieve::{DbOpType, ValidationStatus};
+use diesel::sql_types::{BigInt, Binary, Integer, Nullable, SmallInt, Text};
+use diesel::{QueryableByName, RunQueryDsl, SqliteConnection, sql_query};
+use holo_hash::{ActionHash, DhtOpHash};
+use holochain_zome_types::prelude::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Something that changed in the authored or DHT database between two polls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchEvent {
+    /// When the change was seen, not when it happened.
+    pub seen_at: Timestamp,
+    pub database: WatchedDatabase,
+    pub kind: WatchEventKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchedDatabase {
+    Authored,
+    Dht,
+}
+
+impl Display for WatchedDatabase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchedDatabase::Authored => write!(f, "authored"),
+            WatchedDatabase::Dht => write!(f, "dht"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WatchEventKind {
+    NewOp {
+        hash: DhtOpHash,
+        typ: Option<DbOpType>,
+        validation_status: Option<ValidationStatus>,
+    },
+    Integrated {
+        hash: DhtOpHash,
+        when_integrated: Timestamp,
+    },
+    ValidationStatusChanged {
+        hash: DhtOpHash,
+        from: Option<ValidationStatus>,
+        to: Option<ValidationStatus>,
+    },
+    ValidationAttempted {
+        hash: DhtOpHash,
+        num_validation_attempts: u32,
+    },
+    NewAction {
+        hash: ActionHash,
+        typ: String,
+        seq: u32,
+    },
+}
+
+/// Tracks ops and authored actions between polls, so that only changes are reported.
+///
+/// Each poll only loads the rows that were added, integrated or had a validation attempt since the
+/// last poll, rather than the whole of each table.
+pub struct Watcher {
+    authored_ops: OpTracker,
+    dht_ops: OpTracker,
+    last_action_rowid: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OpState {
+    typ: Option<DbOpType>,
+    validation_status: Option<ValidationStatus>,
+    when_integrated: Option<i64>,
+    num_validation_attempts: u32,
+}
+
+/// The last state seen of each op in one database, and how far through its changes it has read.
+struct OpTracker {
+    states: HashMap<Vec<u8>, OpState>,
+    last_rowid: i64,
+    last_integrated: i64,
+    last_validation_attempt: i64,
+}
+
+#[derive(QueryableByName)]
+struct OpRow {
+    #[diesel(sql_type = BigInt)]
+    rowid: i64,
+    #[diesel(sql_type = Binary)]
+    hash: Vec<u8>,
+    #[diesel(sql_type = Nullable<Text>)]
+    typ: Option<DbOpType>,
+    #[diesel(sql_type = Nullable<SmallInt>)]
+    validation_status: Option<ValidationStatus>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    when_integrated: Option<i64>,
+    #[diesel(sql_type = Nullable<Integer>)]
+    num_validation_attempts: Option<i32>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    last_validation_attempt: Option<i64>,
+}
+
+#[derive(QueryableByName)]
+struct ActionRow {
+    #[diesel(sql_type = BigInt)]
+    rowid: i64,
+    #[diesel(sql_type = Binary)]
+    hash: Vec<u8>,
+    #[diesel(sql_type = Text)]
+    typ: String,
+    #[diesel(sql_type = Integer)]
+    seq: i32,
+}
+
+impl Watcher {
+    /// Take the starting state of the databases, which is not reported as events.
+    pub fn new(authored: &mut SqliteConnection, dht: &mut SqliteConnection) -> HcOpsResult<Self> {
+        let mut authored_ops = OpTracker::new();
+        authored_ops.poll(authored)?;
+        let mut dht_ops = OpTracker::new();
+        dht_ops.poll(dht)?;
+
+        Ok(Self {
+            authored_ops,
+            dht_ops,
+            last_action_rowid: load_new_actions(authored, -1)?
+                .iter()
+                .map(|action| action.rowid)
+                .max()
+                .unwrap_or(-1),
+        })
+    }
+
+    /// Compare the databases with the last poll, returning what changed.
+    ///
+    /// New actions come first in chain order, followed by op changes ordered by op hash, so that
+    /// the same changes are always reported in the same order.
+    pub fn poll(
+        &mut self,
+        authored: &mut SqliteConnection,
+        dht: &mut SqliteConnection,
+    ) -> HcOpsResult<Vec<WatchEvent>> {
+        let seen_at = Timestamp::now();
+        let mut events = Vec::new();
+
+        let mut new_actions = load_new_actions(authored, self.last_action_rowid)?;
+        new_actions.sort_by_key(|action| action.seq);
+        for action in new_actions {
+            self.last_action_rowid = self.last_action_rowid.max(action.rowid);
+            events.push(WatchEvent {
+                seen_at,
+                database: WatchedDatabase::Authored,
+                kind: WatchEventKind::NewAction {
+                    hash: ActionHash::try_from_raw_39(action.hash)?,
+                    typ: action.typ,
+                    seq: action.seq as u32,
+                },
+            });
+        }
+
+        for (database, ops, conn) in [
+            (WatchedDatabase::Authored, &mut self.authored_ops, authored),
+            (WatchedDatabase::Dht, &mut self.dht_ops, dht),
+        ] {
+            events.extend(ops.poll(conn)?.into_iter().map(|kind| WatchEvent {
+                seen_at,
+                database,
+                kind,
+            }));
+        }
+
+        Ok(events)
+    }
+}
+
+impl OpTracker {
+    fn new() -> Self {
+        Self {
+            states: HashMap::new(),
+            last_rowid: -1,
+            last_integrated: i64::MIN,
+            last_validation_attempt: i64::MIN,
+        }
+    }
+
+    /// Load the ops that changed since the last poll, and report how they changed.
+    fn poll(&mut self, conn: &mut SqliteConnection) -> HcOpsResult<Vec<WatchEventKind>> {
+        // Timestamps are compared inclusively, because another op could later be given the same
+        // timestamp as the last one seen. Rows that haven't changed produce no events.
+        let mut rows = sql_query(
+            "SELECT rowid, hash, type AS typ, validation_status, when_integrated, \
+             num_validation_attempts, last_validation_attempt \
+             FROM DhtOp \
+             WHERE rowid > ? OR when_integrated >= ? OR last_validation_attempt >= ?",
+        )
+        .bind::<BigInt, _>(self.last_rowid)
+        .bind::<BigInt, _>(self.last_integrated)
+        .bind::<BigInt, _>(self.last_validation_attempt)
+        .load::<OpRow>(conn)?;
+        rows.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        let mut changed = Vec::with_capacity(rows.len());
+        for row in rows {
+            self.last_rowid = self.last_rowid.max(row.rowid);
+            if let Some(when_integrated) = row.when_integrated {
+                self.last_integrated = self.last_integrated.max(when_integrated);
+            }
+            if let Some(last_validation_attempt) = row.last_validation_attempt {
+                self.last_validation_attempt =
+                    self.last_validation_attempt.max(last_validation_attempt);
+            }
+
+            changed.push((
+                row.hash,
+                OpState {
+                    typ: row.typ,
+                    validation_status: row.validation_status,
+                    when_integrated: row.when_integrated,
+                    num_validation_attempts: row.num_validation_attempts.unwrap_or_default() as u32,
+                },
+            ));
+        }
+
+        let events = diff_op_states(&self.states, &changed)?;
+        self.states.extend(changed);
+
+        Ok(events)
+    }
+}
+
+fn load_new_actions(
+    authored: &mut SqliteConnection,
+    after_rowid: i64,
+) -> HcOpsResult<Vec<ActionRow>> {
+    Ok(
+        sql_query("SELECT rowid, hash, type AS typ, seq FROM Action WHERE rowid > ?")
+            .bind::<BigInt, _>(after_rowid)
+            .load::<ActionRow>(authored)?,
+    )
+}
+
+/// The changes from one set of op states to the next.
+///
+/// Only the ops in `current` are compared, so it can hold just the ops that might have changed.
+/// Events are in the same order as `current`.
+///
+/// A new op is reported once, with its current status, rather than also reporting its validation
+/// and integration. Ops that disappear, for example when the cache is cleared, are not reported.
+fn diff_op_states(
+    previous: &HashMap<Vec<u8>, OpState>,
+    current: &[(Vec<u8>, OpState)],
+) -> HcOpsResult<Vec<WatchEventKind>> {
+    let mut events = Vec::new();
+
+    for (hash, state) in current {
+        let Some(previous) = previous.get(hash) else {
+            events.push(WatchEventKind::NewOp {
+                hash: DhtOpHash::try_from_raw_39(hash.clone())?,
+                typ: state.typ,
+                validation_status: state.validation_status,
+            });
+            continue;
+        };
+
+        if previous == state {
+            continue;
+        }
+
+        if state.num_validation_attempts > previous.num_validation_attempts {
+            events.push(WatchEventKind::ValidationAttempted {
+                hash: DhtOpHash::try_from_raw_39(hash.clone())?,
+                num_validation_attempts: state.num_validation_attempts,
+            });
+        }
+
+        if state.validation_status != previous.validation_status {
+            events.push(WatchEventKind::ValidationStatusChanged {
+                hash: DhtOpHash::try_from_raw_39(hash.clone())?,
+                from: previous.validation_status,
+                to: state.validation_status,
+            });
+        }
+
+        if let (None, Some(when_integrated)) = (previous.when_integrated, state.when_integrated) {
+            events.push(WatchEventKind::Integrated {
+                hash: DhtOpHash::try_from_raw_39(hash.clone())?,
+                when_integrated: Timestamp(when_integrated),
+            });
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op_state(
+        validation_status: Option<ValidationStatus>,
+        when_integrated: Option<i64>,
+        num_validation_attempts: u32,
+    ) -> OpState {
+        OpState {
+            typ: Some(DbOpType::StoreRecord),
+            validation_status,
+            when_integrated,
+            num_validation_attempts,
+        }
+    }
+
+    #[test]
+    fn reports_validation_and_integration_in_order() {
+        let hash = DhtOpHash::from_raw_36(vec![1; 36]);
+        let key = hash.get_raw_39().to_vec();
+
+        let previous = HashMap::from([(key.clone(), op_state(None, None, 1))]);
+        let current = [(key, op_state(Some(ValidationStatus::Valid), Some(100), 2))];
+
+        assert_eq!(
+            vec![
+                WatchEventKind::ValidationAttempted {
+                    hash: hash.clone(),
+                    num_validation_attempts: 2,
+                },
+                WatchEventKind::ValidationStatusChanged {
+                    hash: hash.clone(),
+                    from: None,
+                    to: Some(ValidationStatus::Valid),
+                },
+                WatchEventKind::Integrated {
+                    hash,
+                    when_integrated: Timestamp(100),
+                },
+            ],
+            diff_op_states(&previous, &current).unwrap()
+        );
+    }
+
+    #[test]
+    fn new_ops_are_reported_once() {
+        let hash = DhtOpHash::from_raw_36(vec![2; 36]);
+        let current = [(
+            hash.get_raw_39().to_vec(),
+            op_state(Some(ValidationStatus::Valid), Some(100), 1),
+        )];
+
+        assert_eq!(
+            vec![WatchEventKind::NewOp {
+                hash,
+                typ: Some(DbOpType::StoreRecord),
+                validation_status: Some(ValidationStatus::Valid),
+            }],
+            diff_op_states(&HashMap::new(), &current).unwrap()
+        );
+
+        let previous = HashMap::from(current.clone());
+        assert!(diff_op_states(&previous, &current).unwrap().is_empty());
+    }
+
+    #[test]
+    fn poll_reports_only_changed_ops_in_hash_order() {
+        use crate::retrieve::test_db::{self, TestOp};
+        use diesel::prelude::*;
+
+        let mut authored = test_db::open();
+        let mut dht = test_db::open();
+
+        let mut pending = TestOp::new(5);
+        pending.validation_status = None;
+        pending.when_integrated = None;
+        pending.insert(&mut dht);
+        TestOp::new(6).insert(&mut dht);
+
+        let mut watcher = Watcher::new(&mut authored, &mut dht).unwrap();
+        assert!(watcher.poll(&mut authored, &mut dht).unwrap().is_empty());
+
+        TestOp::new(9).insert(&mut dht);
+        TestOp::new(1).insert(&mut dht);
+        {
+            use crate::retrieve::schema::DhtOp::dsl as f;
+            diesel::update(f::DhtOp.filter(f::hash.eq(pending.hash.get_raw_39().to_vec())))
+                .set((
+                    f::validation_status.eq(Some(ValidationStatus::Valid)),
+                    f::when_integrated.eq(Some(1_000)),
+                ))
+                .execute(&mut dht)
+                .unwrap();
+        }
+
+        let events = watcher
+            .poll(&mut authored, &mut dht)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                WatchEventKind::NewOp {
+                    hash: TestOp::new(1).hash,
+                    typ: Some(DbOpType::StoreRecord),
+                    validation_status: Some(ValidationStatus::Valid),
+                },
+                WatchEventKind::ValidationStatusChanged {
+                    hash: pending.hash.clone(),
+                    from: None,
+                    to: Some(ValidationStatus::Valid),
+                },
+                WatchEventKind::Integrated {
+                    hash: pending.hash,
+                    when_integrated: Timestamp(1_000),
+                },
+                WatchEventKind::NewOp {
+                    hash: TestOp::new(9).hash,
+                    typ: Some(DbOpType::StoreRecord),
+                    validation_status: Some(ValidationStatus::Valid),
+                },
+            ],
+            events
+        );
+
+        assert!(watcher.poll(&mut authored, &mut dht).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+//! Verify that a source chain is well formed.
+//!
+//! Works on the [ChainRecord]s returned by [get_self_agent_chain](crate::retrieve::get_self_agent_chain)
+//! and [get_agent_chain](crate::retrieve::get_agent_chain), and reports every problem found
+//! rather than stopping at the first one.
+
+use crate::retrieve::ChainRecord;
+use holo_hash::{ActionHash, AgentPubKey, EntryHash};
+use holochain_types::chain::ChainItem;
+use holochain_zome_types::prelude::{Action, EntryType};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// A problem found in a source chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainViolation {
+    pub seq: u32,
+    pub action_hash: ActionHash,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The action's sequence number does not follow on from the previous action.
+    SeqGap { expected: u32 },
+    /// More than one action was found at the same sequence number.
+    Fork { other: ActionHash },
+    /// The `prev_action` does not point at the previous action in the chain.
+    PrevActionMismatch {
+        expected: Option<ActionHash>,
+        found: Option<ActionHash>,
+    },
+    /// The action was authored by a different agent to the rest of the chain.
+    AuthorMismatch {
+        expected: AgentPubKey,
+        found: AgentPubKey,
+    },
+    /// The signature does not verify against the author's key.
+    InvalidSignature,
+    /// The stored entry does not hash to the entry hash in the action.
+    EntryHashMismatch {
+        expected: EntryHash,
+        found: EntryHash,
+    },
+    /// The action at the start of the chain is not the one that genesis should have created.
+    UnexpectedGenesisAction { expected: &'static str },
+}
+
+impl Display for ViolationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViolationKind::SeqGap { expected } => {
+                write!(f, "Sequence gap, expected seq {expected}")
+            }
+            ViolationKind::Fork { other } => {
+                write!(f, "Fork, another action has the same seq: {other}")
+            }
+            ViolationKind::PrevActionMismatch { expected, found } => write!(
+                f,
+                "Previous action mismatch, expected {} but found {}",
+                display_optional(expected),
+                display_optional(found)
+            ),
+            ViolationKind::AuthorMismatch { expected, found } => {
+                write!(f, "Author mismatch, expected {expected} but found {found}")
+            }
+            ViolationKind::InvalidSignature => write!(f, "Invalid signature"),
+            ViolationKind::EntryHashMismatch { expected, found } => write!(
+                f,
+                "Entry hash mismatch, action has {expected} but the stored entry hashes to {found}"
+            ),
+            ViolationKind::UnexpectedGenesisAction { expected } => {
+                write!(f, "Unexpected genesis action, expected {expected}")
+            }
+        }
+    }
+}
+
+fn display_optional<T: Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "none".to_string())
+}
+
+/// Check a source chain, ordered by sequence number, for problems.
+///
+/// Checks:
+/// - Sequence numbers increase by one and each `prev_action` points at the previous action.
+/// - There are no forks, where more than one action has the same sequence number.
+/// - Every action is by the same author, and is signed by them.
+/// - Stored entries hash to the entry hash in their action.
+/// - The chain starts with `Dna`, `AgentValidationPkg` and `Create` of the agent key.
+pub fn verify_chain(chain: &[ChainRecord]) -> Vec<ChainViolation> {
+    let mut violations = Vec::new();
+
+    let author = chain.first().map(|r| r.action.action().author().clone());
+    let mut by_seq = HashMap::<u32, ActionHash>::new();
+    let mut previous: Option<&ChainRecord> = None;
+
+    for record in chain {
+        let action = record.action.action();
+        let seq = record.action.seq();
+        let hash = record.action.as_hash();
+
+        // The DHT has one record per distinct validation status of an action's ops, so the same
+        // action can appear more than once. It has already been checked.
+        if by_seq.get(&seq) == Some(hash) {
+            continue;
+        }
+
+        let mut violation = |kind| {
+            violations.push(ChainViolation {
+                seq,
+                action_hash: hash.clone(),
+                kind,
+            })
+        };
+
+        match by_seq.get(&seq) {
+            Some(other) => {
+                violation(ViolationKind::Fork {
+                    other: other.clone(),
+                });
+            }
+            None => {
+                by_seq.insert(seq, hash.clone());
+
+                // Only check linkage for the first action seen at each seq, a fork is reported
+                // for the others.
+                let expected_seq = previous.map(|p| p.action.seq() + 1).unwrap_or(0);
+                if seq != expected_seq {
+                    violation(ViolationKind::SeqGap {
+                        expected: expected_seq,
+                    });
+                } else {
+                    let expected = previous.map(|p| p.action.as_hash().clone());
+                    let found = action.prev_action().cloned();
+                    if expected != found {
+                        violation(ViolationKind::PrevActionMismatch { expected, found });
+                    }
+                }
+
+                previous = Some(record);
+            }
+        }
+
+        if let Some(author) = &author
+            && action.author() != author
+        {
+            violation(ViolationKind::AuthorMismatch {
+                expected: author.clone(),
+                found: action.author().clone(),
+            });
+        }
+
+        if !verify_signature(record) {
+            violation(ViolationKind::InvalidSignature);
+        }
+
+        if let (Some(entry), Some(expected)) = (&record.entry, action.entry_hash()) {
+            let found = EntryHash::with_data_sync(entry);
+            if &found != expected {
+                violation(ViolationKind::EntryHashMismatch {
+                    expected: expected.clone(),
+                    found,
+                });
+            }
+        }
+
+        let expected_genesis = match seq {
+            0 if !matches!(action, Action::Dna(_)) => Some("Dna"),
+            1 if !matches!(action, Action::AgentValidationPkg(_)) => Some("AgentValidationPkg"),
+            2 if !matches!(action, Action::Create(c) if c.entry_type == EntryType::AgentPubKey) => {
+                Some("Create of the agent key")
+            }
+            _ => None,
+        };
+        if let Some(expected) = expected_genesis {
+            violation(ViolationKind::UnexpectedGenesisAction { expected });
+        }
+    }
+
+    violations
+}
+
+fn verify_signature(record: &ChainRecord) -> bool {
+    let action = record.action.action();
+
+    let Ok(data) = holochain_serialized_bytes::encode(action) else {
+        return false;
+    };
+
+    let Ok(pub_key) = <[u8; 32]>::try_from(action.author().get_raw_32()) else {
+        return false;
+    };
+
+    sodoken::sign::verify_detached(&record.action.signature.0, &data, &pub_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieve::ValidationStatus;
+    use holochain_zome_types::prelude::*;
+
+    fn genesis(author: &AgentPubKey) -> Vec<ChainRecord> {
+        let dna = Action::Dna(Dna {
+            author: author.clone(),
+            timestamp: Timestamp::now(),
+            hash: DnaHash::from_raw_36(vec![1; 36]),
+        });
+        let dna = record(dna);
+
+        let avp = Action::AgentValidationPkg(AgentValidationPkg {
+            author: author.clone(),
+            timestamp: Timestamp::now(),
+            action_seq: 1,
+            prev_action: dna.action.as_hash().clone(),
+            membrane_proof: None,
+        });
+        let avp = record(avp);
+
+        let create = Action::Create(Create {
+            author: author.clone(),
+            timestamp: Timestamp::now(),
+            action_seq: 2,
+            prev_action: avp.action.as_hash().clone(),
+            entry_type: EntryType::AgentPubKey,
+            entry_hash: author.clone().into(),
+            weight: Default::default(),
+        });
+        let create = record(create);
+
+        vec![dna, avp, create]
+    }
+
+    fn record(action: Action) -> ChainRecord {
+        ChainRecord {
+            action: SignedActionHashed::from_content_sync(SignedAction::new(
+                action,
+                Signature([0; SIGNATURE_BYTES]),
+            )),
+            validation_status: ValidationStatus::Valid,
+            entry: None,
+        }
+    }
+
+    /// Sign a record's action with a real key. The action hash doesn't depend on the signature, so
+    /// the chain links are unchanged.
+    fn sign(
+        record: &ChainRecord,
+        sec_key: &mut sodoken::SizedLockedArray<{ sodoken::sign::SECRETKEYBYTES }>,
+    ) -> ChainRecord {
+        let action = record.action.action().clone();
+
+        let mut signature = [0; SIGNATURE_BYTES];
+        sodoken::sign::sign_detached(
+            &mut signature,
+            &holochain_serialized_bytes::encode(&action).unwrap(),
+            &sec_key.lock(),
+        )
+        .unwrap();
+
+        ChainRecord {
+            action: SignedActionHashed::from_content_sync(SignedAction::new(
+                action,
+                Signature(signature),
+            )),
+            ..record.clone()
+        }
+    }
+
+    /// The test chains are not signed, so ignore signature violations.
+    fn verify_unsigned(chain: &[ChainRecord]) -> Vec<ChainViolation> {
+        verify_chain(chain)
+            .into_iter()
+            .filter(|v| v.kind != ViolationKind::InvalidSignature)
+            .collect()
+    }
+
+    #[test]
+    fn well_formed_chain() {
+        let author = AgentPubKey::from_raw_36(vec![2; 36]);
+        let chain = genesis(&author);
+
+        assert_eq!(Vec::<ChainViolation>::new(), verify_unsigned(&chain));
+    }
+
+    #[test]
+    fn detect_fork() {
+        let author = AgentPubKey::from_raw_36(vec![2; 36]);
+        let mut chain = genesis(&author);
+
+        let fork = record(Action::AgentValidationPkg(AgentValidationPkg {
+            author: author.clone(),
+            timestamp: Timestamp(0),
+            action_seq: 1,
+            prev_action: chain[0].action.as_hash().clone(),
+            membrane_proof: None,
+        }));
+        let fork_hash = fork.action.as_hash().clone();
+        chain.insert(2, fork);
+
+        let violations = verify_unsigned(&chain);
+
+        assert_eq!(1, violations.len());
+        assert_eq!(fork_hash, violations[0].action_hash);
+        assert_eq!(
+            ViolationKind::Fork {
+                other: chain[1].action.as_hash().clone()
+            },
+            violations[0].kind
+        );
+    }
+
+    #[test]
+    fn same_action_twice_is_not_a_fork() {
+        let author = AgentPubKey::from_raw_36(vec![2; 36]);
+        let mut chain = genesis(&author);
+
+        let mut rejected = chain[1].clone();
+        rejected.validation_status = ValidationStatus::Rejected;
+        chain.insert(2, rejected);
+
+        assert_eq!(Vec::<ChainViolation>::new(), verify_unsigned(&chain));
+    }
+
+    #[test]
+    fn detect_broken_prev_action() {
+        let author = AgentPubKey::from_raw_36(vec![2; 36]);
+        let mut chain = genesis(&author);
+
+        let broken = record(Action::Create(Create {
+            author: author.clone(),
+            timestamp: Timestamp::now(),
+            action_seq: 3,
+            prev_action: chain[0].action.as_hash().clone(),
+            entry_type: EntryType::AgentPubKey,
+            entry_hash: author.clone().into(),
+            weight: Default::default(),
+        }));
+        chain.push(broken);
+
+        let violations = verify_unsigned(&chain);
+
+        assert_eq!(1, violations.len());
+        assert_eq!(3, violations[0].seq);
+        assert!(matches!(
+            violations[0].kind,
+            ViolationKind::PrevActionMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn detect_seq_gap() {
+        let author = AgentPubKey::from_raw_36(vec![2; 36]);
+        let mut chain = genesis(&author);
+        chain.remove(1);
+
+        let violations = verify_unsigned(&chain);
+
+        assert_eq!(
+            vec![ViolationKind::SeqGap { expected: 1 }],
+            violations.into_iter().map(|v| v.kind).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn signatures_are_checked() {
+        let mut pub_key = [0; sodoken::sign::PUBLICKEYBYTES];
+        let mut sec_key =
+            sodoken::SizedLockedArray::<{ sodoken::sign::SECRETKEYBYTES }>::new().unwrap();
+        sodoken::sign::keypair(&mut pub_key, &mut *sec_key.lock()).unwrap();
+        let author = AgentPubKey::from_raw_32(pub_key.to_vec());
+
+        let chain = genesis(&author)
+            .iter()
+            .map(|r| sign(r, &mut sec_key))
+            .collect::<Vec<_>>();
+
+        assert_eq!(Vec::<ChainViolation>::new(), verify_chain(&chain));
+
+        // Change the action after it was signed, keeping the signature.
+        let mut tampered = chain.clone();
+        let mut action = tampered[2].action.action().clone();
+        let Action::Create(create) = &mut action else {
+            panic!("Expected the agent key create");
+        };
+        create.timestamp = Timestamp(0);
+        tampered[2].action = SignedActionHashed::from_content_sync(SignedAction::new(
+            action,
+            tampered[2].action.signature.clone(),
+        ));
+
+        let violations = verify_chain(&tampered);
+
+        assert_eq!(
+            vec![(2, ViolationKind::InvalidSignature)],
+            violations
+                .into_iter()
+                .map(|v| (v.seq, v.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+}